@@ -0,0 +1,98 @@
+#[cfg(feature = "sound")]
+use std::{f32::consts::TAU, num::NonZero};
+
+#[cfg(feature = "sound")]
+use rodio::{buffer::SamplesBuffer, ChannelCount, DeviceSinkBuilder, MixerDeviceSink, SampleRate};
+
+///Samples per second of the sounds played.
+#[cfg(feature = "sound")]
+const SAMPLE_RATE: SampleRate = NonZero::new(22050).unwrap();
+#[cfg(feature = "sound")]
+const CHANNELS: ChannelCount = NonZero::new(1).unwrap();
+///Seconds each note fades in and out over, so that notes don't click.
+#[cfg(feature = "sound")]
+const FADE: f32 = 0.005;
+
+///Something which happened in the game that can be heard, when built with the `sound` feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sound {
+    ///A plane entered the map.
+    Spawn,
+    ///A plane landed or left by its exit.
+    Landing,
+    ///Two planes came too close.
+    Conflict,
+    ///Two planes collided.
+    Crash,
+} impl Sound {
+    ///The notes the sound is made of, as their pitch in hertz, or 0 for a rest, and their length in
+    ///seconds.
+    #[cfg(feature = "sound")]
+    fn notes(self) -> &'static [(f32, f32)] {
+        match self {
+            Sound::Spawn => &[(660.0, 0.08), (880.0, 0.1)],
+            Sound::Landing => &[(880.0, 0.07), (698.5, 0.07), (523.3, 0.14)],
+            Sound::Conflict => &[(1000.0, 0.1), (0.0, 0.06), (1000.0, 0.1)],
+            Sound::Crash => &[(220.0, 0.15), (164.8, 0.15), (110.0, 0.4)],
+        }
+    }
+    ///The sound's samples, from -1 to 1, at a volume from 0 to 1.
+    #[cfg(feature = "sound")]
+    fn samples(self, volume: f32) -> Vec<f32> {
+        let rate = SAMPLE_RATE.get() as f32;
+        let mut samples = vec![];
+        for &(pitch, length) in self.notes() {
+            let count = (length * rate) as usize;
+            for i in 0..count {
+                let time = i as f32 / rate;
+                let envelope = (time / FADE).min((length - time) / FADE).min(1.0);
+                samples.push(if pitch == 0.0 { 0.0 } else { (TAU * pitch * time).sin() * envelope * volume * 0.5 });
+            }
+        }
+        samples
+    }
+}
+
+///Plays sounds through the default audio output. Sounds play in the background, so the game
+///carries on while they do.
+#[cfg(feature = "sound")]
+pub struct Player {
+    ///From 0 to 1.
+    pub volume: f32,
+    pub muted: bool,
+    output: Option<MixerDeviceSink>,
+} #[cfg(feature = "sound")] impl Player {
+    pub fn new(volume: f32, muted: bool) -> Self {
+        let output = DeviceSinkBuilder::open_default_sink().ok().map(|mut output| {
+            //It would be printed over the game as it quits.
+            output.log_on_drop(false);
+            output
+        });
+        Player { volume, muted, output }
+    }
+    ///Whether there is anything to play sounds with.
+    pub fn is_available(&self) -> bool {
+        self.output.is_some()
+    }
+    pub fn play(&self, sound: Sound) {
+        let Some(output) = &self.output else { return };
+        if self.muted || self.volume <= 0.0 {
+            return;
+        }
+        output.mixer().add(SamplesBuffer::new(CHANNELS, SAMPLE_RATE, sound.samples(self.volume)));
+    }
+}
+
+#[cfg(all(test, feature = "sound"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_hold_every_note() {
+        let samples = Sound::Conflict.samples(1.0);
+        let seconds = Sound::Conflict.notes().iter().map(|(_, length)| length).sum::<f32>();
+        assert!((samples.len() as f32 / SAMPLE_RATE.get() as f32 - seconds).abs() < 0.01);
+        assert!(samples.iter().all(|sample| sample.abs() <= 0.5));
+        assert!(Sound::Conflict.samples(0.5).iter().zip(&samples).any(|(quiet, loud)| quiet != loud));
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fs, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{map::{Map, Progress, Situation}, paths, versioned, GameSettings};
+
+///Autosaves kept, one for each game. The oldest are removed as new games are saved.
+const RETENTION: usize = 5;
+
+///A game in progress, saved every few ticks so that it can be resumed with `--resume` after the
+///terminal is closed or the game crashes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Autosave {
+    ///The name the map was chosen by, so that the resumed game can be restarted on it.
+    pub map_name: String,
+    pub seed: u64,
+    ///The settings the game was being played with, which it is resumed with.
+    pub settings: GameSettings,
+    pub situation: Situation,
+    pub progress: Progress,
+} impl Autosave {
+    pub fn of(map: &Map, map_name: &str) -> Self {
+        Autosave {
+            map_name: map_name.to_string(),
+            seed: map.seed,
+            settings: map.settings,
+            situation: map.situation(),
+            progress: map.progress(),
+        }
+    }
+}
+
+///Where autosaves are kept.
+pub fn dir() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("autosaves"))
+}
+
+///A file for a new game's autosaves, named for when it started.
+pub fn new_file() -> Option<PathBuf> {
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    dir().map(|dir| dir.join(format!("{started}.json")))
+}
+
+///Writes the autosave over the game's last one, then removes the oldest games' autosaves. It is
+///written beside the file first, so that being closed partway through never leaves a broken one.
+pub fn save(file: &Path, autosave: &Autosave) -> Result<()> {
+    let Some(dir) = file.parent() else { anyhow::bail!("{} is not in a directory.", file.display()) };
+    fs::create_dir_all(dir)?;
+    let partial = file.with_extension("json.partial");
+    fs::write(&partial, versioned::to_json(autosave)?)?;
+    fs::rename(&partial, file)?;
+    for old in newest_first(dir)?.into_iter().skip(RETENTION) {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+///Reads the autosave `which` names: `last` for the latest game saved, or else a file. Returns the
+///file it was read from along with it.
+pub fn load(which: &str) -> Result<(PathBuf, Autosave)> {
+    let file = if which == "last" {
+        let Some(dir) = dir() else { anyhow::bail!("There is no data directory to find autosaves in.") };
+        let Some(file) = newest_first(&dir).unwrap_or_default().into_iter().next() else { anyhow::bail!("There are no autosaves to resume.") };
+        file
+    } else {
+        PathBuf::from(which)
+    };
+    let autosave: Autosave = versioned::from_json(&fs::read(&file)?)?;
+    let problems = autosave.situation.map.problems();
+    if !problems.is_empty() {
+        anyhow::bail!("{} is not a valid autosave:\n{}", file.display(), problems.join("\n"));
+    }
+    Ok((file, autosave))
+}
+
+///Removes a game's autosave once it can no longer be resumed.
+pub fn remove(file: &Path) {
+    let _ = fs::remove_file(file);
+}
+
+///The autosaves in `dir`, latest first.
+fn newest_first(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| b.cmp(a));
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_latest_games() {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join(format!("atc-autosaves-{}", std::process::id()));
+        let map = crate::map::tests::crossing_map();
+        let autosave = Autosave {
+            map_name: format!("crossing"),
+            seed: 1,
+            settings: crate::Args::parse_from(["atc", "--casual", "-t", "2"]).into(),
+            situation: Situation { map, tick: 30, planes: vec![], announced: vec![] },
+            progress: Progress { planes_landed: 4, planes_announced: 6, score_bonus: 1, score_penalty: 0, player_scores: Default::default(), next_spawn: 33 },
+        };
+        for game in 0..RETENTION + 2 {
+            save(&dir.join(format!("{game}.json")), &autosave).expect("autosave to be written");
+            //Modification times must differ for the newest to be told apart.
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let files = newest_first(&dir).expect("autosaves to be listed");
+        assert_eq!(files.len(), RETENTION);
+        assert_eq!(files[0], dir.join(format!("{}.json", RETENTION + 1)));
+        let loaded: Autosave = versioned::from_json(&fs::read(&files[0]).expect("autosave to be read")).expect("autosave to load");
+        assert_eq!((loaded.situation.tick, loaded.progress.planes_landed, loaded.progress.next_spawn), (30, 4, 33));
+        assert_eq!((loaded.settings.casual, loaded.settings.tick_rate), (true, std::time::Duration::from_secs(2)));
+        fs::remove_dir_all(dir).expect("autosaves to be removed");
+    }
+}
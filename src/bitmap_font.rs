@@ -0,0 +1,179 @@
+///Size in pixels of a character cell. Glyphs are 5 pixels wide and 7 tall, in the top left of the
+///cell, leaving space between characters and lines.
+pub const CELL_SIZE: (usize, usize) = (6, 9);
+///Row of the cell underlines are drawn on.
+pub const UNDERLINE_ROW: usize = 7;
+///Where lines drawn through a cell cross, at the middle of the glyphs.
+const CENTER: (usize, usize) = (2, 3);
+
+///Glyphs for the printable ASCII characters, from space to `~`. Each is 7 rows, top to bottom, with
+///the leftmost pixel of a row in the fifth bit.
+const ASCII: [[u8; 7]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], //' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], //'!'
+    [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], //'"'
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], //'#'
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], //'$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], //'%'
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], //'&'
+    [0x04, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00], //'''
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], //'('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], //')'
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], //'*'
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], //'+'
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], //','
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], //'-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], //'.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], //'/'
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], //'0'
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], //'1'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], //'2'
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], //'3'
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], //'4'
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], //'5'
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], //'6'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], //'7'
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], //'8'
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], //'9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], //':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], //';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], //'<'
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], //'='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], //'>'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], //'?'
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], //'@'
+    [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], //'A'
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], //'B'
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], //'C'
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], //'D'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], //'E'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], //'F'
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], //'G'
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], //'H'
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], //'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], //'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], //'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], //'L'
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], //'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], //'N'
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], //'O'
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], //'P'
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], //'Q'
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], //'R'
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], //'S'
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], //'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], //'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], //'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], //'W'
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], //'X'
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], //'Y'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], //'Z'
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], //'['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], //'\'
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], //']'
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], //'^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], //'_'
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], //'`'
+    [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f], //'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e], //'b'
+    [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e], //'c'
+    [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f], //'d'
+    [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e], //'e'
+    [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08], //'f'
+    [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e], //'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], //'h'
+    [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e], //'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c], //'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], //'k'
+    [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], //'l'
+    [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11], //'m'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], //'n'
+    [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e], //'o'
+    [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10], //'p'
+    [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01], //'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], //'r'
+    [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e], //'s'
+    [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06], //'t'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d], //'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04], //'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a], //'w'
+    [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11], //'x'
+    [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e], //'y'
+    [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f], //'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], //'{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], //'|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], //'}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], //'~'
+];
+
+///Glyphs for the other characters the Unicode glyph set draws with, apart from lines.
+const SYMBOLS: [(char, [u8; 7]); 15] = [
+    ('·', [0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00]),
+    ('✱', [0x00, 0x15, 0x0e, 0x1f, 0x0e, 0x15, 0x00]),
+    ('✖', [0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x00]),
+    ('•', [0x00, 0x00, 0x0e, 0x0e, 0x0e, 0x00, 0x00]),
+    ('▪', [0x00, 0x00, 0x0e, 0x0e, 0x0e, 0x00, 0x00]),
+    ('↑', [0x04, 0x0e, 0x15, 0x04, 0x04, 0x04, 0x04]),
+    ('↓', [0x04, 0x04, 0x04, 0x04, 0x15, 0x0e, 0x04]),
+    ('→', [0x00, 0x04, 0x02, 0x1f, 0x02, 0x04, 0x00]),
+    ('←', [0x00, 0x04, 0x08, 0x1f, 0x08, 0x04, 0x00]),
+    ('↗', [0x00, 0x07, 0x03, 0x05, 0x08, 0x10, 0x00]),
+    ('↘', [0x00, 0x10, 0x08, 0x05, 0x03, 0x07, 0x00]),
+    ('↙', [0x00, 0x01, 0x02, 0x14, 0x18, 0x1c, 0x00]),
+    ('↖', [0x00, 0x1c, 0x18, 0x14, 0x02, 0x01, 0x00]),
+    ('↻', [0x00, 0x0d, 0x13, 0x13, 0x10, 0x11, 0x0e]),
+    ('↺', [0x00, 0x16, 0x19, 0x19, 0x01, 0x11, 0x0e]),
+];
+
+///Which of the up, right, down and left edges of a cell a box-drawing character joins, for those
+///which join their neighbors.
+fn box_edges(glyph: char) -> Option<[bool; 4]> {
+    Some(match glyph {
+        '─' => [false, true, false, true],
+        '│' => [true, false, true, false],
+        '┌' => [false, true, true, false],
+        '┐' => [false, false, true, true],
+        '└' => [true, true, false, false],
+        '┘' => [true, false, false, true],
+        '┼' => [true, true, true, true],
+        _ => return None,
+    })
+}
+
+///Calls `set` with the column and row of each pixel of a cell which is drawn for a glyph. Glyphs
+///which the font does not have are drawn as `?`.
+pub fn draw(glyph: char, mut set: impl FnMut(usize, usize)) {
+    let (width, height) = CELL_SIZE;
+    if let Some([up, right, down, left]) = box_edges(glyph) {
+        let (x, y) = CENTER;
+        for (joined, columns, rows) in [(up, x..x + 1, 0..y + 1), (right, x..width, y..y + 1), (down, x..x + 1, y..height), (left, 0..x + 1, y..y + 1)] {
+            if joined {
+                for row in rows {
+                    for column in columns.clone() {
+                        set(column, row);
+                    }
+                }
+            }
+        }
+        return;
+    }
+    //Diagonals run from corner to corner, so that leader lines meet across cells.
+    if glyph == '╱' || glyph == '╲' {
+        for row in 0..height {
+            let column = row * (width - 1) / (height - 1);
+            set(if glyph == '╲' { column } else { width - 1 - column }, row);
+        }
+        return;
+    }
+    let rows = match glyph {
+        ' '..='~' => ASCII[glyph as usize - ' ' as usize],
+        _ => SYMBOLS.iter().find(|(symbol, _)| *symbol == glyph).map(|(_, rows)| *rows).unwrap_or(ASCII['?' as usize - ' ' as usize]),
+    };
+    for (row, bits) in rows.into_iter().enumerate() {
+        for column in 0..5 {
+            if bits & (0x10 >> column) != 0 {
+                set(column, row);
+            }
+        }
+    }
+}
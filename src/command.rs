@@ -1,6 +1,8 @@
 use std::fmt::Display;
 
-use crate::{direction::{CircleDirection, OrdinalDirection}, map::MapStatic, map_objects::{GridRenderable, ListItemPartRenderable}, plane::{Plane, Visibility}};
+use serde::{Deserialize, Serialize};
+
+use crate::{direction::{CircleDirection, OrdinalDirection}, i18n::{tr, tr_spans}, location::GroundLocation, map::MapStatic, map_objects::{GridRenderable, ListItemPartRenderable, FINAL_APPROACH_LENGTH}, plane::{Plane, Visibility}, styled::{self, Span, Style}, units::altitude_unit};
 
 enum InputHandling {
     Handled,
@@ -11,54 +13,187 @@ enum InputHandling {
 trait CommandFragment<T>: Clone {
     ///Mutates the fragment based on an input.
     fn input(&mut self, letter: char) -> InputHandling;
-    fn as_text(&self) -> String;
+    ///The fragment as it is previewed while it is typed.
+    fn spans(&self) -> Vec<Span>;
     fn to_complete(&self) -> Option<T>;
 }
 
+///Why a command could not be carried out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandRejection {
+    PlaneNotFound(char),
+    PlaneOnGround(char),
+    EmptySlot(SlotKey),
+    WrongArgumentCount(SlotKey),
+    ///The slot refers to slots which refer to others, too deep to be a command, or in a loop.
+    SlotTooDeep(SlotKey),
+    UnfilledPlaceholder,
+    NoSuchAirway(u16),
+    NoSuchProcedure(u16),
+    ///The plane is not departing from or arriving at the procedure's airport.
+    WrongProcedure(char, u16),
+    PendingPlaneNotFound(char),
+    PlaneAirborne(char),
+    ///The plane is not within `FINAL_APPROACH_LENGTH` cells before its destination's runway.
+    NotOnFinal(char),
+    NoSuchDestination(CompleteDivert),
+} impl Display for CommandRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            CommandRejection::PlaneNotFound(p) => tr!(PlaneNotFound, plane = p),
+            CommandRejection::PlaneOnGround(p) => tr!(PlaneOnGround, plane = p),
+            CommandRejection::EmptySlot(n) => tr!(EmptySlot, slot = n),
+            CommandRejection::WrongArgumentCount(n) => tr!(WrongArgumentCount, slot = n),
+            CommandRejection::SlotTooDeep(n) => tr!(SlotTooDeep, slot = n),
+            CommandRejection::UnfilledPlaceholder => tr!(UnfilledPlaceholder),
+            CommandRejection::NoSuchAirway(n) => tr!(NoSuchAirway, airway = n),
+            CommandRejection::NoSuchProcedure(n) => tr!(NoSuchProcedure, procedure = n),
+            CommandRejection::WrongProcedure(p, n) => tr!(WrongProcedure, plane = p, procedure = n),
+            CommandRejection::PendingPlaneNotFound(p) => tr!(PendingPlaneNotFound, plane = p),
+            CommandRejection::PlaneAirborne(p) => tr!(PlaneAirborne, plane = p),
+            CommandRejection::NotOnFinal(p) => tr!(NotOnFinal, plane = p, cells = FINAL_APPROACH_LENGTH),
+            CommandRejection::NoSuchDestination(d) => tr!(NoSuchDestination, destination = d),
+        };
+        write!(f, "{message}")
+    }
+}
+
 fn digit_as_num(digit: char) -> u16 {
-    if !('0'..='9').contains(&digit) {
+    if !digit.is_ascii_digit() {
         panic!("Digit out of range: {digit}");
     }
     (digit as u16) - '0' as u16
 }
 
+///`value` with a digit written after it, or None if that is too large to be a number.
+fn append_digit(value: u16, digit: char) -> Option<u16> {
+    value.checked_mul(10)?.checked_add(digit_as_num(digit))
+}
+
+///A number given to a command. Commands stored in slots may leave it as a placeholder (`$`), which
+///is filled in by the arguments given when the slot is referenced. Saved as the number, or `"$"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Argument {
+    Value(u16),
+    Placeholder,
+} impl Argument {
+    fn from_input(letter: char) -> Option<Argument> {
+        match letter {
+            '0'..='9' => Some(Argument::Value(digit_as_num(letter))),
+            '$' => Some(Argument::Placeholder),
+            _ => None,
+        }
+    }
+    pub fn value(self) -> u16 {
+        match self {
+            Argument::Value(v) => v,
+            Argument::Placeholder => unreachable!("map should have bound placeholders to arguments"),
+        }
+    }
+    ///Replaces a placeholder with the next of `arguments`. Returns false if they have run out.
+    fn bind(&mut self, arguments: &mut impl Iterator<Item = Argument>) -> bool {
+        if *self == Argument::Placeholder {
+            match arguments.next() {
+                Some(a) => *self = a,
+                None => return false,
+            }
+        }
+        true
+    }
+} impl Display for Argument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Argument::Value(v) => write!(f, "{v}"),
+            Argument::Placeholder => write!(f, "$"),
+        }
+    }
+} impl Serialize for Argument {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Argument::Value(v) => serializer.serialize_u16(*v),
+            //TOML has no null, so placeholders are written the way they are entered.
+            Argument::Placeholder => serializer.serialize_str("$"),
+        }
+    }
+} impl<'de> Deserialize<'de> for Argument {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum SavedArgument {
+            Value(u16),
+            Text(String),
+            ///Placeholders used to be saved as null.
+            Null,
+        }
+        match SavedArgument::deserialize(deserializer)? {
+            SavedArgument::Value(v) => Ok(Argument::Value(v)),
+            SavedArgument::Text(text) if text == "$" => Ok(Argument::Placeholder),
+            SavedArgument::Text(text) => Err(serde::de::Error::custom(format!("expected a number or \"$\", found \"{text}\""))),
+            SavedArgument::Null => Ok(Argument::Placeholder),
+        }
+    }
+}
+
+fn altitude_text(altitude: Argument) -> String {
+    match altitude {
+        Argument::Value(v) => altitude_unit().long(v),
+        Argument::Placeholder => format!("$"),
+    }
+}
+
+///An altitude as it is shown in the plane list.
+fn short_altitude_text(altitude: Argument) -> String {
+    match altitude {
+        Argument::Value(v) => altitude_unit().short(v),
+        Argument::Placeholder => format!("$"),
+    }
+}
+
 //Could derive Copy, but implicit copy leads to bugginess with *self.
 #[derive(Debug, Clone, Default)]
 pub enum Altitude {
     #[default]
     Undefined,
-    Plus(Option<u16>),
-    Minus(Option<u16>),
-    To(u16),
+    Plus(Option<Argument>),
+    Minus(Option<Argument>),
+    To(Argument),
 }
 impl CommandFragment<CompleteAltitude> for Altitude {
     fn input(&mut self, letter: char) -> InputHandling {
         match (&self, letter) {
             (Altitude::Undefined, '\x7f') => { return InputHandling::Back },
+            (Altitude::To(Argument::Value(v)), '\x7f') if *v >= 10 => *self = Altitude::To(Argument::Value(v / 10)),
+            (Altitude::Plus(Some(Argument::Value(v))), '\x7f') if *v >= 10 => *self = Altitude::Plus(Some(Argument::Value(v / 10))),
+            (Altitude::Minus(Some(Argument::Value(v))), '\x7f') if *v >= 10 => *self = Altitude::Minus(Some(Argument::Value(v / 10))),
             (Altitude::To(_) | Altitude::Plus(None) | Altitude::Minus(None), '\x7f') => *self = Altitude::Undefined,
             (Altitude::Plus(Some(_)), '\x7f') => *self = Altitude::Plus(None),
             (Altitude::Minus(Some(_)), '\x7f') => *self = Altitude::Minus(None),
 
-            (Altitude::Undefined, '0'..='9') => *self = Altitude::To(digit_as_num(letter)),
+            (Altitude::Undefined, '0'..='9' | '$') => *self = Altitude::To(Argument::from_input(letter).unwrap()),
             (Altitude::Undefined, 'c' | '+' | '=') => *self = Altitude::Plus(None),
             (Altitude::Undefined, 'd' | '-' | '_') => *self = Altitude::Minus(None),
 
-            (Altitude::Plus(None), '0'..='9') => *self = Altitude::Plus(Some(digit_as_num(letter))),
-            (Altitude::Minus(None), '0'..='9') => *self = Altitude::Minus(Some(digit_as_num(letter))),
+            (Altitude::Plus(None), '0'..='9' | '$') => *self = Altitude::Plus(Argument::from_input(letter)),
+            (Altitude::Minus(None), '0'..='9' | '$') => *self = Altitude::Minus(Argument::from_input(letter)),
+
+            //A second digit makes a two-digit flight level.
+            (Altitude::To(Argument::Value(v)), '0'..='9') if (1..10).contains(v) => *self = Altitude::To(Argument::Value(v * 10 + digit_as_num(letter))),
+            (Altitude::Plus(Some(Argument::Value(v))), '0'..='9') if (1..10).contains(v) => *self = Altitude::Plus(Some(Argument::Value(v * 10 + digit_as_num(letter)))),
+            (Altitude::Minus(Some(Argument::Value(v))), '0'..='9') if (1..10).contains(v) => *self = Altitude::Minus(Some(Argument::Value(v * 10 + digit_as_num(letter)))),
             _ => return InputHandling::Unhandled,
         }
 
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
-        match self {
-            Altitude::Undefined => format!("altitude:"),
-            Altitude::To(val) => format!("altitude: {val}000ft"),
-            Altitude::Plus(None) => format!("altitude: climb"),
-            Altitude::Minus(None) => format!("altitude: descend"),
-            Altitude::Plus(Some(val)) => format!("altitude: climb {val}000ft"),
-            Altitude::Minus(Some(val)) => format!("altitude: descend {val}000ft"),
-        }
+    fn spans(&self) -> Vec<Span> {
+        vec![Span::plain(match self {
+            Altitude::Undefined => tr!(AltitudePrompt),
+            Altitude::To(val) => tr!(AltitudeTo, altitude = altitude_text(*val)),
+            Altitude::Plus(None) => tr!(AltitudeClimb),
+            Altitude::Minus(None) => tr!(AltitudeDescend),
+            Altitude::Plus(Some(val)) => tr!(AltitudeClimbTo, altitude = altitude_text(*val)),
+            Altitude::Minus(Some(val)) => tr!(AltitudeDescendTo, altitude = altitude_text(*val)),
+        })]
     }
     fn to_complete(&self) -> Option<CompleteAltitude> {
         match self {
@@ -70,17 +205,25 @@ impl CommandFragment<CompleteAltitude> for Altitude {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum CompleteAltitude {
-    Plus(u16),
-    Minus(u16),
-    To(u16),
+    Plus(Argument),
+    Minus(Argument),
+    To(Argument),
 } impl ListItemPartRenderable for CompleteAltitude {
-    fn render(&self, _colorize: bool) -> String {
+    fn spans(&self, _colorize: bool) -> Vec<Span> {
+        vec![Span::plain(match self {
+            CompleteAltitude::To(v) => format!("fl={}", short_altitude_text(*v)),
+            CompleteAltitude::Plus(v) => format!("fl+{}", short_altitude_text(*v)),
+            CompleteAltitude::Minus(v) => format!("fl-{}", short_altitude_text(*v)),
+        })]
+    }
+} impl Into<Altitude> for CompleteAltitude {
+    fn into(self) -> Altitude {
         match self {
-            CompleteAltitude::To(v) => format!("fl={v}"),
-            CompleteAltitude::Plus(v) => format!("fl+{v}"),
-            CompleteAltitude::Minus(v) => format!("fl-{v}"),
+            CompleteAltitude::Plus(v)  => Altitude::Plus(Some(v)),
+            CompleteAltitude::Minus(v) => Altitude::Minus(Some(v)),
+            CompleteAltitude::To(v)    => Altitude::To(v),
         }
     }
 }
@@ -108,11 +251,11 @@ pub enum Turn {
         
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
-        match self {
-            Turn::None => format!("turn"),
-            Turn::ToHeading(h) => format!("turn to {}", h.to_deg()),
-        }
+    fn spans(&self) -> Vec<Span> {
+        vec![Span::plain(match self {
+            Turn::None => tr!(TurnPrompt),
+            Turn::ToHeading(h) => tr!(TurnTo, heading = h.to_deg()),
+        })]
     }
     fn to_complete(&self) -> Option<CompleteTurn> {
         match self {
@@ -122,13 +265,19 @@ pub enum Turn {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum CompleteTurn {
     ToHeading(OrdinalDirection),
 } impl ListItemPartRenderable for CompleteTurn {
-    fn render(&self, _colorize: bool) -> String {
+    fn spans(&self, _colorize: bool) -> Vec<Span> {
         match self {
-            CompleteTurn::ToHeading(dir) => format!("{}", dir.to_deg()),
+            CompleteTurn::ToHeading(dir) => vec![Span::plain(dir.to_deg().to_string())],
+        }
+    }
+} impl Into<Turn> for CompleteTurn {
+    fn into(self) -> Turn {
+        match self {
+            CompleteTurn::ToHeading(dir) => Turn::ToHeading(dir),
         }
     }
 }
@@ -151,12 +300,12 @@ pub enum Circle {
 
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
-        String::from(match self {
-            Circle::None => "circle",
-            Circle::Clockwise => "circle clockwise",
-            Circle::CounterClockwise => "circle counter-clockwise",
-        })
+    fn spans(&self) -> Vec<Span> {
+        vec![Span::plain(match self {
+            Circle::None => tr!(CirclePrompt),
+            Circle::Clockwise => tr!(CircleClockwise),
+            Circle::CounterClockwise => tr!(CircleCounterClockwise),
+        })]
     }
     fn to_complete(&self) -> Option<CompleteCircle> {
         match self {
@@ -167,16 +316,16 @@ pub enum Circle {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum CompleteCircle {
     Clockwise,
     CounterClockwise,
 } impl ListItemPartRenderable for CompleteCircle {
-    fn render(&self, _colorize: bool) -> String {
-        match self {
-            CompleteCircle::Clockwise => format!("circle CW"),
-            CompleteCircle::CounterClockwise => format!("circle CCW"),
-        }
+    fn spans(&self, _colorize: bool) -> Vec<Span> {
+        vec![Span::plain(match self {
+            CompleteCircle::Clockwise => "circle CW",
+            CompleteCircle::CounterClockwise => "circle CCW",
+        })]
     }
 } impl Into<CircleDirection> for CompleteCircle {
     fn into(self) -> CircleDirection {
@@ -185,10 +334,17 @@ pub enum CompleteCircle {
             CompleteCircle::CounterClockwise => CircleDirection::CounterClockwise,
         }
     }
+} impl Into<Circle> for CompleteCircle {
+    fn into(self) -> Circle {
+        match self {
+            CompleteCircle::Clockwise        => Circle::Clockwise,
+            CompleteCircle::CounterClockwise => Circle::CounterClockwise,
+        }
+    }
 }
 
 //This enum is always complete.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum SetVisibility {
     Mark,
     Unmark,
@@ -198,12 +354,12 @@ pub enum SetVisibility {
         if letter == '\x7f' { return InputHandling::Back }
         InputHandling::Unhandled
     }
-    fn as_text(&self) -> String {
-        String::from(match self {
-            SetVisibility::Mark   => "mark",
-            SetVisibility::Unmark => "unmark",
-            SetVisibility::Ignore => "ignore",
-        })
+    fn spans(&self) -> Vec<Span> {
+        vec![Span::plain(match self {
+            SetVisibility::Mark   => tr!(Mark),
+            SetVisibility::Unmark => tr!(Unmark),
+            SetVisibility::Ignore => tr!(Ignore),
+        })]
     }
     fn to_complete(&self) -> Option<SetVisibility> {
         Some(*self)
@@ -217,54 +373,111 @@ pub enum SetVisibility {
         }
     }
 } impl ListItemPartRenderable for SetVisibility {
-    fn render(&self, _colorize: bool) -> String {
-        match self {
-            SetVisibility::Mark   => format!("mark"),
-            SetVisibility::Unmark => format!("unmark"),
-            SetVisibility::Ignore => format!("ignore"),
-        }
+    fn spans(&self, _colorize: bool) -> Vec<Span> {
+        vec![Span::plain(match self {
+            SetVisibility::Mark   => "mark",
+            SetVisibility::Unmark => "unmark",
+            SetVisibility::Ignore => "ignore",
+        })]
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum PointOfInterest {
     Default(u16),
-    Beacon(Option<u16>),
+    Beacon(Option<Argument>),
+    Altitude(Option<Argument>),
+    Exit(Option<Argument>),
+    ///Entered as `x,y`. The second value is `None` until the comma is entered.
+    Grid(Option<u16>, Option<Option<u16>>),
 } impl CommandFragment<CompletePointOfInterest> for PointOfInterest {
     fn input(&mut self, letter: char) -> InputHandling {
         match (&self, letter) {
             (PointOfInterest::Beacon(None), '\x7f') => return InputHandling::Back,
             (PointOfInterest::Default(_), '\x7f') => return InputHandling::Back,
-            (PointOfInterest::Beacon(None), '0'..='9') => *self = PointOfInterest::Beacon(Some(digit_as_num(letter))),
+            (PointOfInterest::Beacon(None), '0'..='9' | '$') => *self = PointOfInterest::Beacon(Argument::from_input(letter)),
             (PointOfInterest::Beacon(Some(_)), '\x7f') => *self = PointOfInterest::Beacon(None),
+            (PointOfInterest::Altitude(None), '\x7f') => return InputHandling::Back,
+            (PointOfInterest::Altitude(None), '0'..='9' | '$') => *self = PointOfInterest::Altitude(Argument::from_input(letter)),
+            (PointOfInterest::Altitude(Some(Argument::Value(v))), '0'..='9') if (1..10).contains(v) => *self = PointOfInterest::Altitude(Some(Argument::Value(v * 10 + digit_as_num(letter)))),
+            (PointOfInterest::Altitude(Some(Argument::Value(v))), '\x7f') if *v >= 10 => *self = PointOfInterest::Altitude(Some(Argument::Value(v / 10))),
+            (PointOfInterest::Altitude(Some(_)), '\x7f') => *self = PointOfInterest::Altitude(None),
+            (PointOfInterest::Exit(None), '\x7f') => return InputHandling::Back,
+            (PointOfInterest::Exit(None), '0'..='9' | '$') => *self = PointOfInterest::Exit(Argument::from_input(letter)),
+            (PointOfInterest::Exit(Some(_)), '\x7f') => *self = PointOfInterest::Exit(None),
+
+            (PointOfInterest::Grid(None, None), '\x7f') => return InputHandling::Back,
+            (PointOfInterest::Grid(x, None), '0'..='9') => {
+                let Some(x) = append_digit(x.unwrap_or(0), letter) else { return InputHandling::Unhandled };
+                *self = PointOfInterest::Grid(Some(x), None);
+            },
+            (PointOfInterest::Grid(Some(x), None), '\x7f') => *self = PointOfInterest::Grid(if *x >= 10 { Some(x / 10) } else { None }, None),
+            (PointOfInterest::Grid(Some(x), None), ',') => *self = PointOfInterest::Grid(Some(*x), Some(None)),
+            (PointOfInterest::Grid(x, Some(y)), '0'..='9') => {
+                let Some(y) = append_digit(y.unwrap_or(0), letter) else { return InputHandling::Unhandled };
+                *self = PointOfInterest::Grid(*x, Some(Some(y)));
+            },
+            (PointOfInterest::Grid(x, Some(None)), '\x7f') => *self = PointOfInterest::Grid(*x, None),
+            (PointOfInterest::Grid(x, Some(Some(y))), '\x7f') => *self = PointOfInterest::Grid(*x, Some(if *y >= 10 { Some(y / 10) } else { None })),
             _ => return InputHandling::Unhandled,
         }
 
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
-        match self {
-            PointOfInterest::Beacon(None) => format!("\x1b[33m*\x1b[39m"),
-            PointOfInterest::Beacon(Some(n)) => format!("\x1b[33m*{n}\x1b[39m"),
-            PointOfInterest::Default(n) => format!("\x1b[33m*{n}\x1b[39m"),
-        }
+    fn spans(&self) -> Vec<Span> {
+        vec![match self {
+            PointOfInterest::Beacon(None) => Span::new("*", Style::colored(3)),
+            PointOfInterest::Beacon(Some(n)) => Span::new(format!("*{n}"), Style::colored(3)),
+            PointOfInterest::Default(n) => Span::new(format!("*{n}"), Style::colored(3)),
+            PointOfInterest::Altitude(None) => Span::plain(tr!(AtAltitudePrompt)),
+            PointOfInterest::Altitude(Some(n)) => Span::plain(tr!(AtAltitude, altitude = altitude_text(*n))),
+            PointOfInterest::Exit(None) => Span::new("E", Style::colored(1)),
+            PointOfInterest::Exit(Some(n)) => Span::new(format!("E{n}"), Style::colored(1)),
+            PointOfInterest::Grid(x, y) => Span::plain(format!(
+                "({}{}{})",
+                x.map(|x| x.to_string()).unwrap_or_default(),
+                if y.is_some() { "," } else { "" },
+                y.flatten().map(|y| y.to_string()).unwrap_or_default(),
+            )),
+        }]
     }
     fn to_complete(&self) -> Option<CompletePointOfInterest> {
         match self {
-            PointOfInterest::Default(n) | PointOfInterest::Beacon(Some(n)) => Some(CompletePointOfInterest::Beacon(*n)),
+            PointOfInterest::Default(n) => Some(CompletePointOfInterest::Beacon(Argument::Value(*n))),
+            PointOfInterest::Beacon(Some(n)) => Some(CompletePointOfInterest::Beacon(*n)),
+            PointOfInterest::Altitude(Some(n)) => Some(CompletePointOfInterest::Altitude(*n)),
+            PointOfInterest::Exit(Some(n)) => Some(CompletePointOfInterest::Exit(*n)),
+            PointOfInterest::Grid(Some(x), Some(Some(y))) => Some(CompletePointOfInterest::Grid(GroundLocation(*x, *y))),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum CompletePointOfInterest {
-    Beacon(u16),
+    Beacon(Argument),
+    ///Satisfied when the plane is at this flight level.
+    Altitude(Argument),
+    ///Satisfied when the plane is lined up with this exit, i.e. in its column for exits on the top
+    ///or bottom edge and in its row for exits on the left or right edge.
+    Exit(Argument),
+    Grid(GroundLocation),
 } impl ListItemPartRenderable for CompletePointOfInterest {
-    fn render(&self, colorize: bool) -> String {
-        match (self, colorize) {
-            (CompletePointOfInterest::Beacon(n), false) => format!("*{n}"),
-            (CompletePointOfInterest::Beacon(n), true)  => format!("\x1b[33m*{n}\x1b[39m"),
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        vec![match self {
+            CompletePointOfInterest::Beacon(n) => Span::new(format!("*{n}"), Style::colored(3).when(colorize)),
+            CompletePointOfInterest::Altitude(n) => Span::plain(format!("fl{}", short_altitude_text(*n))),
+            CompletePointOfInterest::Exit(n) => Span::new(format!("E{n}"), Style::colored(1).when(colorize)),
+            CompletePointOfInterest::Grid(GroundLocation(x, y)) => Span::plain(format!("({x},{y})")),
+        }]
+    }
+} impl Into<PointOfInterest> for CompletePointOfInterest {
+    fn into(self) -> PointOfInterest {
+        match self {
+            CompletePointOfInterest::Beacon(n) => PointOfInterest::Beacon(Some(n)),
+            CompletePointOfInterest::Altitude(n) => PointOfInterest::Altitude(Some(n)),
+            CompletePointOfInterest::Exit(n) => PointOfInterest::Exit(Some(n)),
+            CompletePointOfInterest::Grid(GroundLocation(x, y)) => PointOfInterest::Grid(Some(x), Some(Some(y))),
         }
     }
 } impl CompletePointOfInterest {
@@ -272,13 +485,31 @@ pub enum CompletePointOfInterest {
         match self {
             CompletePointOfInterest::Beacon(n) => {
                 for beacon in &map.beacons {
-                    if beacon.location() == plane.location() && beacon.index == *n {
+                    if beacon.location() == plane.location() && beacon.index == n.value() {
                         return true;
                     }
                 }
 
                 false
-            }
+            },
+            CompletePointOfInterest::Altitude(n) => plane.flight_level() == n.value(),
+            CompletePointOfInterest::Exit(n) => {
+                let Some(GroundLocation(px, py)) = plane.location() else { return false };
+                for exit in &map.exits {
+                    if exit.index != n.value() {
+                        continue;
+                    }
+                    let GroundLocation(ex, ey) = exit.exit_location.into();
+                    let on_vertical_edge = ex == 0 || ex == map.width - 1;
+                    let on_horizontal_edge = ey == 0 || ey == map.height - 1;
+                    if (on_horizontal_edge && px == ex) || (on_vertical_edge && py == ey) || (px == ex && py == ey) {
+                        return true;
+                    }
+                }
+
+                false
+            },
+            CompletePointOfInterest::Grid(loc) => plane.location() == Some(*loc),
         }
     }
 }
@@ -292,7 +523,11 @@ pub struct At {
         match (&mut self.poi, letter) {
             (None, '\x7f') => return InputHandling::Back,
             (None, 'b' | '*') => self.poi = Some(PointOfInterest::Beacon(None)),
+            (None, 'a') => self.poi = Some(PointOfInterest::Altitude(None)),
+            (None, 'e') => self.poi = Some(PointOfInterest::Exit(None)),
+            (None, 'g') => self.poi = Some(PointOfInterest::Grid(None, None)),
             (None, '0'..='9') => self.poi = Some(PointOfInterest::Default(digit_as_num(letter))),
+            (None, '$') => self.poi = Some(PointOfInterest::Beacon(Some(Argument::Placeholder))),
             (Some(ref mut poi), _) => {
                 return match poi.input(letter) {
                     InputHandling::Handled => InputHandling::Handled,
@@ -308,11 +543,8 @@ pub struct At {
 
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
-        format!("{} at {}", self.tail.as_text(), match &self.poi {
-            None => String::new(),
-            Some(poi) => poi.as_text(),
-        })
+    fn spans(&self) -> Vec<Span> {
+        tr_spans!(At, command = self.tail.spans(), point = self.poi.as_ref().map(|poi| poi.spans()).unwrap_or_default())
     }
     fn to_complete(&self) -> Option<CompleteAt> {
         let Some(tail) = self.tail.to_complete() else { return None };
@@ -326,34 +558,119 @@ pub struct At {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompleteAt {
     pub tail: Box<CompleteCommandSegment>,
     pub poi: CompletePointOfInterest,
 } impl ListItemPartRenderable for CompleteAt {
-    fn render(&self, colorize: bool) -> String {
-        format!("{}@{}", self.tail.render(colorize), self.poi.render(colorize))
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        [self.tail.spans(colorize), vec![Span::plain("@")], self.poi.spans(colorize)].concat()
+    }
+} impl CompleteAt {
+    fn bind_arguments(&mut self, arguments: &mut impl Iterator<Item = Argument>) -> bool {
+        let (CompletePointOfInterest::Beacon(p) | CompletePointOfInterest::Altitude(p) | CompletePointOfInterest::Exit(p)) = &mut self.poi else {
+            return self.tail.bind_arguments(arguments);
+        };
+        self.tail.bind_arguments(arguments) && p.bind(arguments)
+    }
+} impl Into<At> for CompleteAt {
+    fn into(self) -> At {
+        At {
+            tail: Box::new((*self.tail).into()),
+            poi: Some(self.poi.into()),
+        }
+    }
+}
+
+///Runs a fallback command if an `At` condition is not met within a number of ticks.
+#[derive(Debug, Clone)]
+pub struct Else {
+    pub at: At,
+    pub timeout: Option<Argument>,
+    ///The command run instead, once `:` has been typed after the timeout.
+    pub fallback: Option<Box<CommandSegment>>,
+} impl CommandFragment<CompleteElse> for Else {
+    fn input(&mut self, letter: char) -> InputHandling {
+        match (self.timeout, &mut self.fallback, letter) {
+            (None, _, '\x7f') => return InputHandling::Back,
+            (None, _, '0'..='9' | '$') => self.timeout = Argument::from_input(letter),
+            (Some(Argument::Value(v)), None, '0'..='9') => match append_digit(v, letter) {
+                Some(v) => self.timeout = Some(Argument::Value(v)),
+                None => return InputHandling::Unhandled,
+            },
+            (Some(Argument::Value(v)), None, '\x7f') if v >= 10 => self.timeout = Some(Argument::Value(v / 10)),
+            (Some(_), None, '\x7f') => self.timeout = None,
+            (Some(_), None, ':') => self.fallback = Some(Box::new(CommandSegment::None)),
+            (Some(_), Some(fallback), _) => match fallback.input(letter) {
+                InputHandling::Back => self.fallback = None,
+                handling => return handling,
+            },
+            _ => return InputHandling::Unhandled,
+        }
+
+        InputHandling::Handled
+    }
+    fn spans(&self) -> Vec<Span> {
+        match (self.timeout, &self.fallback) {
+            (None, _) => tr_spans!(OrElsePrompt, command = self.at.spans(), ticks = [timing_span(format!("?"))]),
+            (Some(t), None) => tr_spans!(OrElsePrompt, command = self.at.spans(), ticks = [timing_span(format!("?{t}"))]),
+            (Some(t), Some(fallback)) => tr_spans!(OrElse, command = self.at.spans(), ticks = [timing_span(format!("?{t}:"))], fallback = fallback.spans()),
+        }
+    }
+    fn to_complete(&self) -> Option<CompleteElse> {
+        let Some(at) = self.at.to_complete() else { return None };
+        let Some(timeout) = self.timeout else { return None };
+        let Some(fallback) = self.fallback.as_ref().and_then(|fallback| fallback.to_complete()) else { return None };
+        Some(CompleteElse {
+            at,
+            timeout,
+            remaining: None,
+            fallback: Box::new(fallback),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompleteElse {
+    pub at: CompleteAt,
+    pub timeout: Argument,
+    ///Ticks until the fallback runs. `None` until the command is first given to a plane.
+    #[serde(default)]
+    pub remaining: Option<u16>,
+    pub fallback: Box<CompleteCommandSegment>,
+} impl ListItemPartRenderable for CompleteElse {
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        let timeout = Span::new(format!("?{}:", self.timeout), Style::colored(6).when(colorize));
+        [self.at.spans(colorize), vec![timeout], self.fallback.spans(colorize)].concat()
+    }
+} impl Into<Else> for CompleteElse {
+    fn into(self) -> Else {
+        Else {
+            at: self.at.into(),
+            timeout: Some(self.timeout),
+            fallback: Some(Box::new((*self.fallback).into())),
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct In {
     pub tail: Box<CommandSegment>,
-    pub time: Option<u16>,
+    pub time: Option<Argument>,
 } impl CommandFragment<CompleteIn> for In {
     fn input(&mut self, letter: char) -> InputHandling {
         match (self.time, letter) {
             (None, '\x7f') => return InputHandling::Back,
-            (None, '0'..='9') => self.time = Some(digit_as_num(letter)),
+            (None, '0'..='9' | '$') => self.time = Argument::from_input(letter),
             _ => return InputHandling::Unhandled,
         }
 
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
+    fn spans(&self) -> Vec<Span> {
         match self.time {
-            None => format!("{} in \x1b[36m#\x1b[39m ticks", self.tail.as_text()),
-            Some(t) => format!("{} in \x1b[36m#{t}\x1b[39m ticks", self.tail.as_text()),
+            None => tr_spans!(In, command = self.tail.spans(), ticks = [timing_span(format!("#"))]),
+            Some(t) => tr_spans!(In, command = self.tail.spans(), ticks = [timing_span(format!("#{t}"))]),
         }
     }
     fn to_complete(&self) -> Option<CompleteIn> {
@@ -366,16 +683,77 @@ pub struct In {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompleteIn {
     pub tail: Box<CompleteCommandSegment>,
-    pub time: u16,
+    pub time: Argument,
 } impl ListItemPartRenderable for CompleteIn {
-    fn render(&self, colorize: bool) -> String {
-        if colorize {
-            format!("{}\x1b[36m#{}\x1b[39m", self.tail.render(true), self.time)
-        } else {
-            format!("{}#{}", self.tail.render(false), self.time)
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        [self.tail.spans(colorize), vec![Span::new(format!("#{}", self.time), Style::colored(6).when(colorize))]].concat()
+    }
+} impl Into<In> for CompleteIn {
+    fn into(self) -> In {
+        In {
+            tail: Box::new((*self.tail).into()),
+            time: Some(self.time),
+        }
+    }
+}
+
+///Repeats a command every so many ticks, until it is cancelled.
+#[derive(Debug, Clone)]
+pub struct Every {
+    pub tail: Box<CommandSegment>,
+    pub interval: Option<Argument>,
+} impl CommandFragment<CompleteEvery> for Every {
+    fn input(&mut self, letter: char) -> InputHandling {
+        match (self.interval, letter) {
+            (None, '\x7f') => return InputHandling::Back,
+            (None, '0'..='9' | '$') => self.interval = Argument::from_input(letter),
+            (Some(Argument::Value(v)), '0'..='9') => match append_digit(v, letter) {
+                Some(v) => self.interval = Some(Argument::Value(v)),
+                None => return InputHandling::Unhandled,
+            },
+            (Some(Argument::Value(v)), '\x7f') if v >= 10 => self.interval = Some(Argument::Value(v / 10)),
+            (Some(_), '\x7f') => self.interval = None,
+            _ => return InputHandling::Unhandled,
+        }
+
+        InputHandling::Handled
+    }
+    fn spans(&self) -> Vec<Span> {
+        match self.interval {
+            None => tr_spans!(Every, command = self.tail.spans(), ticks = [timing_span(format!("~"))]),
+            Some(t) => tr_spans!(Every, command = self.tail.spans(), ticks = [timing_span(format!("~{t}"))]),
+        }
+    }
+    fn to_complete(&self) -> Option<CompleteEvery> {
+        let Some(interval) = self.interval else { return None };
+        let Some(tail) = self.tail.to_complete() else { return None };
+        Some(CompleteEvery {
+            tail: Box::new(tail),
+            interval,
+            remaining: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompleteEvery {
+    pub tail: Box<CompleteCommandSegment>,
+    pub interval: Argument,
+    ///Ticks until the next repetition. `None` until the command is first given to a plane.
+    #[serde(default)]
+    pub remaining: Option<u16>,
+} impl ListItemPartRenderable for CompleteEvery {
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        [self.tail.spans(colorize), vec![Span::new(format!("~{}", self.interval), Style::colored(6).when(colorize))]].concat()
+    }
+} impl Into<Every> for CompleteEvery {
+    fn into(self) -> Every {
+        Every {
+            tail: Box::new((*self.tail).into()),
+            interval: Some(self.interval),
         }
     }
 }
@@ -391,8 +769,8 @@ pub struct And {
             (r, l) => r.input(l)
         }
     }
-    fn as_text(&self) -> String {
-        format!("{} & {}", self.left.as_text(), self.right.as_text())
+    fn spans(&self) -> Vec<Span> {
+        [self.left.spans(), vec![Span::plain(" & ")], self.right.spans()].concat()
     }
     fn to_complete(&self) -> Option<CompleteAnd> {
         let Some(left) = self.left.to_complete() else { return None };
@@ -402,49 +780,195 @@ pub struct And {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompleteAnd {
     pub left: Box<CompleteCommandSegment>,
     pub right: Box<CompleteCommandSegment>,
 } impl ListItemPartRenderable for CompleteAnd {
-    fn render(&self, colorize: bool) -> String {
-        format!("{};{}", self.left.render(colorize), self.right.render(colorize))
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        [self.left.spans(colorize), vec![Span::plain(";")], self.right.spans(colorize)].concat()
+    }
+} impl Into<And> for CompleteAnd {
+    fn into(self) -> And {
+        And {
+            left: Box::new((*self.left).into()),
+            right: Box::new((*self.right).into()),
+        }
+    }
+}
+
+///A delay or timeout, as it is previewed.
+fn timing_span(text: String) -> Span {
+    Span::new(text, Style::colored(6))
+}
+
+fn arguments_text(arguments: &[Argument]) -> String {
+    if arguments.is_empty() {
+        return String::new();
     }
+    format!("({})", arguments.iter().map(Argument::to_string).collect::<Vec<_>>().join(","))
 }
 
+///How a command slot is referred to: by its digit, or by the name it was given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SlotKey {
+    Number(u16),
+    Name(String),
+} impl SlotKey {
+    ///Whether this is the name of a slot with the given name. Names are not case sensitive.
+    pub fn names(&self, name: Option<&str>) -> bool {
+        matches!((self, name), (SlotKey::Name(key), Some(name)) if key.eq_ignore_ascii_case(name))
+    }
+} impl Display for SlotKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SlotKey::Number(n) => write!(f, "{n}"),
+            SlotKey::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+///A reference to a command slot, by its digit or by its name. Digits (or `$`) after it are
+///arguments which fill in the placeholders of the slot's command, in order. Letters after a name
+///continue it, so delays after a named reference must be entered as `@`, `#`, or `~`.
 #[derive(Debug, Clone, Default)]
-pub struct Ref(Option<u16>);
+pub struct Ref(Option<SlotKey>, Vec<Argument>);
 impl CommandFragment<CompleteRef> for Ref {
     fn input(&mut self, letter: char) -> InputHandling {
-        match (self.0, letter) {
+        match (&mut self.0, letter) {
             (None, '\x7f') => return InputHandling::Back,
-            (Some(_), '\x7f') => self.0 = None,
-            (None, '0'..='9') => self.0 = Some(digit_as_num(letter)),
+            (Some(key), '\x7f') => if self.1.pop().is_none() {
+                match key {
+                    SlotKey::Name(name) if name.len() > 1 => { name.pop(); },
+                    _ => self.0 = None,
+                }
+            },
+            (None, '0'..='9') => self.0 = Some(SlotKey::Number(digit_as_num(letter))),
+            (None, 'a'..='z' | 'A'..='Z') => self.0 = Some(SlotKey::Name(letter.to_string())),
+            (Some(SlotKey::Name(name)), 'a'..='z' | 'A'..='Z') if self.1.is_empty() => name.push(letter),
+            (Some(_), '0'..='9' | '$') => self.1.extend(Argument::from_input(letter)),
             _ => return InputHandling::Unhandled,
         }
 
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
-        match self.0 {
-            None => format!("\x1b[34m%\x1b[39m"),
-            Some(n) => format!("\x1b[34m%{n}\x1b[39m"),
-        }
+    fn spans(&self) -> Vec<Span> {
+        let text = match &self.0 {
+            None => format!("%"),
+            Some(key) => format!("%{key}{}", arguments_text(&self.1)),
+        };
+        vec![Span::new(text, Style::colored(4))]
     }
     fn to_complete(&self) -> Option<CompleteRef> {
-        self.0.map(CompleteRef)
+        self.0.clone().map(|key| CompleteRef(key, self.1.clone()))
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct CompleteRef(pub u16);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompleteRef(pub SlotKey, #[serde(default)] pub Vec<Argument>);
 impl ListItemPartRenderable for CompleteRef {
-    fn render(&self, colorize: bool) -> String {
-        if colorize {
-            format!("\x1b[34m%{}\x1b[39m", self.0)
-        } else {
-            format!("%{}", self.0)
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        vec![Span::new(format!("%{}{}", self.0, arguments_text(&self.1)), Style::colored(4).when(colorize))]
+    }
+} impl Into<Ref> for CompleteRef {
+    fn into(self) -> Ref {
+        Ref(Some(self.0), self.1)
+    }
+}
+///A new destination for a plane, entered as `e` and an exit's index or `a` and an airport's index.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Divert {
+    #[default]
+    None,
+    Exit(Option<u16>),
+    Airport(Option<u16>),
+} impl CommandFragment<CompleteDivert> for Divert {
+    fn input(&mut self, letter: char) -> InputHandling {
+        match (&self, letter) {
+            (Divert::None, '\x7f') => return InputHandling::Back,
+            (Divert::None, 'e') => *self = Divert::Exit(None),
+            (Divert::None, 'a') => *self = Divert::Airport(None),
+            (Divert::Exit(None) | Divert::Airport(None), '\x7f') => *self = Divert::None,
+            (Divert::Exit(None), '0'..='9') => *self = Divert::Exit(Some(digit_as_num(letter))),
+            (Divert::Airport(None), '0'..='9') => *self = Divert::Airport(Some(digit_as_num(letter))),
+            (Divert::Exit(Some(_)), '\x7f') => *self = Divert::Exit(None),
+            (Divert::Airport(Some(_)), '\x7f') => *self = Divert::Airport(None),
+            _ => return InputHandling::Unhandled,
+        }
+
+        InputHandling::Handled
+    }
+    fn spans(&self) -> Vec<Span> {
+        match self {
+            Divert::None => vec![Span::plain(tr!(DivertPrompt))],
+            Divert::Exit(n) => tr_spans!(Divert, destination = [Span::new(format!("E{}", n.map(|n| n.to_string()).unwrap_or_default()), Style::colored(1))]),
+            Divert::Airport(n) => tr_spans!(Divert, destination = [Span::new(format!("A{}", n.map(|n| n.to_string()).unwrap_or_default()), Style::colored(4))]),
+        }
+    }
+    fn to_complete(&self) -> Option<CompleteDivert> {
+        match self {
+            Divert::Exit(Some(n)) => Some(CompleteDivert::Exit(*n)),
+            Divert::Airport(Some(n)) => Some(CompleteDivert::Airport(*n)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CompleteDivert {
+    Exit(u16),
+    Airport(u16),
+} impl ListItemPartRenderable for CompleteDivert {
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        let color = match self {
+            CompleteDivert::Exit(_) => 1,
+            CompleteDivert::Airport(_) => 4,
+        };
+        vec![Span::plain("divert "), Span::new(self.to_string(), Style::colored(color).when(colorize))]
+    }
+} impl Into<Divert> for CompleteDivert {
+    fn into(self) -> Divert {
+        match self {
+            CompleteDivert::Exit(n) => Divert::Exit(Some(n)),
+            CompleteDivert::Airport(n) => Divert::Airport(Some(n)),
+        }
+    }
+} impl Display for CompleteDivert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompleteDivert::Exit(n) => write!(f, "E{n}"),
+            CompleteDivert::Airport(n) => write!(f, "A{n}"),
+        }
+    }
+}
+
+///Operations on a command slot itself, rather than a command to be stored in it.
+//This enum is always complete.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SlotAction {
+    Delete,
+    Edit,
+    Name(String),
+} impl CommandFragment<SlotAction> for SlotAction {
+    fn input(&mut self, letter: char) -> InputHandling {
+        match (self, letter) {
+            (SlotAction::Name(name), '\x7f') => if name.pop().is_none() { return InputHandling::Back },
+            (SlotAction::Name(name), _) if !letter.is_control() => name.push(letter),
+            (_, '\x7f') => return InputHandling::Back,
+            _ => return InputHandling::Unhandled,
         }
+
+        InputHandling::Handled
+    }
+    fn spans(&self) -> Vec<Span> {
+        vec![Span::plain(match self {
+            SlotAction::Delete => tr!(DeleteSlot),
+            SlotAction::Edit => tr!(EditSlot),
+            SlotAction::Name(name) => tr!(NameSlot, name = name),
+        })]
+    }
+    fn to_complete(&self) -> Option<SlotAction> {
+        Some(self.clone())
     }
 }
 
@@ -457,9 +981,24 @@ pub enum CommandSegment {
     Circle(Circle),
     SetVisibility(SetVisibility),
     At(At),
+    Else(Else),
     And(And),
     In(In),
+    Every(Every),
     Ref(Ref),
+    Cancel,
+    Takeoff,
+    Land,
+    Note(String),
+    ///Entered as `v` and the airway's index.
+    Airway(Option<u16>),
+    ///Entered as `p` and the procedure's index.
+    Procedure(Option<u16>),
+    ///Only given to planes which have not yet appeared. Entered as `h` and a number of ticks.
+    Hold(Option<u16>),
+    ///Entered as `d` and the new destination.
+    Divert(Divert),
+    SlotAction(SlotAction),
 } impl CommandSegment {
     pub fn current_segment(&self) -> CommandSegment {
         match self {
@@ -470,12 +1009,17 @@ pub enum CommandSegment {
     pub fn target(&self) -> Option<PointOfInterest> {
         match self.current_segment() {
             CommandSegment::At(At { poi: Some(p), .. }) => Some(p.clone()),
+            CommandSegment::Else(Else { at: At { poi: Some(p), .. }, .. }) => Some(p.clone()),
             _ => None,
         }
     }
+    ///The segment as it is previewed while it is typed.
+    pub fn preview(&self) -> Vec<Span> {
+        self.spans()
+    }
 } impl Display for CommandSegment {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_text())
+        write!(f, "{}", styled::text(&self.spans()))
     }
 } impl CommandFragment<CompleteCommandSegment> for CommandSegment {
     fn input(&mut self, letter: char) -> InputHandling {
@@ -491,6 +1035,13 @@ pub enum CommandSegment {
                     'm' => *self = CommandSegment::SetVisibility(SetVisibility::Mark),
                     'u' => *self = CommandSegment::SetVisibility(SetVisibility::Unmark),
                     'i' => *self = CommandSegment::SetVisibility(SetVisibility::Ignore),
+                    'x' => *self = CommandSegment::Cancel,
+                    'l' => *self = CommandSegment::Takeoff,
+                    'f' => *self = CommandSegment::Land,
+                    '"' => *self = CommandSegment::Note(String::new()),
+                    'v' => *self = CommandSegment::Airway(None),
+                    'p' => *self = CommandSegment::Procedure(None),
+                    'd' => *self = CommandSegment::Divert(Divert::default()),
                     _ => return InputHandling::Unhandled,
                 }
 
@@ -501,9 +1052,25 @@ pub enum CommandSegment {
             CommandSegment::Circle(c) => c.input(letter),
             CommandSegment::SetVisibility(v) => v.input(letter),
             CommandSegment::At(a) => a.input(letter),
+            CommandSegment::Else(e) => e.input(letter),
             CommandSegment::And(a) => a.input(letter),
             CommandSegment::In(i) => i.input(letter),
+            CommandSegment::Every(e) => e.input(letter),
             CommandSegment::Ref(r) => r.input(letter),
+            CommandSegment::Cancel | CommandSegment::Takeoff | CommandSegment::Land => if letter == '\x7f' { InputHandling::Back } else { InputHandling::Unhandled },
+            CommandSegment::Note(note) => match letter {
+                '\x7f' => if note.pop().is_none() { InputHandling::Back } else { InputHandling::Handled },
+                _ if !letter.is_control() => { note.push(letter); InputHandling::Handled },
+                _ => InputHandling::Unhandled,
+            },
+            CommandSegment::Airway(index) | CommandSegment::Procedure(index) | CommandSegment::Hold(index) => match (&index, letter) {
+                (None, '\x7f') => InputHandling::Back,
+                (None, '0'..='9') => { *index = Some(digit_as_num(letter)); InputHandling::Handled },
+                (Some(_), '\x7f') => { *index = None; InputHandling::Handled },
+                _ => InputHandling::Unhandled,
+            },
+            CommandSegment::Divert(d) => d.input(letter),
+            CommandSegment::SlotAction(s) => s.input(letter),
         };
 
         match response {
@@ -511,7 +1078,10 @@ pub enum CommandSegment {
                 match self {
                     CommandSegment::And(a) if a.to_complete().is_none() => InputHandling::Unhandled,
                     CommandSegment::At(a) if a.to_complete().is_none()  => InputHandling::Unhandled,
+                    CommandSegment::Else(e) if e.to_complete().is_none() => InputHandling::Unhandled,
                     CommandSegment::In(i) if i.to_complete().is_none()  => InputHandling::Unhandled,
+                    CommandSegment::Every(e) if e.to_complete().is_none() => InputHandling::Unhandled,
+                    CommandSegment::Note(_) | CommandSegment::Hold(_) | CommandSegment::SlotAction(_) => InputHandling::Unhandled,
                     _ => match letter {
                         'a' | '@' => {
                             *self = CommandSegment::At(At {
@@ -533,7 +1103,23 @@ pub enum CommandSegment {
                                 time: None,
                             });
                             InputHandling::Handled
-                        }
+                        },
+                        '?' => {
+                            let CommandSegment::At(at) = self else { return InputHandling::Unhandled };
+                            *self = CommandSegment::Else(Else {
+                                at: at.clone(),
+                                timeout: None,
+                                fallback: None,
+                            });
+                            InputHandling::Handled
+                        },
+                        '~' | 'r' => {
+                            *self = CommandSegment::Every(Every {
+                                tail: Box::new(self.clone()),
+                                interval: None,
+                            });
+                            InputHandling::Handled
+                        },
                         _ => InputHandling::Unhandled,
                     }
                 }
@@ -544,6 +1130,10 @@ pub enum CommandSegment {
                     *self = *a.tail.clone();
                     InputHandling::Handled
                 },
+                CommandSegment::Else(e) => {
+                    *self = CommandSegment::At(e.at.clone());
+                    InputHandling::Handled
+                },
                 CommandSegment::And(a) => {
                     *self = *a.left.clone();
                     InputHandling::Handled
@@ -552,6 +1142,10 @@ pub enum CommandSegment {
                     *self = *i.tail.clone();
                     InputHandling::Handled
                 },
+                CommandSegment::Every(e) => {
+                    *self = *e.tail.clone();
+                    InputHandling::Handled
+                },
                 _ => {
                     *self = CommandSegment::None;
                     InputHandling::Handled
@@ -559,18 +1153,33 @@ pub enum CommandSegment {
             }
         }
     }
-    fn as_text(&self) -> String {
-        match self {
-            CommandSegment::None => String::new(),
-            CommandSegment::Altitude(a) => a.as_text(),
-            CommandSegment::Turn(t) => t.as_text(),
-            CommandSegment::Circle(c) => c.as_text(),
-            CommandSegment::SetVisibility(v) => v.as_text(),
-            CommandSegment::At(a) => a.as_text(),
-            CommandSegment::And(a) => a.as_text(),
-            CommandSegment::In(i) => i.as_text(),
-            CommandSegment::Ref(r) => r.as_text(),
-        }
+    fn spans(&self) -> Vec<Span> {
+        let text = match self {
+            CommandSegment::None => return vec![],
+            CommandSegment::Altitude(a) => return a.spans(),
+            CommandSegment::Turn(t) => return t.spans(),
+            CommandSegment::Circle(c) => return c.spans(),
+            CommandSegment::SetVisibility(v) => return CommandFragment::spans(v),
+            CommandSegment::At(a) => return a.spans(),
+            CommandSegment::Else(e) => return e.spans(),
+            CommandSegment::And(a) => return a.spans(),
+            CommandSegment::In(i) => return i.spans(),
+            CommandSegment::Every(e) => return e.spans(),
+            CommandSegment::Ref(r) => return r.spans(),
+            CommandSegment::Divert(d) => return d.spans(),
+            CommandSegment::SlotAction(s) => return s.spans(),
+            CommandSegment::Cancel => tr!(Cancel),
+            CommandSegment::Takeoff => tr!(Takeoff),
+            CommandSegment::Land => tr!(Land),
+            CommandSegment::Note(note) => tr!(Note, note = note),
+            CommandSegment::Airway(None) => tr!(Airway, airway = ""),
+            CommandSegment::Airway(Some(n)) => tr!(Airway, airway = n),
+            CommandSegment::Procedure(None) => tr!(Procedure, procedure = ""),
+            CommandSegment::Procedure(Some(n)) => tr!(Procedure, procedure = n),
+            CommandSegment::Hold(None) => tr!(HoldPrompt),
+            CommandSegment::Hold(Some(n)) => tr!(Hold, ticks = n),
+        };
+        vec![Span::plain(text)]
     }
     fn to_complete(&self) -> Option<CompleteCommandSegment> {
         match self {
@@ -579,37 +1188,152 @@ pub enum CommandSegment {
             CommandSegment::Circle(c) => c.to_complete().map(CompleteCommandSegment::Circle),
             CommandSegment::SetVisibility(v) => Some(CompleteCommandSegment::SetVisibility(*v)),
             CommandSegment::At(a) => a.to_complete().map(CompleteCommandSegment::At),
+            CommandSegment::Else(e) => e.to_complete().map(CompleteCommandSegment::Else),
             CommandSegment::And(a) => a.to_complete().map(CompleteCommandSegment::And),
             CommandSegment::In(i) => i.to_complete().map(CompleteCommandSegment::In),
+            CommandSegment::Every(e) => e.to_complete().map(CompleteCommandSegment::Every),
             CommandSegment::Ref(r) => r.to_complete().map(CompleteCommandSegment::Ref),
+            CommandSegment::Cancel => Some(CompleteCommandSegment::Cancel),
+            CommandSegment::Takeoff => Some(CompleteCommandSegment::Takeoff),
+            CommandSegment::Land => Some(CompleteCommandSegment::Land),
+            CommandSegment::Note(note) => Some(CompleteCommandSegment::Note(note.clone())),
+            CommandSegment::Airway(airway) => airway.map(CompleteCommandSegment::Airway),
+            CommandSegment::Procedure(procedure) => procedure.map(CompleteCommandSegment::Procedure),
+            CommandSegment::Hold(ticks) => ticks.filter(|t| *t > 0).map(CompleteCommandSegment::Hold),
+            CommandSegment::Divert(d) => d.to_complete().map(CompleteCommandSegment::Divert),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompleteCommandSegment {
     Altitude(CompleteAltitude),
     Turn(CompleteTurn),
     Circle(CompleteCircle),
     SetVisibility(SetVisibility),
     At(CompleteAt),
+    Else(CompleteElse),
     And(CompleteAnd),
     In(CompleteIn),
+    Every(CompleteEvery),
     Ref(CompleteRef),
+    ///Clears the plane's pending command.
+    Cancel,
+    ///Lets a plane waiting at an airport take off, climbing to its assigned flight level or 1 if
+    ///it has none.
+    Takeoff,
+    ///Clears a plane on final approach to land, which it must be at night.
+    Land,
+    ///Sets the note on the plane's flight strip.
+    Note(String),
+    ///Follows an airway, turning onto each leg at its first beacon.
+    Airway(u16),
+    ///Flies a standard departure or arrival.
+    Procedure(u16),
+    ///Delays a plane's entry by this many ticks.
+    Hold(u16),
+    ///Gives the plane a new destination, at a cost to the score.
+    Divert(CompleteDivert),
     None,
 } impl ListItemPartRenderable for CompleteCommandSegment {
-    fn render(&self, colorize: bool) -> String {
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        let text = match self {
+            CompleteCommandSegment::Altitude(a) => return a.spans(colorize),
+            CompleteCommandSegment::Turn(t) => return t.spans(colorize),
+            CompleteCommandSegment::Circle(c) => return c.spans(colorize),
+            CompleteCommandSegment::SetVisibility(v) => return ListItemPartRenderable::spans(v, colorize),
+            CompleteCommandSegment::At(a) => return a.spans(colorize),
+            CompleteCommandSegment::Else(e) => return e.spans(colorize),
+            CompleteCommandSegment::And(a) => return a.spans(colorize),
+            CompleteCommandSegment::In(i) => return i.spans(colorize),
+            CompleteCommandSegment::Every(e) => return e.spans(colorize),
+            CompleteCommandSegment::Ref(r) => return r.spans(colorize),
+            CompleteCommandSegment::Divert(d) => return d.spans(colorize),
+            CompleteCommandSegment::None => return vec![Span::new("[]", Style { bg: Some(1), ..Style::default() }.when(colorize))],
+            CompleteCommandSegment::Cancel => format!("cancel"),
+            CompleteCommandSegment::Takeoff => format!("takeoff"),
+            CompleteCommandSegment::Land => format!("land"),
+            CompleteCommandSegment::Note(note) => format!("note: {note}"),
+            CompleteCommandSegment::Airway(n) => format!("via V{n}"),
+            CompleteCommandSegment::Procedure(n) => format!("cleared P{n}"),
+            CompleteCommandSegment::Hold(n) => format!("hold outside for {n}"),
+        };
+        vec![Span::plain(text)]
+    }
+} impl CompleteCommandSegment {
+    ///Whether the command sets up a repetition, which stays with the plane once given.
+    pub fn repeats(&self) -> bool {
+        match self {
+            CompleteCommandSegment::Every(_) => true,
+            CompleteCommandSegment::And(CompleteAnd { left, right }) => left.repeats() || right.repeats(),
+            _ => false,
+        }
+    }
+    ///Fills in placeholders with `arguments`, in the order they were entered. Returns false if there
+    ///are not enough arguments; some placeholders will have been filled in regardless.
+    pub fn bind_arguments(&mut self, arguments: &mut impl Iterator<Item = Argument>) -> bool {
+        match self {
+            CompleteCommandSegment::Altitude(CompleteAltitude::To(a) | CompleteAltitude::Plus(a) | CompleteAltitude::Minus(a)) => a.bind(arguments),
+            CompleteCommandSegment::At(at) => at.bind_arguments(arguments),
+            CompleteCommandSegment::Else(CompleteElse { at, timeout, fallback, .. }) => at.bind_arguments(arguments) && timeout.bind(arguments) && fallback.bind_arguments(arguments),
+            CompleteCommandSegment::In(CompleteIn { tail, time }) => tail.bind_arguments(arguments) && time.bind(arguments),
+            CompleteCommandSegment::Every(CompleteEvery { tail, interval, .. }) => tail.bind_arguments(arguments) && interval.bind(arguments),
+            CompleteCommandSegment::And(CompleteAnd { left, right }) => left.bind_arguments(arguments) && right.bind_arguments(arguments),
+            CompleteCommandSegment::Ref(CompleteRef(_, args)) => args.iter_mut().all(|a| a.bind(arguments)),
+            _ => true,
+        }
+    }
+    ///Whether the command can be given to a plane. Holds are only for planes which have not
+    ///appeared, so a slot read from a file holding one could never be used.
+    pub fn is_for_planes(&self) -> bool {
         match self {
-            CompleteCommandSegment::Altitude(a) => a.render(colorize),
-            CompleteCommandSegment::Turn(t) => t.render(colorize),
-            CompleteCommandSegment::Circle(c) => c.render(colorize),
-            CompleteCommandSegment::SetVisibility(v) => v.render(colorize),
-            CompleteCommandSegment::At(a) => a.render(colorize),
-            CompleteCommandSegment::And(a) => a.render(colorize),
-            CompleteCommandSegment::In(i) => i.render(colorize),
-            CompleteCommandSegment::Ref(r) => r.render(colorize),
-            CompleteCommandSegment::None => if colorize { String::from("\x1b[41m[]\x1b[49m") } else { String::from("[]") },
+            CompleteCommandSegment::Hold(_) => false,
+            CompleteCommandSegment::At(CompleteAt { tail, .. }) | CompleteCommandSegment::In(CompleteIn { tail, .. }) | CompleteCommandSegment::Every(CompleteEvery { tail, .. }) => tail.is_for_planes(),
+            CompleteCommandSegment::Else(CompleteElse { at: CompleteAt { tail, .. }, fallback, .. }) => tail.is_for_planes() && fallback.is_for_planes(),
+            CompleteCommandSegment::And(CompleteAnd { left, right }) => left.is_for_planes() && right.is_for_planes(),
+            _ => true,
+        }
+    }
+    ///Points which some part of the command waits for the plane to reach. Commands in slots are
+    ///not looked into.
+    pub fn conditions(&self) -> Vec<CompletePointOfInterest> {
+        match self {
+            CompleteCommandSegment::At(CompleteAt { tail, poi }) | CompleteCommandSegment::Else(CompleteElse { at: CompleteAt { tail, poi }, .. }) => {
+                let mut conditions = tail.conditions();
+                conditions.push(*poi);
+                if let CompleteCommandSegment::Else(CompleteElse { fallback, .. }) = self {
+                    conditions.extend(fallback.conditions());
+                }
+                conditions
+            },
+            CompleteCommandSegment::In(CompleteIn { tail, .. }) | CompleteCommandSegment::Every(CompleteEvery { tail, .. }) => tail.conditions(),
+            CompleteCommandSegment::And(CompleteAnd { left, right }) => [left.conditions(), right.conditions()].concat(),
+            _ => vec![],
+        }
+    }
+} impl Into<CommandSegment> for CompleteCommandSegment {
+    fn into(self) -> CommandSegment {
+        match self {
+            CompleteCommandSegment::Altitude(a) => CommandSegment::Altitude(a.into()),
+            CompleteCommandSegment::Turn(t) => CommandSegment::Turn(t.into()),
+            CompleteCommandSegment::Circle(c) => CommandSegment::Circle(c.into()),
+            CompleteCommandSegment::SetVisibility(v) => CommandSegment::SetVisibility(v),
+            CompleteCommandSegment::At(a) => CommandSegment::At(a.into()),
+            CompleteCommandSegment::Else(e) => CommandSegment::Else(e.into()),
+            CompleteCommandSegment::And(a) => CommandSegment::And(a.into()),
+            CompleteCommandSegment::In(i) => CommandSegment::In(i.into()),
+            CompleteCommandSegment::Every(e) => CommandSegment::Every(e.into()),
+            CompleteCommandSegment::Ref(r) => CommandSegment::Ref(r.into()),
+            CompleteCommandSegment::Cancel => CommandSegment::Cancel,
+            CompleteCommandSegment::Takeoff => CommandSegment::Takeoff,
+            CompleteCommandSegment::Land => CommandSegment::Land,
+            CompleteCommandSegment::Note(note) => CommandSegment::Note(note),
+            CompleteCommandSegment::Airway(n) => CommandSegment::Airway(Some(n)),
+            CompleteCommandSegment::Procedure(n) => CommandSegment::Procedure(Some(n)),
+            CompleteCommandSegment::Hold(n) => CommandSegment::Hold(Some(n)),
+            CompleteCommandSegment::Divert(d) => CommandSegment::Divert(d.into()),
+            CompleteCommandSegment::None => CommandSegment::None,
         }
     }
 }
@@ -619,54 +1343,107 @@ pub enum CommandTarget {
     #[default]
     None,
     Plane(char),
-    Slot(Option<u16>),
+    ///Every marked plane.
+    All,
+    Slot(Option<SlotKey>),
+    ///The name of a slot being entered after `%`, finished with a space.
+    SlotName(String),
+    SlotList,
+    ///A plane which has been announced but has not yet appeared, entered as `^` and its callsign.
+    Pending(Option<char>),
+    ///A plane to describe without commanding it, entered as `?` and its callsign.
+    Query(Option<char>),
 } impl CommandFragment<CompleteCommandTarget> for CommandTarget {
     fn input(&mut self, letter: char) -> InputHandling {
+        if let CommandTarget::SlotName(name) = self {
+            match letter {
+                '\x7f' => if name.pop().is_some() && name.is_empty() { *self = CommandTarget::Slot(None) },
+                ' ' => *self = CommandTarget::Slot(Some(SlotKey::Name(std::mem::take(name)))),
+                'a'..='z' | 'A'..='Z' => name.push(letter),
+                _ => return InputHandling::Unhandled,
+            }
+            return InputHandling::Handled;
+        }
         match (&self, letter) {
             (CommandTarget::None, '\x7f') => return InputHandling::Back,
-            (CommandTarget::Plane(_), '\x7f') => *self = CommandTarget::None,
+            (CommandTarget::Plane(_) | CommandTarget::All, '\x7f') => *self = CommandTarget::None,
             (CommandTarget::Slot(None), '\x7f') => *self = CommandTarget::None,
+            (CommandTarget::Slot(Some(SlotKey::Name(name))), '\x7f') => *self = CommandTarget::SlotName(name.clone()),
             (CommandTarget::Slot(Some(_)), '\x7f') => *self = CommandTarget::Slot(None),
+            (CommandTarget::SlotList, '\x7f') => *self = CommandTarget::Slot(None),
+            (CommandTarget::Pending(None), '\x7f') => *self = CommandTarget::None,
+            (CommandTarget::Pending(Some(_)), '\x7f') => *self = CommandTarget::Pending(None),
+            (CommandTarget::Query(None), '\x7f') => *self = CommandTarget::None,
+            (CommandTarget::Query(Some(_)), '\x7f') => *self = CommandTarget::Query(None),
 
             (CommandTarget::None, 'a'..='z' | 'A'..='Z') => *self = CommandTarget::Plane(letter),
             (CommandTarget::None, '%') => *self = CommandTarget::Slot(None),
-            (CommandTarget::Slot(None), '0'..='9') => *self = CommandTarget::Slot(Some(digit_as_num(letter))),
+            (CommandTarget::None, '!' | '*') => *self = CommandTarget::All,
+            (CommandTarget::Slot(None), '0'..='9') => *self = CommandTarget::Slot(Some(SlotKey::Number(digit_as_num(letter)))),
+            (CommandTarget::Slot(None), 'a'..='z' | 'A'..='Z') => *self = CommandTarget::SlotName(letter.to_string()),
+            (CommandTarget::Slot(None), '?') => *self = CommandTarget::SlotList,
+            (CommandTarget::None, '^') => *self = CommandTarget::Pending(None),
+            (CommandTarget::Pending(None), 'a'..='z' | 'A'..='Z') => *self = CommandTarget::Pending(Some(letter)),
+            (CommandTarget::None, '?') => *self = CommandTarget::Query(None),
+            (CommandTarget::Query(None), 'a'..='z' | 'A'..='Z') => *self = CommandTarget::Query(Some(letter)),
             _ => return InputHandling::Unhandled,
         }
 
         InputHandling::Handled
     }
-    fn as_text(&self) -> String {
+    fn spans(&self) -> Vec<Span> {
+        let (plane, slot, pending, query) = (Style::colored(2), Style::colored(4), Style { dim: true, ..Style::default() }, Style::colored(6));
         match self {
-            CommandTarget::None => String::new(),
-            CommandTarget::Plane(c) => format!("\x1b[32m{c}\x1b[39m: "),
-            CommandTarget::Slot(None) => format!("\x1b[34m%\x1b[39m"),
-            CommandTarget::Slot(Some(n)) => format!("\x1b[34m%{n}\x1b[39m: "),
+            CommandTarget::None => vec![],
+            CommandTarget::Plane(c) => vec![Span::new(*c, plane), Span::plain(": ")],
+            CommandTarget::All => vec![Span::new(tr!(AllPlanes), plane), Span::plain(": ")],
+            CommandTarget::Slot(None) => vec![Span::new("%", slot)],
+            CommandTarget::Slot(Some(key)) => vec![Span::new(format!("%{key}"), slot), Span::plain(": ")],
+            CommandTarget::SlotName(name) => vec![Span::new(format!("%{name}"), slot)],
+            CommandTarget::SlotList => vec![Span::new("%?", slot), Span::plain(format!(" {}", tr!(ListSlots)))],
+            CommandTarget::Pending(None) => vec![Span::new("^", pending)],
+            CommandTarget::Pending(Some(c)) => vec![Span::new(format!("^{c}"), pending), Span::plain(": ")],
+            CommandTarget::Query(None) => vec![Span::new("?", query)],
+            CommandTarget::Query(Some(c)) => vec![Span::new(format!("?{c}"), query), Span::plain(format!(" {}", tr!(Query)))],
         }
     }
     fn to_complete(&self) -> Option<CompleteCommandTarget> {
         match self {
             CommandTarget::Plane(c) => Some(CompleteCommandTarget::Plane(*c)),
-            CommandTarget::Slot(Some(n)) => Some(CompleteCommandTarget::Slot(*n)),
+            CommandTarget::All => Some(CompleteCommandTarget::All),
+            CommandTarget::Slot(Some(key)) => Some(CompleteCommandTarget::Slot(key.clone())),
+            CommandTarget::SlotList => Some(CompleteCommandTarget::SlotList),
+            CommandTarget::Pending(Some(c)) => Some(CompleteCommandTarget::Pending(*c)),
+            CommandTarget::Query(Some(c)) => Some(CompleteCommandTarget::Query(*c)),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompleteCommandTarget {
     Plane(char),
-    Slot(u16),
+    All,
+    Slot(SlotKey),
+    ///Deletes, edits, or names the slot, rather than storing a command in it.
+    SlotAction(SlotKey, SlotAction),
+    SlotList,
+    Pending(char),
+    Query(char),
 } impl CompleteCommandTarget {
-    pub fn as_text(self) -> String {
+    pub fn spans(self) -> Vec<Span> {
         let incomplete: CommandTarget = self.into();
-        incomplete.as_text()
+        incomplete.spans()
     }
 } impl Into<CommandTarget> for CompleteCommandTarget {
     fn into(self) -> CommandTarget {
         match self {
             CompleteCommandTarget::Plane(p) => CommandTarget::Plane(p),
-            CompleteCommandTarget::Slot(s)  => CommandTarget::Slot(Some(s)),
+            CompleteCommandTarget::All => CommandTarget::All,
+            CompleteCommandTarget::Slot(key) | CompleteCommandTarget::SlotAction(key, _) => CommandTarget::Slot(Some(key)),
+            CompleteCommandTarget::SlotList => CommandTarget::SlotList,
+            CompleteCommandTarget::Pending(p) => CommandTarget::Pending(Some(p)),
+            CompleteCommandTarget::Query(p) => CommandTarget::Query(Some(p)),
         }
     }
 }
@@ -685,6 +1462,20 @@ pub struct Command {
     pub fn input(&mut self, letter: char) {
         match self.target.to_complete() {
             None => { self.target.input(letter); },
+            Some(CompleteCommandTarget::SlotList | CompleteCommandTarget::Query(_)) if letter != '\x7f' => {},
+            Some(CompleteCommandTarget::Slot(_)) if matches!(self.head, CommandSegment::None) && matches!(letter, 'x' | 'e' | 'n') => {
+                self.head = CommandSegment::SlotAction(match letter {
+                    'x' => SlotAction::Delete,
+                    'e' => SlotAction::Edit,
+                    _ => SlotAction::Name(String::new()),
+                });
+            },
+            //Planes which have not appeared can only be held.
+            Some(CompleteCommandTarget::Pending(_)) if matches!(self.head, CommandSegment::None) && letter != '\x7f' => {
+                if letter == 'h' {
+                    self.head = CommandSegment::Hold(None);
+                }
+            },
             Some(_) => match self.head.input(letter) {
                 InputHandling::Handled => {},
                 InputHandling::Unhandled => {
@@ -698,6 +1489,14 @@ pub struct Command {
     }
     pub fn to_complete(&mut self) -> Option<CompleteCommand> {
         let Some(target) = self.target.to_complete() else { return None };
+        if let CompleteCommandTarget::SlotList | CompleteCommandTarget::Query(_) = target {
+            return Some(CompleteCommand { target, head: CompleteCommandSegment::None });
+        }
+        //Slot actions apply to the slot itself, so they are never stored in one or given to a plane.
+        if let (CompleteCommandTarget::Slot(key), CommandSegment::SlotAction(action)) = (&target, &self.head) {
+            let target = CompleteCommandTarget::SlotAction(key.clone(), action.to_complete()?);
+            return Some(CompleteCommand { target, head: CompleteCommandSegment::None });
+        }
         let Some(command) = self.head.to_complete() else { return None };
         Some(CompleteCommand {
             target, head: command,
@@ -706,20 +1505,53 @@ pub struct Command {
     pub fn current_segment(&self) -> CommandSegment {
         self.head.current_segment()
     }
+    ///The command as it is previewed while it is typed.
+    pub fn spans(&self) -> Vec<Span> {
+        [self.target.spans(), self.head.spans()].concat()
+    }
 } impl Display for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.target.as_text())?;
-        write!(f, "{}", self.head.as_text())?;
-        Ok(())
+        write!(f, "{}", styled::text(&self.spans()))
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompleteCommand {
     pub target: CompleteCommandTarget,
     pub head: CompleteCommandSegment,
 } impl ListItemPartRenderable for CompleteCommand {
-    fn render(&self, colorize: bool) -> String {
-        self.head.render(colorize)
+    fn spans(&self, colorize: bool) -> Vec<Span> {
+        self.head.spans(colorize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> Command {
+        let mut command = Command::default();
+        for letter in text.chars() {
+            command.input(letter);
+        }
+        command
+    }
+
+    #[test]
+    fn numbers_too_large_are_not_typed() {
+        let mut command = typed("atwag65535,99999999");
+        let Some(CompleteCommandSegment::At(CompleteAt { poi: CompletePointOfInterest::Grid(GroundLocation(x, y)), .. })) = command.to_complete().map(|command| command.head) else { panic!("a grid location") };
+        assert_eq!((x, y), (65535, 9999));
+        let Some(CompleteCommandSegment::Every(CompleteEvery { interval, .. })) = typed("am~700000").to_complete().map(|command| command.head) else { panic!("a repeating command") };
+        assert_eq!(interval, Argument::Value(7000));
+    }
+
+    #[test]
+    fn fallbacks_follow_a_colon() {
+        assert!(typed("atwag1,2?5a2").to_complete().is_none());
+        let command = typed("atwag1,2?5:a2").to_complete().expect("command to be complete");
+        let CompleteCommandSegment::Else(CompleteElse { timeout, fallback, .. }) = command.head else { panic!("a conditional with a fallback") };
+        assert_eq!(timeout, Argument::Value(5));
+        assert!(matches!(*fallback, CompleteCommandSegment::Altitude(CompleteAltitude::To(Argument::Value(2)))));
     }
 }
@@ -0,0 +1,144 @@
+use std::{backtrace::Backtrace, collections::VecDeque, env, fs, panic::{self, PanicHookInfo}, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Mutex}, thread, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::Result;
+
+use crate::{input::Input, paths, GameSettings};
+
+///Most key presses kept for a crash report. Older ones are forgotten as new ones come.
+const INPUT_RETENTION: usize = 300;
+
+///What is known about the game being played, for a crash report if it panics.
+#[derive(Debug, Default)]
+struct Context {
+    map: String,
+    seed: u64,
+    settings: String,
+    tick: u32,
+    ///The latest key presses, with the tick each was pressed on.
+    inputs: VecDeque<(u32, Input)>,
+} impl Context {
+    fn record_input(&mut self, input: Input) {
+        if self.inputs.len() == INPUT_RETENTION {
+            self.inputs.pop_front();
+        }
+        self.inputs.push_back((self.tick, input));
+    }
+    ///Everything needed to play the game again up to the panic, along with where it happened.
+    fn bundle(&self, message: &str, location: &str, backtrace: &str) -> serde_json::Value {
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "message": message,
+            "location": location,
+            "arguments": env::args().collect::<Vec<_>>(),
+            "map": self.map,
+            "seed": self.seed,
+            "settings": self.settings,
+            "tick": self.tick,
+            "inputs": self.inputs.iter().map(|(tick, input)| (tick, format!("{input:?}"))).collect::<Vec<_>>(),
+            "backtrace": backtrace,
+        })
+    }
+}
+
+static CONTEXT: Mutex<Option<Context>> = Mutex::new(None);
+///The panic message and where its report was saved, once the game has panicked.
+static REPORT: Mutex<Option<(String, Result<PathBuf, String>)>> = Mutex::new(None);
+///Whether a thread other than the main one has panicked. Only the main thread's panics unwind the
+///game, so `check_threads` carries the others over to it.
+static PANICKED_ELSEWHERE: AtomicBool = AtomicBool::new(false);
+
+///Saves a crash report when the game panics, instead of printing the panic over the game, where
+///it would be lost once the terminal is restored. `report` tells the player about it afterward.
+pub fn install() {
+    *CONTEXT.lock().unwrap_or_else(|e| e.into_inner()) = Some(Context::default());
+    panic::set_hook(Box::new(|info| {
+        let message = panic_message(info);
+        let location = info.location().map_or(format!("an unknown location"), |location| location.to_string());
+        let saved = save(&message, &location).map_err(|e| e.to_string());
+        *REPORT.lock().unwrap_or_else(|e| e.into_inner()) = Some((format!("{message} at {location}"), saved));
+        if thread::current().name() != Some("main") {
+            PANICKED_ELSEWHERE.store(true, Ordering::SeqCst);
+        }
+    }));
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    payload.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| format!("unknown panic"))
+}
+
+fn save(message: &str, location: &str) -> Result<PathBuf> {
+    let Some(dir) = paths::data_dir().map(|dir| dir.join("crashes")) else { anyhow::bail!("There is no data directory to save it in.") };
+    let bundle = match CONTEXT.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(context) => context.bundle(message, location, &Backtrace::force_capture().to_string()),
+        None => anyhow::bail!("The game had not started."),
+    };
+    fs::create_dir_all(&dir)?;
+    let file = dir.join(format!("crash-{}.json", SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis()));
+    fs::write(&file, serde_json::ser::to_string_pretty(&bundle)?)?;
+    Ok(file)
+}
+
+fn update(change: impl FnOnce(&mut Context)) {
+    if let Some(context) = CONTEXT.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        change(context);
+    }
+}
+
+///Notes the game being started, which a crash report is about.
+pub fn start_game(map: &str, seed: u64, settings: &GameSettings) {
+    update(|context| {
+        context.map = map.to_string();
+        context.seed = seed;
+        context.settings = format!("{settings:?}");
+        context.inputs.clear();
+    });
+}
+
+pub fn set_tick(tick: u32) {
+    update(|context| context.tick = tick);
+}
+
+pub fn record_input(input: Input) {
+    update(|context| context.record_input(input));
+}
+
+///Panics on this thread if another has panicked, so that the game is unwound, restoring the
+///terminal, instead of carrying on without input or hanging. The first panic is the one reported.
+pub fn check_threads() {
+    if PANICKED_ELSEWHERE.swap(false, Ordering::SeqCst) {
+        panic::resume_unwind(Box::new(format!("another thread panicked")));
+    }
+}
+
+///Tells the player why the game stopped, and where its crash report was saved, once the terminal
+///has been restored. Returns whether there was a panic to report.
+pub fn report() -> bool {
+    let Some((panic, saved)) = REPORT.lock().unwrap_or_else(|e| e.into_inner()).take() else { return false };
+    eprintln!("ATC crashed: {panic}.");
+    match saved {
+        Ok(file) => eprintln!("A crash report was saved to {}. Please include it when reporting the bug.", file.display()),
+        Err(e) => eprintln!("Could not save a crash report: {e}"),
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_inputs() {
+        let mut context = Context { map: format!("crossing"), seed: 42, ..Default::default() };
+        for tick in 0..INPUT_RETENTION as u32 + 10 {
+            context.tick = tick;
+            context.record_input(Input::Char('a'));
+        }
+        let bundle = context.bundle("oops", "src/map.rs:1:1", "");
+        assert_eq!((&bundle["map"], &bundle["seed"], &bundle["tick"]), (&serde_json::json!("crossing"), &serde_json::json!(42), &serde_json::json!(INPUT_RETENTION + 9)));
+        assert_eq!(bundle["inputs"].as_array().map(Vec::len), Some(INPUT_RETENTION));
+        assert_eq!(bundle["inputs"][0], serde_json::json!([10, "Char('a')"]));
+    }
+}
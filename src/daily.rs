@@ -0,0 +1,81 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{map::Map, GameStatus};
+
+///Fewest and most ticks between plane spawns in a daily challenge.
+const SPAWN_RATES: (u32, u32) = (15, 30);
+///Planes shown on each row of the result grid.
+const GRID_WIDTH: usize = 10;
+
+///The scenario for one day's challenge, the same for everyone playing on that date.
+#[derive(Debug, Clone)]
+pub struct Daily {
+    ///The date, as `YYYY-MM-DD`.
+    pub date: String,
+    pub seed: u64,
+    ///Name of the generated map, which `--map` would accept.
+    pub map: String,
+    pub plane_spawn_rate: u32,
+} impl Daily {
+    ///Today's challenge, by the UTC date.
+    pub fn today() -> Self {
+        let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86400).unwrap_or_default();
+        Daily::on_day(days as i64)
+    }
+    ///The challenge on the day this many days after 1970-01-01.
+    pub fn on_day(days: i64) -> Self {
+        let (year, month, day) = civil_date(days);
+        let date = format!("{year:04}-{month:02}-{day:02}");
+        //Seeded from the date itself, with an RNG whose output is the same on every platform and
+        //version, so the scenario doesn't depend on anything else on the machine.
+        let mut rng = ChaCha8Rng::seed_from_u64(year as u64 * 10000 + month as u64 * 100 + day as u64);
+        let seed = rng.random();
+        Daily {
+            date,
+            seed,
+            map: format!("random:{}", rng.random::<u64>()),
+            plane_spawn_rate: rng.random_range(SPAWN_RATES.0..=SPAWN_RATES.1),
+        }
+    }
+    ///A result to share, with the score and a square for each plane: green if it reached its
+    ///destination within par, yellow if it reached it later, red if it ended the game, and white if
+    ///it was still flying.
+    pub fn summary(&self, map: &Map) -> String {
+        let ended_by = map.exit_state().iter().flat_map(|status| match *status {
+            GameStatus::PlanesCrashed(a, b) | GameStatus::PlanesInConflict(a, b) => vec![a, b],
+            GameStatus::PlaneExited(p) | GameStatus::PlaneFailedLanding(p) | GameStatus::PlaneMissedExitLevels(p) | GameStatus::PlaneFoundNoStand(p) | GameStatus::PlaneLandedUncleared(p) => vec![p],
+            GameStatus::SituationCleared(_) | GameStatus::Completed(_) => vec![],
+        }).collect::<Vec<_>>();
+        let squares = map.stats.planes.iter().map(|plane| match plane.finished {
+            Some(_) if plane.handling_time <= plane.par => '🟩',
+            Some(_) => '🟨',
+            None if ended_by.contains(&plane.callsign) => '🟥',
+            None => '⬜',
+        }).collect::<Vec<_>>();
+
+        let mut summary = format!("ATC Daily {}: {} points in {} ticks", self.date, map.score(), map.tick_no());
+        for row in squares.chunks(GRID_WIDTH) {
+            summary += "\n";
+            summary.extend(row);
+        }
+        summary
+    }
+}
+
+///Converts days since 1970-01-01 into a year, month, and day.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    //Counts from 0000-03-01, so that leap days fall at the end of each year.
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
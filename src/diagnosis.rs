@@ -0,0 +1,122 @@
+use crate::{direction::{CardinalDirection, OrdinalDirection}, location::{Destination, GroundLocation, Location}, map_objects::{GridRenderable, ListItemPartRenderable}, plane::{Plane, Visibility}, stats::{GameStats, PlaneStats}, units::altitude_unit, GameStatus};
+
+///Explains how a game was lost, from the commands given to the planes involved and what they did,
+///as a learning aid. `tick` is the tick the game was lost on, and `planes` are those still in it.
+pub fn diagnose(status: GameStatus, tick: u32, stats: &GameStats, planes: &[Plane]) -> Vec<String> {
+    let mut findings = vec![];
+    let callsigns = match status {
+        GameStatus::PlanesCrashed(a, b) | GameStatus::PlanesInConflict(a, b) => {
+            let start = stats.conflict_start(a, b);
+            match start {
+                Some(start) if start < tick => findings.push(format!("Planes {a} and {b} came into conflict at tick {start}, {} ticks before.", tick - start)),
+                Some(_) => {},
+                None => findings.push(format!("Planes {a} and {b} collided without coming into conflict first.")),
+            }
+            let levels = [a, b].map(|callsign| stats.latest(callsign).and_then(|history| Some((history.altitudes.last()?, held_level_since(history, start)))));
+            if let [Some((level_a, true)), Some((level_b, true))] = levels {
+                if level_a == level_b {
+                    findings.push(format!("Both stayed at {}; a climb or descent for either would have kept them apart.", altitude_unit().long(*level_a)));
+                }
+            }
+            for callsign in [a, b] {
+                findings.extend(stats.latest(callsign).and_then(|history| neglect(history, start.filter(|start| *start < tick))));
+            }
+            vec![a, b]
+        },
+        GameStatus::PlaneExited(callsign) | GameStatus::PlaneMissedExitLevels(callsign) | GameStatus::PlaneFailedLanding(callsign) => {
+            let plane = planes.iter().find(|plane| plane.callsign == callsign);
+            let history = stats.latest(callsign);
+            let never = |climbing: bool| history.is_some_and(|history| !history.altitudes.windows(2).any(|pair| pair[1] != pair[0] && (pair[1] > pair[0]) == climbing));
+            match plane.map(|plane| (plane.location, plane.destination)) {
+                Some((Location::Flight(location), Destination::Airport(airport))) if GroundLocation::from(location) == airport.location => {
+                    let heading = <CardinalDirection as Into<OrdinalDirection>>::into(airport.launch_direction).to_deg();
+                    findings.push(format!("Plane {callsign} reached {} from the wrong side; planes land there heading {heading:03}, coming in behind the runway.", Destination::Airport(airport)));
+                },
+                Some((Location::Flight(location), destination)) if location.2 == 0 => {
+                    findings.push(format!("Plane {callsign} descended to the ground at {},{}, {} cells from {destination}.", location.0, location.1, destination.location().map_or(0, |at| GroundLocation::from(location).chebyshev_distance(at))));
+                },
+                Some((Location::Flight(_), Destination::Airport(airport))) if never(false) => {
+                    findings.push(format!("Plane {callsign} was bound for {} but was never given a descent.", Destination::Airport(airport)));
+                },
+                Some((Location::Flight(location), Destination::Exit(exit))) if GroundLocation::from(location) == exit.exit_location.into() => {
+                    let level = location.2;
+                    let needed = (0..100).filter(|level| exit.allows_level(*level)).min_by_key(|needed| needed.abs_diff(level)).unwrap_or(level);
+                    findings.push(format!("Plane {callsign} reached {} at {}, but could only leave at {}.", Destination::Exit(exit), altitude_unit().long(level), altitude_unit().long(needed)));
+                    if never(needed > level) {
+                        findings.push(format!("Plane {callsign} was never given a {}.", if needed > level { "climb" } else { "descent" }));
+                    }
+                },
+                Some((Location::Flight(location), Destination::Exit(exit))) => {
+                    let location = GroundLocation::from(location);
+                    findings.push(format!("Plane {callsign} left the map at {},{}, {} cells from {}.", location.0, location.1, location.chebyshev_distance(exit.exit_location.into()), Destination::Exit(exit)));
+                },
+                _ => {},
+            }
+            findings.extend(history.and_then(|history| neglect(history, None)));
+            vec![callsign]
+        },
+        GameStatus::PlaneFoundNoStand(callsign) => {
+            findings.push(format!("Plane {callsign} was allowed to land with every stand taken; hold arrivals, or divert them, while stands are short."));
+            vec![callsign]
+        },
+        GameStatus::PlaneLandedUncleared(callsign) => {
+            findings.push(format!("Plane {callsign} was never cleared to land (F) on its final approach."));
+            vec![callsign]
+        },
+        GameStatus::SituationCleared(_) | GameStatus::Completed(_) => vec![],
+    };
+    for plane in planes.iter().filter(|plane| callsigns.contains(&plane.callsign)) {
+        if plane.is_nordo() {
+            findings.push(format!("Plane {}'s radio had failed, so commands could not reach it.", plane.callsign));
+        }
+        if plane.show == Visibility::Ignored {
+            findings.push(format!("Plane {} had been ignored, and was dimmed on the radar.", plane.callsign));
+        }
+        if let Some(command) = &plane.command {
+            findings.push(format!("Plane {} was still waiting to carry out: {}.", plane.callsign, command.text()));
+        }
+    }
+    findings
+}
+
+///Whether the plane stayed at one flight level from `since`, or from when it entered.
+fn held_level_since(history: &PlaneStats, since: Option<u32>) -> bool {
+    let skip = since.map_or(0, |since| since.saturating_sub(history.entered).saturating_sub(history.earlier_altitudes) as usize);
+    history.altitudes.iter().skip(skip).collect::<Vec<_>>().windows(2).all(|pair| pair[0] == pair[1])
+}
+
+///What the plane was left without: any command at all, or any after `since`.
+fn neglect(history: &PlaneStats, since: Option<u32>) -> Option<String> {
+    let callsign = history.callsign;
+    match (history.command_ticks.last(), since) {
+        (None, _) => Some(format!("Plane {callsign} was never given a command after entering at tick {}.", history.entered)),
+        (Some(&last), Some(since)) if last <= since => Some(format!("Plane {callsign} was given no command once the conflict began; its last was at tick {last}.")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(callsign: char, command_ticks: Vec<u32>, altitudes: Vec<u16>) -> PlaneStats {
+        PlaneStats {
+            callsign, is_jet: true, start: format!("E0"), destination: format!("E1"), entered: 0, finished: None, handling_time: 0, par: 0,
+            commands: command_ticks.len() as u32, conflicts: 1, altitudes, earlier_altitudes: 0, command_ticks,
+        }
+    }
+
+    #[test]
+    fn blames_planes_left_alone_in_a_conflict() {
+        let mut stats = GameStats::default();
+        stats.planes.push(history('a', vec![], vec![5, 5, 5, 5]));
+        stats.planes.push(history('b', vec![1, 3], vec![4, 5, 5, 5]));
+        stats.conflict_began(2, 'a', 'b');
+        let findings = diagnose(GameStatus::PlanesCrashed('b', 'a'), 4, &stats, &[]);
+        assert_eq!(findings, vec![
+            format!("Planes b and a came into conflict at tick 2, 2 ticks before."),
+            format!("Both stayed at 5000ft; a climb or descent for either would have kept them apart."),
+            format!("Plane a was never given a command after entering at tick 0."),
+        ]);
+    }
+}
@@ -1,6 +1,8 @@
 use std::fmt::Display;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::{glyphs::glyphs, location::GroundLocation};
 
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum CircleDirection {
@@ -9,7 +11,7 @@ pub enum CircleDirection {
     CounterClockwise
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum CardinalDirection {
     #[serde(alias = "n")]
@@ -42,16 +44,11 @@ pub enum CardinalDirection {
     }
 } impl Display for CardinalDirection {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", match self {
-            CardinalDirection::North => "^",
-            CardinalDirection::South => "v",
-            CardinalDirection::East  => ">",
-            CardinalDirection::West  => "<",
-        })
+        write!(f, "{}", glyphs().direction((*self).into()))
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum OrdinalDirection {
     #[serde(alias = "n")]
@@ -71,6 +68,11 @@ pub enum OrdinalDirection {
     #[serde(alias = "sw")]
     SouthWest,
 } impl OrdinalDirection {
+    ///Every direction, clockwise from north.
+    pub const CLOCKWISE: [OrdinalDirection; 8] = [
+        OrdinalDirection::North, OrdinalDirection::NorthEast, OrdinalDirection::East, OrdinalDirection::SouthEast,
+        OrdinalDirection::South, OrdinalDirection::SouthWest, OrdinalDirection::West, OrdinalDirection::NorthWest,
+    ];
     pub fn as_offset(&self) -> (i16, i16) {
         match self {
             OrdinalDirection::North => ( 0, -1),
@@ -83,120 +85,124 @@ pub enum OrdinalDirection {
             OrdinalDirection::SouthWest => (-1,  1),
         }
     }
+    ///The direction to fly from one location to reach another, if they share a row, column, or
+    ///diagonal.
+    pub fn between(GroundLocation(from_x, from_y): GroundLocation, GroundLocation(to_x, to_y): GroundLocation) -> Option<OrdinalDirection> {
+        let (dx, dy) = (to_x as i16 - from_x as i16, to_y as i16 - from_y as i16);
+        if (dx, dy) == (0, 0) || (dx != 0 && dy != 0 && dx.abs() != dy.abs()) {
+            return None;
+        }
+        OrdinalDirection::CLOCKWISE.into_iter().find(|d| d.as_offset() == (dx.signum(), dy.signum()))
+    }
+    ///Position clockwise from north, in steps of 45 degrees.
+    fn index(&self) -> i16 {
+        OrdinalDirection::CLOCKWISE.iter().position(|d| d == self).expect("every direction to be listed") as i16
+    }
+    ///The direction this many 45 degree steps clockwise, or counterclockwise if negative.
+    pub fn rotated(&self, steps: i16) -> OrdinalDirection {
+        OrdinalDirection::CLOCKWISE[(self.index() + steps).rem_euclid(OrdinalDirection::CLOCKWISE.len() as i16) as usize]
+    }
+    ///Number of 45 degree steps to turn from this direction to another, positive clockwise and
+    ///negative counterclockwise, taking the shorter way. Opposite directions are reached clockwise.
+    pub fn turn_steps_between(&self, target: OrdinalDirection) -> i16 {
+        let count = OrdinalDirection::CLOCKWISE.len() as i16;
+        let steps = (target.index() - self.index()).rem_euclid(count);
+        if steps > count / 2 { steps - count } else { steps }
+    }
+    ///Turns toward the target, by at most 90 degrees. Turns of more than 90 degrees overshoot on
+    ///the following tick, and opposite directions are reached clockwise.
     pub fn rotate_toward(self, target: OrdinalDirection) -> OrdinalDirection {
-        use OrdinalDirection::*;
-        match (self, target) { //Yes. I just wrote 64 lines of truth table.
-            //Valid cases
-            (North, West)          => West,
-            (North, NorthWest)     => NorthWest,
-            (North, North)         => North,
-            (North, NorthEast)     => NorthEast,
-            (North, East)          => East,
-
-            (NorthEast, NorthWest) => NorthWest,
-            (NorthEast, North)     => North,
-            (NorthEast, NorthEast) => NorthEast,
-            (NorthEast, East)      => East,
-            (NorthEast, SouthEast) => SouthEast,
-
-            (East, North)          => North,
-            (East, NorthEast)      => NorthEast,
-            (East, East)           => East,
-            (East, SouthEast)      => SouthEast,
-            (East, South)          => South,
-
-            (SouthEast, NorthEast) => NorthEast,
-            (SouthEast, East)      => East,
-            (SouthEast, SouthEast) => SouthEast,
-            (SouthEast, South)     => South,
-            (SouthEast, SouthWest) => SouthWest,
-
-            (South, East)          => East,
-            (South, SouthEast)     => SouthEast,
-            (South, South)         => South,
-            (South, SouthWest)     => SouthWest,
-            (South, West)          => West,
+        self.rotated(self.turn_steps_between(target).clamp(-2, 2))
+    }
+    pub fn rotated_90(&self, direction: CircleDirection) -> OrdinalDirection {
+        match direction {
+            CircleDirection::Clockwise        => self.rotated(2),
+            CircleDirection::CounterClockwise => self.rotated(-2),
+        }
+    }
+    pub fn to_deg(&self) -> u16 {
+        self.index() as u16 * 45
+    }
+    ///The direction nearest to a heading in degrees clockwise from north. Headings over 360 wrap
+    ///around.
+    pub fn from_deg(degrees: u16) -> OrdinalDirection {
+        //Each direction covers 22.5 degrees either side of it, so no whole number of degrees is a tie.
+        OrdinalDirection::CLOCKWISE[((degrees % 360 + 22) / 45 % 8) as usize]
+    }
+    ///Degrees to turn from this direction to another, positive clockwise and negative
+    ///counterclockwise, taking the shorter way. Opposite directions give 180.
+    #[allow(dead_code)]
+    pub fn angle_to(&self, target: OrdinalDirection) -> i16 {
+        self.turn_steps_between(target) * 45
+    }
+}
 
-            (SouthWest, SouthEast) => SouthEast,
-            (SouthWest, South)     => South,
-            (SouthWest, SouthWest) => SouthWest,
-            (SouthWest, West)      => West,
-            (SouthWest, NorthWest) => NorthWest,
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            (West, South)          => South,
-            (West, SouthWest)      => SouthWest,
-            (West, West)           => West,
-            (West, NorthWest)      => NorthWest,
-            (West, North)          => North,
+    #[test]
+    fn from_deg_rounds_to_nearest_direction() {
+        for direction in OrdinalDirection::CLOCKWISE {
+            assert_eq!(OrdinalDirection::from_deg(direction.to_deg()), direction);
+        }
+        assert_eq!(OrdinalDirection::from_deg(22), OrdinalDirection::North);
+        assert_eq!(OrdinalDirection::from_deg(23), OrdinalDirection::NorthEast);
+        assert_eq!(OrdinalDirection::from_deg(200), OrdinalDirection::South);
+        assert_eq!(OrdinalDirection::from_deg(338), OrdinalDirection::North);
+        assert_eq!(OrdinalDirection::from_deg(450), OrdinalDirection::East);
+    }
 
-            (NorthWest, SouthWest) => SouthWest,
-            (NorthWest, West)      => West,
-            (NorthWest, NorthWest) => NorthWest,
-            (NorthWest, North)     => North,
-            (NorthWest, NorthEast) => NorthEast,
-            
-            //Reflex angles
-            (North, SouthEast)     => East,
-            (North, SouthWest)     => West,
-            (NorthEast, South)     => SouthEast,
-            (NorthEast, West)      => NorthWest,
-            (East, NorthWest)      => North,
-            (East, SouthWest)      => South,
-            (SouthEast, North)     => NorthEast,
-            (SouthEast, West)      => SouthWest,
-            (South, NorthEast)     => East,
-            (South, NorthWest)     => West,
-            (SouthWest, North)     => NorthWest,
-            (SouthWest, East)      => SouthEast,
-            (West, NorthEast)      => North,
-            (West, SouthEast)      => South,
-            (NorthWest, East)      => NorthEast,
-            (NorthWest, South)     => SouthWest,
+    #[test]
+    fn angle_to_takes_the_shorter_way() {
+        assert_eq!(OrdinalDirection::North.angle_to(OrdinalDirection::North), 0);
+        assert_eq!(OrdinalDirection::North.angle_to(OrdinalDirection::East), 90);
+        assert_eq!(OrdinalDirection::North.angle_to(OrdinalDirection::NorthWest), -45);
+        assert_eq!(OrdinalDirection::West.angle_to(OrdinalDirection::NorthEast), 135);
+        assert_eq!(OrdinalDirection::SouthEast.angle_to(OrdinalDirection::NorthWest), 180);
+        assert_eq!(OrdinalDirection::NorthWest.angle_to(OrdinalDirection::SouthEast), 180);
+    }
 
-            //180s (always go CW)
-            (North, South)         => East,
-            (NorthEast, SouthWest) => SouthEast,
-            (East, West)           => South,
-            (SouthEast, NorthWest) => SouthWest,
-            (South, North)         => West,
-            (SouthWest, NorthEast) => NorthWest,
-            (West, East)           => North,
-            (NorthWest, SouthEast) => NorthEast,
+    #[test]
+    fn rotate_toward_turns_at_most_90_degrees() {
+        use OrdinalDirection::{North as N, NorthEast as NE, East as E, SouthEast as SE, South as S, SouthWest as SW, West as W, NorthWest as NW};
+        //From, target, and the direction after one turn.
+        let cases = [
+            (N, W, W), (N, NW, NW), (N, N, N), (N, NE, NE),
+            (N, E, E), (NE, NW, NW), (NE, N, N), (NE, NE, NE),
+            (NE, E, E), (NE, SE, SE), (E, N, N), (E, NE, NE),
+            (E, E, E), (E, SE, SE), (E, S, S), (SE, NE, NE),
+            (SE, E, E), (SE, SE, SE), (SE, S, S), (SE, SW, SW),
+            (S, E, E), (S, SE, SE), (S, S, S), (S, SW, SW),
+            (S, W, W), (SW, SE, SE), (SW, S, S), (SW, SW, SW),
+            (SW, W, W), (SW, NW, NW), (W, S, S), (W, SW, SW),
+            (W, W, W), (W, NW, NW), (W, N, N), (NW, SW, SW),
+            (NW, W, W), (NW, NW, NW), (NW, N, N), (NW, NE, NE),
+            (N, SE, E), (N, SW, W), (NE, S, SE), (NE, W, NW),
+            (E, NW, N), (E, SW, S), (SE, N, NE), (SE, W, SW),
+            (S, NE, E), (S, NW, W), (SW, N, NW), (SW, E, SE),
+            (W, NE, N), (W, SE, S), (NW, E, NE), (NW, S, SW),
+            (N, S, E), (NE, SW, SE), (E, W, S), (SE, NW, SW),
+            (S, N, W), (SW, NE, NW), (W, E, N), (NW, SE, NE),
+        ];
+        for (from, target, expected) in cases {
+            assert_eq!(from.rotate_toward(target), expected, "turning from {from:?} toward {target:?}");
         }
     }
-    pub fn rotated_90(&self, direction: CircleDirection) -> OrdinalDirection {
-        use OrdinalDirection::*;
-        use CircleDirection::*;
-        match (self, direction) {
-            (North,     Clockwise)        => East,
-            (NorthEast, Clockwise)        => SouthEast,
-            (East,      Clockwise)        => South,
-            (SouthEast, Clockwise)        => SouthWest,
-            (South,     Clockwise)        => West,
-            (SouthWest, Clockwise)        => NorthWest,
-            (West,      Clockwise)        => North,
-            (NorthWest, Clockwise)        => NorthEast,
 
-            (North,     CounterClockwise) => West,
-            (NorthWest, CounterClockwise) => SouthWest,
-            (West,      CounterClockwise) => South,
-            (SouthWest, CounterClockwise) => SouthEast,
-            (South,     CounterClockwise) => East,
-            (SouthEast, CounterClockwise) => NorthEast,
-            (East,      CounterClockwise) => North,
-            (NorthEast, CounterClockwise) => NorthWest,
-        }
+    #[test]
+    fn rotated_90() {
+        assert_eq!(OrdinalDirection::North.rotated_90(CircleDirection::Clockwise), OrdinalDirection::East);
+        assert_eq!(OrdinalDirection::NorthWest.rotated_90(CircleDirection::Clockwise), OrdinalDirection::NorthEast);
+        assert_eq!(OrdinalDirection::North.rotated_90(CircleDirection::CounterClockwise), OrdinalDirection::West);
+        assert_eq!(OrdinalDirection::SouthWest.rotated_90(CircleDirection::CounterClockwise), OrdinalDirection::SouthEast);
     }
-    pub fn to_deg(&self) -> u16 {
-        match self {
-            OrdinalDirection::North     => 0,
-            OrdinalDirection::NorthEast => 45,
-            OrdinalDirection::East      => 90,
-            OrdinalDirection::SouthEast => 135,
-            OrdinalDirection::South     => 180,
-            OrdinalDirection::SouthWest => 225,
-            OrdinalDirection::West      => 270,
-            OrdinalDirection::NorthWest => 315,
-        }
+
+    #[test]
+    fn turn_steps_between() {
+        assert_eq!(OrdinalDirection::East.turn_steps_between(OrdinalDirection::East), 0);
+        assert_eq!(OrdinalDirection::East.turn_steps_between(OrdinalDirection::SouthWest), 3);
+        assert_eq!(OrdinalDirection::East.turn_steps_between(OrdinalDirection::NorthWest), -3);
+        assert_eq!(OrdinalDirection::East.turn_steps_between(OrdinalDirection::West), 4);
     }
 }
@@ -0,0 +1,195 @@
+use rand::{seq::IndexedRandom, Rng};
+
+use crate::{command::{Argument, CompleteAt, CompleteCommandSegment, CompletePointOfInterest, CompleteTurn}, direction::{CardinalDirection, OrdinalDirection}, location::{AirLocation, Destination, GroundLocation, Location}, map::{AnnouncedPlane, MapStatic, Situation, MAP_VERSION, PAR_ALLOWANCE}, map_objects::{Airport, Beacon, Exit, GridRenderable}, plane::{Plane, Visibility}};
+
+///Cells across and down the drill map. Crossings happen in its middle.
+const SIZE: u16 = 17;
+const MIDDLE: u16 = SIZE / 2;
+///Points for a round resolved with a single command. Each command beyond it costs one, down to one
+///point for any round resolved at all.
+const ROUND_POINTS: u32 = 5;
+
+///A traffic geometry to practice on, set up again and again on a small map of its own, with each
+///round scored by how few commands resolved it.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Drill {
+    ///Two planes at the same altitude, on tracks which cross in the middle of the map at the same
+    ///moment.
+    Crossing,
+    ///Two planes reaching a beacon at the same moment, bound for the same exit, with a third
+    ///following the first.
+    Merge,
+    ///An arrival which must descend to its airport through the altitude of a plane crossing below
+    ///it.
+    Descent,
+} impl Drill {
+    ///A square map with an exit in the middle of each edge, numbered clockwise from the top, a
+    ///beacon east of the middle, and an airport south of it, landed on heading south.
+    pub fn map(self) -> MapStatic {
+        let last = SIZE - 1;
+        let exits = [
+            (MIDDLE, 0, CardinalDirection::North),
+            (last, MIDDLE, CardinalDirection::East),
+            (MIDDLE, last, CardinalDirection::South),
+            (0, MIDDLE, CardinalDirection::West),
+        ].into_iter().enumerate().map(|(index, (x, y, outward))| Exit {
+            index: index as u16,
+            entry_location: AirLocation(x, y, 5),
+            entry_direction: <CardinalDirection as Into<OrdinalDirection>>::into(outward).rotated(4),
+            exit_location: AirLocation(x, y, 5),
+            exit_direction: outward.into(),
+            exit_flight_levels: Some((1, 9)),
+            weights: None,
+            handoff_interval: None,
+            crossing: None,
+        }).collect::<Vec<_>>();
+        let beacons = vec![Beacon { index: 0, location: GroundLocation(MIDDLE + 4, MIDDLE) }];
+        let airports = vec![Airport { location: GroundLocation(MIDDLE, MIDDLE + 6), launch_direction: CardinalDirection::South, index: 0, weights: None, stands: None }];
+        let is_taken = |location: GroundLocation| beacons.iter().any(|b| b.location == location) || airports.iter().any(|a| a.location == location);
+        let mut path_markers = (1..last).flat_map(|i| [GroundLocation(i, MIDDLE), GroundLocation(MIDDLE, i)])
+            .filter(|location| !is_taken(*location))
+            .collect::<Vec<_>>();
+        //The middle is reached from both directions.
+        path_markers.dedup();
+        MapStatic {
+            version: MAP_VERSION,
+            name: format!("Drill: {}", self.name()),
+            author: format!("generated"),
+            width: SIZE, height: SIZE,
+            description: Some(format!("Practice for {} traffic, one round after another.", self.name())),
+            difficulty: None,
+            tags: vec![format!("drill")],
+            recommended: Default::default(),
+            exits, beacons, airports, path_markers,
+            airways: vec![],
+            procedures: vec![],
+            forbidden_routes: vec![],
+            ceiling: None,
+            radar: None,
+            goal: None,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Drill::Crossing => "crossing",
+            Drill::Merge => "merge",
+            Drill::Descent => "descent",
+        }
+    }
+    ///The planes of a new round on the drill's map, which appear the tick after `tick`. Which way
+    ///they come from and at what altitude changes from round to round.
+    pub fn round(self, map: &MapStatic, tick: u32, rng: &mut impl Rng) -> Situation {
+        let exit = |index: u16| Destination::Exit(map.exits[index as usize]);
+        let level = rng.random_range(3..=7);
+        //Each plane's start, where it is, which way it is heading, its altitude and its destination.
+        let planes: Vec<(Destination, GroundLocation, OrdinalDirection, u16, Destination)> = match self {
+            Drill::Crossing => {
+                //Rotated a quarter turn at a time, so that they can come from any two neighboring sides.
+                let turns = rng.random_range(0..4);
+                let rotate = |mut location: GroundLocation, mut direction: OrdinalDirection, mut index: u16| {
+                    for _ in 0..turns {
+                        location = GroundLocation(SIZE - 1 - location.1, location.0);
+                        direction = direction.rotated(2);
+                        index = (index + 1) % 4;
+                    }
+                    (location, direction, index)
+                };
+                let (west, east, east_exit) = rotate(GroundLocation(MIDDLE - 7, MIDDLE), OrdinalDirection::East, 1);
+                let (south, north, north_exit) = rotate(GroundLocation(MIDDLE, MIDDLE + 7), OrdinalDirection::North, 0);
+                vec![
+                    (exit((east_exit + 2) % 4), west, east, level, exit(east_exit)),
+                    (exit((north_exit + 2) % 4), south, north, level, exit(north_exit)),
+                ]
+            },
+            Drill::Merge => {
+                //The second plane comes in from above or below.
+                let (side, y, direction) = if rng.random_bool(0.5) { (0, 1, OrdinalDirection::SouthEast) } else { (2, SIZE - 2, OrdinalDirection::NorthEast) };
+                vec![
+                    (exit(3), GroundLocation(MIDDLE - 3, MIDDLE), OrdinalDirection::East, level, exit(1)),
+                    (exit(side), GroundLocation(MIDDLE - 3, y), direction, level, exit(1)),
+                    (exit(3), GroundLocation(MIDDLE - 6, MIDDLE), OrdinalDirection::East, level, exit(1)),
+                ]
+            },
+            Drill::Descent => {
+                //The crossing plane comes from the east or the west.
+                let (side, x, direction, finish) = if rng.random_bool(0.5) { (1, MIDDLE + 3, OrdinalDirection::West, 3) } else { (3, MIDDLE - 3, OrdinalDirection::East, 1) };
+                vec![
+                    (exit(0), GroundLocation(MIDDLE, 1), OrdinalDirection::South, 6, Destination::Airport(map.airports[0])),
+                    (exit(side), GroundLocation(x, 4), direction, rng.random_range(2..=3), exit(finish)),
+                ]
+            },
+        };
+        let callsigns = ('a'..='z').collect::<Vec<_>>().choose_multiple(rng, planes.len()).copied().collect::<Vec<_>>();
+        let announced = planes.into_iter().zip(callsigns).enumerate().map(|(i, ((start, GroundLocation(x, y), direction, level, destination), callsign))| {
+            let location = Location::Flight(AirLocation(x, y, level));
+            let finish = destination.location().unwrap_or(GroundLocation(x, y));
+            let mut plane = Plane {
+                location, destination,
+                target_flight_level: level,
+                callsign,
+                is_jet: true,
+                ticks_active: 0,
+                par: GroundLocation(x, y).chebyshev_distance(finish) as u32 + PAR_ALLOWANCE,
+                current_direction: direction,
+                target_direction: direction,
+                show: Visibility::Marked,
+                command: None,
+                cleared_for_takeoff: false,
+                cleared_to_land: false,
+                diverted: false,
+                radio_failure: None,
+                note: String::new(),
+                previous_location: location,
+                warnings: vec![],
+                reported_flight_level: None,
+                trail: Default::default(),
+            };
+            //The plane merging onto the first one's track is already cleared to turn onto it.
+            if self == Drill::Merge && i == 1 {
+                plane.command = Some(CompleteCommandSegment::At(CompleteAt {
+                    tail: Box::new(CompleteCommandSegment::Turn(CompleteTurn::ToHeading(OrdinalDirection::East))),
+                    poi: CompletePointOfInterest::Beacon(Argument::Value(0)),
+                }));
+            }
+            AnnouncedPlane { plane, start, ticks: 1 }
+        }).collect();
+        Situation { map: map.clone(), tick, planes: vec![], announced }
+    }
+    ///Points for a round resolved with this many commands.
+    pub fn points(commands: u32) -> u32 {
+        ROUND_POINTS.saturating_sub(commands.saturating_sub(1)).max(1)
+    }
+}
+
+///How far a game of drills has got.
+#[derive(Debug, Clone, Copy)]
+pub struct DrillProgress {
+    pub drill: Drill,
+    ///Rounds started, counting the one being played.
+    pub round: u32,
+    ///The tick the round was set up on.
+    pub start: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn rounds_fit_their_map() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for drill in [Drill::Crossing, Drill::Merge, Drill::Descent] {
+            let map = drill.map();
+            assert_eq!(map.problems(), Vec::<String>::new());
+            for _ in 0..8 {
+                for announced in drill.round(&map, 0, &mut rng).announced {
+                    let Location::Flight(location) = announced.plane.location else { panic!("drill planes to be in the air") };
+                    assert!(GroundLocation::from(location).is_inside(&map) && !GroundLocation::from(location).is_on_edge(&map));
+                }
+            }
+        }
+        assert_eq!((Drill::points(1), Drill::points(3), Drill::points(9)), (5, 3, 1));
+    }
+}
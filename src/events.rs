@@ -0,0 +1,30 @@
+use std::{io::Read, path::PathBuf, thread};
+
+use anyhow::Result;
+use crossbeam_channel::Sender;
+
+use crate::{input::{Input, InputReader}, map::MapStatic};
+
+///Something the game loop wakes up for, sent to it from another thread.
+pub enum Event {
+    ///Keys were pressed.
+    Input(Vec<Input>),
+    ///A watched map file changed, and was read again.
+    MapChanged(PathBuf, Box<Result<MapStatic>>),
+    ///The leaderboard server answered, with these lines for the message log.
+    #[cfg(feature = "leaderboard")]
+    Leaderboard(Vec<Vec<crate::styled::Span>>),
+}
+
+///Reads key presses from another thread, sending them as they come.
+pub fn spawn_input(source: impl Read + Send + 'static, events: Sender<Event>) {
+    thread::spawn(move || {
+        let mut reader = InputReader::new(source);
+        while let Some(inputs) = reader.read() {
+            //Sending fails once the game loop has finished, when input is no longer needed.
+            if !inputs.is_empty() && events.send(Event::Input(inputs)).is_err() {
+                break;
+            }
+        }
+    });
+}
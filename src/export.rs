@@ -0,0 +1,316 @@
+#[cfg(feature = "snapshot")]
+use std::collections::HashMap;
+
+#[cfg(feature = "snapshot")]
+use crate::bitmap_font;
+use serde::{Deserialize, Serialize};
+
+use crate::styled::{Cell, CellGrid, Style};
+
+///Color of text drawn in the terminal's default color.
+const FOREGROUND: &str = "#e5e5e5";
+///Color behind text with no background color.
+const BACKGROUND: &str = "#000000";
+///The 16 standard and bright colors, as xterm draws them.
+const STANDARD_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+///Size in pixels of a character in SVG frames.
+const SVG_CELL: (f32, f32) = (8.4, 17.0);
+const SVG_FONT_SIZE: f32 = 14.0;
+///How many pixels wide and tall each pixel of the bitmap font is drawn in GIF images.
+#[cfg(feature = "snapshot")]
+const GIF_SCALE: usize = 2;
+///Largest code GIF's compression can use.
+#[cfg(feature = "snapshot")]
+const MAX_LZW_CODE: u16 = 4095;
+
+///A file format the board can be exported as, colors and all.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+pub enum FrameFormat {
+    ///A standalone SVG image.
+    Svg,
+    ///A standalone web page.
+    Html,
+} impl FrameFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Svg => "svg",
+            FrameFormat::Html => "html",
+        }
+    }
+    ///The grid as a file in this format, titled `title`.
+    pub fn export(self, grid: &CellGrid, title: &str) -> String {
+        match self {
+            FrameFormat::Svg => to_svg(grid, title),
+            FrameFormat::Html => to_html(grid, title),
+        }
+    }
+}
+
+///A color of the 256-color palette as a CSS color.
+fn palette(index: u8) -> String {
+    match index {
+        0..=15 => STANDARD_COLORS[index as usize].to_string(),
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            format!("#{:02x}{:02x}{:02x}", level(index / 36), level(index / 6 % 6), level(index % 6))
+        },
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            format!("#{gray:02x}{gray:02x}{gray:02x}")
+        },
+    }
+}
+
+///The text and background colors a style is drawn in, swapped if it is in reverse video. The
+///background is None where it is left as the page's.
+fn colors(style: Style) -> (String, Option<String>) {
+    let (fg, bg) = (style.fg.map(palette), style.bg.map(palette));
+    if style.reverse {
+        (bg.unwrap_or(BACKGROUND.to_string()), Some(fg.unwrap_or(FOREGROUND.to_string())))
+    } else {
+        (fg.unwrap_or(FOREGROUND.to_string()), bg)
+    }
+}
+
+///A color of the 256-color palette as red, green and blue.
+#[cfg(feature = "snapshot")]
+fn rgb(index: u8) -> [u8; 3] {
+    let color = palette(index);
+    [1, 3, 5].map(|start| u8::from_str_radix(&color[start..start + 2], 16).unwrap_or(0))
+}
+
+///The palette indices a style's text and background are drawn in. The default colors are the
+///standard white and black, as in the other formats, and dim text is the palette color closest to
+///half as bright.
+#[cfg(feature = "snapshot")]
+fn color_indices(style: Style) -> (u8, u8) {
+    let (mut fg, mut bg) = (style.fg.unwrap_or(7), style.bg.unwrap_or(0));
+    if style.reverse {
+        (fg, bg) = (bg, fg);
+    }
+    if style.dim {
+        let dimmed = rgb(fg).map(|channel| channel as i32 / 2);
+        fg = (0..=255).min_by_key(|&index| rgb(index).iter().zip(dimmed).map(|(&channel, dimmed)| (channel as i32 - dimmed).pow(2)).sum::<i32>()).unwrap_or(fg);
+    }
+    (fg, bg)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+///Splits a row into runs of cells drawn in the same style, with the column each starts at.
+fn runs(row: &[Cell]) -> Vec<(usize, Style, String)> {
+    let mut runs: Vec<(usize, Style, String)> = vec![];
+    for (column, cell) in row.iter().enumerate() {
+        match runs.last_mut() {
+            Some((_, style, text)) if *style == cell.style => text.push(cell.glyph),
+            _ => runs.push((column, cell.style, cell.glyph.to_string())),
+        }
+    }
+    runs
+}
+
+fn to_html(grid: &CellGrid, title: &str) -> String {
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n", escape(title));
+    html += &format!("<style>body {{ background: {BACKGROUND}; }} pre {{ color: {FOREGROUND}; font-family: monospace; line-height: 1.2; }}</style>\n");
+    html += "</head>\n<body>\n<pre>";
+    for row in grid.rows() {
+        for (_, style, text) in runs(row) {
+            let (fg, bg) = colors(style);
+            let mut css = format!("color: {fg};");
+            if let Some(bg) = bg {
+                css += &format!(" background: {bg};");
+            }
+            for (set, property) in [(style.bold, " font-weight: bold;"), (style.dim, " opacity: 0.5;"), (style.italic, " font-style: italic;"), (style.underline, " text-decoration: underline;")] {
+                if set {
+                    css += property;
+                }
+            }
+            html += &format!("<span style=\"{css}\">{}</span>", escape(&text));
+        }
+        html.push('\n');
+    }
+    html += "</pre>\n</body>\n</html>\n";
+    html
+}
+
+fn to_svg(grid: &CellGrid, title: &str) -> String {
+    let (cell_width, cell_height) = SVG_CELL;
+    let (width, height) = (grid.width as f32 * cell_width, grid.rows().count() as f32 * cell_height);
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width:.1}\" height=\"{height}\" viewBox=\"0 0 {width:.1} {height}\">\n");
+    svg += &format!("<title>{}</title>\n", escape(title));
+    svg += &format!("<rect width=\"100%\" height=\"100%\" fill=\"{BACKGROUND}\"/>\n");
+    svg += &format!("<g font-family=\"monospace\" font-size=\"{SVG_FONT_SIZE}\" xml:space=\"preserve\">\n");
+    for (y, row) in grid.rows().enumerate() {
+        let top = y as f32 * cell_height;
+        let runs = runs(row);
+        //Backgrounds go beneath all of the row's text.
+        for (column, style, text) in &runs {
+            if let (_, Some(bg)) = colors(*style) {
+                svg += &format!("<rect x=\"{:.1}\" y=\"{top}\" width=\"{:.1}\" height=\"{cell_height}\" fill=\"{bg}\"/>\n", *column as f32 * cell_width, text.chars().count() as f32 * cell_width);
+            }
+        }
+        svg += &format!("<text y=\"{:.1}\">", top + cell_height * 0.8);
+        for (column, style, text) in runs {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let (fg, _) = colors(style);
+            svg += &format!("<tspan x=\"{:.1}\" fill=\"{fg}\"", column as f32 * cell_width);
+            for (set, attribute) in [(style.bold, " font-weight=\"bold\""), (style.dim, " fill-opacity=\"0.5\""), (style.italic, " font-style=\"italic\""), (style.underline, " text-decoration=\"underline\"")] {
+                if set {
+                    svg += attribute;
+                }
+            }
+            svg += &format!(">{}</tspan>", escape(&text));
+        }
+        svg += "</text>\n";
+    }
+    svg += "</g>\n</svg>\n";
+    svg
+}
+
+///The grid as a GIF image, drawn with a bitmap font in the terminal's colors.
+#[cfg(feature = "snapshot")]
+pub fn to_gif(grid: &CellGrid) -> Vec<u8> {
+    let (cell_width, cell_height) = bitmap_font::CELL_SIZE;
+    let (width, height) = (grid.width * cell_width, grid.rows().count() * cell_height);
+    let mut pixels = vec![0; width * height];
+    for (y, row) in grid.rows().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let (fg, bg) = color_indices(cell.style);
+            let origin = y * cell_height * width + x * cell_width;
+            for row in 0..cell_height {
+                pixels[origin + row * width..origin + row * width + cell_width].fill(bg);
+            }
+            bitmap_font::draw(cell.glyph, |column, row| {
+                pixels[origin + row * width + column] = fg;
+                //Bold text is drawn twice, a pixel apart.
+                if cell.style.bold && column + 1 < cell_width {
+                    pixels[origin + row * width + column + 1] = fg;
+                }
+            });
+            if cell.style.underline {
+                let start = origin + bitmap_font::UNDERLINE_ROW * width;
+                pixels[start..start + cell_width].fill(fg);
+            }
+        }
+    }
+    let pixels = pixels.chunks(width).flat_map(|row| {
+        let row = row.iter().flat_map(|&pixel| [pixel; GIF_SCALE]).collect::<Vec<_>>();
+        vec![row; GIF_SCALE]
+    }).flatten().collect::<Vec<_>>();
+    let (width, height) = (width * GIF_SCALE, height * GIF_SCALE);
+
+    let mut gif = b"GIF89a".to_vec();
+    //The screen, with a global color table of all 256 colors.
+    gif.extend((width as u16).to_le_bytes());
+    gif.extend((height as u16).to_le_bytes());
+    gif.extend([0xf7, 0, 0]);
+    gif.extend((0..=255).flat_map(rgb));
+    //The one image, covering the screen.
+    gif.push(0x2c);
+    gif.extend([0, 0, 0, 0]);
+    gif.extend((width as u16).to_le_bytes());
+    gif.extend((height as u16).to_le_bytes());
+    gif.push(0);
+    gif.push(8);
+    for block in lzw(&pixels).chunks(255) {
+        gif.push(block.len() as u8);
+        gif.extend(block);
+    }
+    gif.push(0);
+    gif.push(0x3b);
+    gif
+}
+
+///Compresses 8-bit color indices the way GIF images are, with variable-length codes.
+#[cfg(feature = "snapshot")]
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 256;
+    const END: u16 = 257;
+    let mut bytes = vec![];
+    let (mut buffer, mut buffered) = (0u32, 0);
+    let mut write = |code: u16, size: u32| {
+        buffer |= (code as u32) << buffered;
+        buffered += size;
+        while buffered >= 8 {
+            bytes.push(buffer as u8);
+            buffer >>= 8;
+            buffered -= 8;
+        }
+    };
+    let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+    let (mut next, mut size) = (END + 1, 9);
+    write(CLEAR, size);
+    let Some((&first, rest)) = indices.split_first() else {
+        write(END, size);
+        write(0, 7);
+        return bytes;
+    };
+    let mut current = first as u16;
+    for &index in rest {
+        if let Some(&code) = codes.get(&(current, index)) {
+            current = code;
+            continue;
+        }
+        write(current, size);
+        if next >= 1 << size && size < 12 {
+            size += 1;
+        }
+        //Once the table is full, it starts over rather than going on with codes it has.
+        if next >= MAX_LZW_CODE {
+            write(CLEAR, size);
+            codes.clear();
+            (next, size) = (END + 1, 9);
+        } else {
+            codes.insert((current, index), next);
+            next += 1;
+        }
+        current = index as u16;
+    }
+    write(current, size);
+    if next >= 1 << size && size < 12 {
+        size += 1;
+    }
+    write(END, size);
+    //Pads out the last byte.
+    write(0, 7);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::styled::Span;
+
+    #[test]
+    fn keeps_colors() {
+        let lines = [vec![Span::new("*1", Style::colored(3)), Span::new("<", Style::default()), Span::new("E", Style { reverse: true, ..Style::default() })]];
+        let grid = CellGrid::from_lines(&lines);
+        let html = FrameFormat::Html.export(&grid, "Crossing");
+        assert!(html.contains("<span style=\"color: #cdcd00;\">*1</span><span style=\"color: #e5e5e5;\">&lt;</span>"));
+        assert!(html.contains("<span style=\"color: #000000; background: #e5e5e5;\">E</span>"));
+        let svg = FrameFormat::Svg.export(&grid, "Crossing");
+        assert!(svg.contains("<tspan x=\"0.0\" fill=\"#cdcd00\">*1</tspan>"));
+        assert!(svg.contains("fill=\"#e5e5e5\"/>"));
+        assert_eq!(palette(196), "#ff0000");
+        assert_eq!(palette(244), "#808080");
+    }
+
+    #[cfg(feature = "snapshot")]
+    #[test]
+    fn gif_fits_the_grid() {
+        let grid = CellGrid::from_lines(&[vec![Span::new("E1", Style { bold: true, ..Style::default() }), Span::new(" ┼", Style::default())]]);
+        let gif = to_gif(&grid);
+        let (cell_width, cell_height) = bitmap_font::CELL_SIZE;
+        assert!(gif.starts_with(b"GIF89a"));
+        assert_eq!(gif[6..10], [(4 * cell_width * GIF_SCALE) as u8, 0, (cell_height * GIF_SCALE) as u8, 0]);
+        assert_eq!(gif.last(), Some(&0x3b));
+    }
+}
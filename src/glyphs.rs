@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::direction::OrdinalDirection;
+
+///Which characters the game is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum GlyphSet {
+    ///Plain ASCII, for limited terminals.
+    Ascii,
+    ///Box-drawing characters and arrows.
+    Unicode,
+}
+
+#[derive(Debug)]
+pub struct GlyphTable {
+    pub blank: &'static str,
+    pub path_marker: &'static str,
+    pub beacon: &'static str,
+    ///Where planes crashed, in casual mode.
+    pub crash_site: &'static str,
+    ///Planes beyond radar range.
+    pub primary_target: &'static str,
+    ///Where planes have been, when trails are shown.
+    pub trail: &'static str,
+    ///The range and bearing line.
+    pub measurement: &'static str,
+    ///Datablock markers for planes climbing, descending, and turning each way.
+    pub climb: &'static str,
+    pub descend: &'static str,
+    pub turn_clockwise: &'static str,
+    pub turn_counterclockwise: &'static str,
+    ///Leader lines to datablocks moved up and right, or down and right.
+    pub leader_rising: &'static str,
+    pub leader_falling: &'static str,
+    ///Clockwise from north.
+    pub directions: [&'static str; 8],
+    pub horizontal: &'static str,
+    pub vertical: &'static str,
+    pub top_left: &'static str,
+    pub top_right: &'static str,
+    pub bottom_left: &'static str,
+    pub bottom_right: &'static str,
+    pub arrow: &'static str,
+    ///Crossing restrictions on exits.
+    pub at_or_above: &'static str,
+    pub at_or_below: &'static str,
+    ///Minimap areas with one plane, two, and so on, the last for that many or more.
+    pub density: &'static [&'static str],
+}
+
+const ASCII: GlyphTable = GlyphTable {
+    blank: ".",
+    path_marker: "+",
+    beacon: "*",
+    crash_site: "X",
+    primary_target: "o",
+    trail: ":",
+    measurement: "#",
+    climb: "^",
+    descend: "v",
+    turn_clockwise: ")",
+    turn_counterclockwise: "(",
+    leader_rising: "/",
+    leader_falling: "\\",
+    directions: ["^", "/", ">", "\\", "v", "/", "<", "\\"],
+    horizontal: "-",
+    vertical: "|",
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    arrow: "->",
+    at_or_above: ">=",
+    at_or_below: "<=",
+    density: &["1", "2", "3", "4", "5", "6", "7", "8", "9", "+"],
+};
+
+const UNICODE: GlyphTable = GlyphTable {
+    blank: "·",
+    path_marker: "┼",
+    beacon: "✱",
+    crash_site: "✖",
+    primary_target: "◦",
+    trail: "•",
+    measurement: "▪",
+    climb: "↑",
+    descend: "↓",
+    turn_clockwise: "↻",
+    turn_counterclockwise: "↺",
+    leader_rising: "╱",
+    leader_falling: "╲",
+    directions: ["↑", "↗", "→", "↘", "↓", "↙", "←", "↖"],
+    horizontal: "─",
+    vertical: "│",
+    top_left: "┌",
+    top_right: "┐",
+    bottom_left: "└",
+    bottom_right: "┘",
+    arrow: "→",
+    at_or_above: "≥",
+    at_or_below: "≤",
+    //Braille patterns with a dot more for each plane.
+    density: &["⡀", "⡄", "⡆", "⡇", "⣇", "⣧", "⣷", "⣿"],
+};
+
+static USE_UNICODE: AtomicBool = AtomicBool::new(false);
+
+///Selects the glyph set for everything drawn afterward.
+pub fn set_glyphs(set: GlyphSet) {
+    USE_UNICODE.store(set == GlyphSet::Unicode, Ordering::Relaxed);
+}
+
+pub fn glyphs() -> &'static GlyphTable {
+    if USE_UNICODE.load(Ordering::Relaxed) { &UNICODE } else { &ASCII }
+}
+
+impl GlyphTable {
+    ///How an area with `count` planes in it is drawn on the minimap.
+    pub fn density(&self, count: u16) -> &'static str {
+        match count {
+            0 => self.blank,
+            _ => self.density[(count as usize - 1).min(self.density.len() - 1)],
+        }
+    }
+    pub fn direction(&self, direction: OrdinalDirection) -> &'static str {
+        self.directions[match direction {
+            OrdinalDirection::North     => 0,
+            OrdinalDirection::NorthEast => 1,
+            OrdinalDirection::East      => 2,
+            OrdinalDirection::SouthEast => 3,
+            OrdinalDirection::South     => 4,
+            OrdinalDirection::SouthWest => 5,
+            OrdinalDirection::West      => 6,
+            OrdinalDirection::NorthWest => 7,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denser_areas_have_more_dots() {
+        assert_eq!([0, 1, 9, 10, 40].map(|count| ASCII.density(count)), [".", "1", "9", "+", "+"]);
+        let dots = (1..=9).map(|count| UNICODE.density(count).chars().next().map_or(0, |c| (c as u32 - 0x2800).count_ones())).collect::<Vec<_>>();
+        assert_eq!(dots, [1, 2, 3, 4, 5, 6, 7, 8, 8]);
+    }
+}
@@ -0,0 +1,366 @@
+use std::{collections::HashMap, env, fmt::Display, fs, sync::OnceLock};
+
+use anyhow::Result;
+
+use crate::{paths, styled::Span};
+
+///Translations of messages into the chosen locale, by message name. Messages missing from it are
+///shown in English.
+static TRANSLATIONS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+///Declares the messages the game shows, with their English text.
+macro_rules! messages {
+    ($($name:ident => $english:literal,)*) => {
+        ///A message shown to the player, looked up in the chosen locale's translations by its name.
+        ///`{name}` in a message is filled in with the argument of that name.
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum Message {
+            $($name,)*
+        } impl Message {
+            pub const ALL: &'static [Message] = &[$(Message::$name,)*];
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Message::$name => stringify!($name),)*
+                }
+            }
+            pub fn english(self) -> &'static str {
+                match self {
+                    $(Message::$name => $english,)*
+                }
+            }
+        }
+    };
+}
+
+messages! {
+    //Command previews, shown as a command is typed.
+    AltitudePrompt => "altitude:",
+    AltitudeTo => "altitude: {altitude}",
+    AltitudeClimb => "altitude: climb",
+    AltitudeDescend => "altitude: descend",
+    AltitudeClimbTo => "altitude: climb {altitude}",
+    AltitudeDescendTo => "altitude: descend {altitude}",
+    TurnPrompt => "turn",
+    TurnTo => "turn to {heading}",
+    CirclePrompt => "circle",
+    CircleClockwise => "circle clockwise",
+    CircleCounterClockwise => "circle counter-clockwise",
+    Mark => "mark",
+    Unmark => "unmark",
+    Ignore => "ignore",
+    AtAltitudePrompt => "altitude",
+    AtAltitude => "altitude {altitude}",
+    At => "{command} at {point}",
+    OrElsePrompt => "{command} or else in {ticks} ticks",
+    OrElse => "{command} or else in {ticks} ticks {fallback}",
+    In => "{command} in {ticks} ticks",
+    Every => "{command} every {ticks} ticks",
+    DeleteSlot => "delete",
+    EditSlot => "edit",
+    NameSlot => "name: {name}",
+    Cancel => "cancel",
+    Takeoff => "cleared for takeoff",
+    Land => "cleared to land",
+    Note => "note: {note}",
+    Airway => "via V{airway}",
+    Procedure => "cleared P{procedure}",
+    HoldPrompt => "hold outside for",
+    Hold => "hold outside for {ticks}",
+    AllPlanes => "all",
+    ListSlots => "list slots",
+    Query => "query",
+    DivertPrompt => "divert to",
+    Divert => "divert to {destination}",
+
+    //Why a command was rejected.
+    PlaneNotFound => "There is no plane {plane}.",
+    PlaneOnGround => "Plane {plane} cannot turn until it has taken off.",
+    EmptySlot => "Command slot %{slot} is empty.",
+    WrongArgumentCount => "Command slot %{slot} was given the wrong number of arguments.",
+    SlotTooDeep => "Command slot %{slot} refers to slots too many times over, or to itself.",
+    UnfilledPlaceholder => "Placeholders can only be left in command slots.",
+    NoSuchAirway => "There is no airway V{airway}.",
+    NoSuchProcedure => "There is no procedure P{procedure}.",
+    WrongProcedure => "Procedure P{procedure} is not for plane {plane}'s airport.",
+    PendingPlaneNotFound => "No plane {plane} is waiting to enter.",
+    PlaneAirborne => "Plane {plane} has already taken off.",
+    NotOnFinal => "Plane {plane} can only be cleared to land within {cells} cells before its runway.",
+    NoSuchDestination => "There is no {destination} to divert to.",
+
+    //Why the game ended.
+    PlanesCrashed => "Plane {plane} crashed into plane {other}.",
+    PlaneExited => "Plane {plane} exited improperly.",
+    PlaneFailedLanding => "Plane {plane} landed improperly.",
+    PlaneMissedExitLevels => "Plane {plane} left outside its exit's flight levels.",
+    PlaneFoundNoStand => "Plane {plane} landed with no free stand.",
+    PlaneLandedUncleared => "Plane {plane} landed without being cleared to land.",
+    PlanesInConflict => "Planes {plane} and {other} came into conflict.",
+    SituationCleared => "Situation cleared in {ticks} ticks.",
+    CompletedTraffic => "Completed: all {planes} planes handled.",
+    CompletedPlanes => "Completed: {planes} planes reached their destinations.",
+    CompletedTicks => "Completed: survived {ticks} ticks.",
+
+    //What happens to planes, in the log and timeline.
+    Jet => "jet",
+    Prop => "prop",
+    PlaneAnnounced => "{plane} ({kind}) will enter at {start} in {ticks} ticks, destination {destination}.",
+    PlaneEntered => "{plane} ({kind}) entered at {start}, destination {destination}.",
+    PlaneReached => "{plane} reached {destination} after {ticks} ticks.",
+    WithinPar => "{plane} reached {destination} in {ticks} ticks, within par of {par}.",
+    LeftTooSoon => "{plane} left by {exit} too soon after the last plane.",
+    Crashed => "{plane} and {other} crashed.",
+    Conflict => "Conflict: {plane} and {other}.",
+    Diverted => "{plane} diverted to {destination}.",
+    Holding => "{plane} will hold outside, entering in {ticks} ticks.",
+    PlaneRemoved => "Plane {plane} was removed, as it no longer fits the map.",
+    RadioLost => "Plane {plane} has lost its radio, and will not answer for {ticks} ticks.",
+    RadioRestored => "Plane {plane}'s radio is working again.",
+    WaitingForRadio => "{plane} has no radio, so it will be told once it is back.",
+    MisreadHeading => "Plane {plane} misread its instruction, and is turning to {heading} instead.",
+    MisreadAltitude => "Plane {plane} misread its instruction, and is leveling off at {altitude} instead.",
+    AboveCeiling => "Plane {plane} cannot climb above the ceiling, so it will stop at flight level {ceiling}.",
+    BelowGround => "Plane {plane} cannot descend below the ground, so it will descend to flight level 0.",
+    NoResponse => "No response from {plane}. Say again?",
+    Readback => "\"{command}\", {plane}.",
+    Rejected => "(rejected: {reason})",
+    QueryRoute => "{plane}: {destination} is {distance} away, bearing {bearing}, about {ticks} ticks at {altitude}.",
+    QueryRouteHere => "{plane}: {destination} is {distance} away, about {ticks} ticks at {altitude}.",
+    QueryNoConflicts => "{plane}: no conflicts within {ticks} ticks.",
+    QueryConflict => "{plane}: conflict with {other} in {ticks} ticks.",
+    RoundFailed => "Round {round} failed: {status}",
+    RoundResolved => "Round {round} resolved in {ticks} ticks with {commands} commands.",
+    RoundResolvedWithOneCommand => "Round {round} resolved in {ticks} ticks with 1 command.",
+
+    //Measuring range and bearing.
+    Measured => "Measured {measurement}.",
+    Measurement => "range {range}, bearing {bearing} ({direction}), {range} ticks for jets and {prop_ticks} for props",
+    Measuring => "measure: {measurement}",
+    MeasureStart => "move with the arrow keys or a callsign, and press Enter to start the line",
+    MeasureEnd => "move to where the line ends",
+
+    //Above the plane list.
+    SortedByCallsign => "by callsign",
+    SortedByAltitude => "by altitude",
+    SortedByDestination => "by destination",
+    SortedByTimeActive => "by time active",
+    MarkedOnly => "marked only",
+    Goal => "goal",
+    GoalTraffic => "{progress}/{target} planes handled",
+    GoalPlanes => "{progress}/{target} planes",
+    GoalTicks => "{progress}/{target} ticks",
+    FlowWindow => "per {ticks}t",
+    FlowThroughput => "{arrivals} arr {departures} dep",
+    FlowPlanes => "planes",
+    FlowAverage => "avg",
+    FlowHandlingTime => "{ticks}t",
+    FlowAlerts => "alerts",
+    Stands => "stands",
+    Time => "Time: {tick}",
+    Score => "Score: {score}",
+    Rate => "Rate: {seconds}s",
+    Stepping => "STEP",
+    DrillRound => "DRILL {round}",
+    Puzzle => "PUZZLE",
+    SpawnHeld => "SPAWN HELD",
+    Night => "NIGHT",
+    ListHeading => "plane dest cmd",
+    ListRange => "{first}-{last} of {total}",
+    Arrivals => "arrivals",
+    Departures => "departures",
+
+    //Beneath the radar, while a command is entered.
+    TargetWithOnePlane => "{point} is at ({x},{y}), with 1 plane waiting for it",
+    TargetWithPlanes => "{point} is at ({x},{y}), with {planes} planes waiting for it",
+    EntersIn => "in {ticks} ticks",
+    DepartureFrom => "departure from airport {airport}",
+    ArrivalTo => "arrival to airport {airport}",
+
+    //Status messages in the log.
+    DailyChallenge => "Daily challenge for {date}.",
+    CouldNotLoadMacros => "Could not load macros: {error}",
+    CouldNotLoadSlots => "Could not load command slots: {error}",
+    CouldNotSaveSlots => "Could not save command slots: {error}",
+    SlotsSaved => "Command slots saved to {file}.",
+    CouldNotSaveMacros => "Could not save macros: {error}",
+    BoardSaved => "Board saved to {file}.",
+    CouldNotSaveBoard => "Could not save board: {error}",
+    SituationSaved => "Situation saved to {file}.",
+    CouldNotSaveSituation => "Could not save situation: {error}",
+    SituationLoaded => "Clear all {planes} planes without a conflict, as quickly as you can.",
+    FinalBoardSaved => "Final board saved to {file}.",
+    CouldNotSaveFinalBoard => "Could not save the final board: {error}",
+    StatisticsSaved => "Statistics saved to {files}.",
+    CouldNotSaveStatistics => "Could not save statistics: {error}",
+    Reloaded => "Reloaded {file}.",
+    CouldNotReload => "Could not reload {file}: {error}",
+    CouldNotStartGame => "Could not start the game: {error}",
+    SoundUnavailable => "There is no audio output to play sounds through.",
+    SoundMuted => "Sound muted.",
+    SoundUnmuted => "Sound unmuted.",
+    Rewound => "Rewound {ticks} ticks, to tick {tick}. Rewinds left: {left}.",
+    RewoundEvent => "Rewound {ticks} ticks.",
+    RewindCasualOnly => "Only casual games can be rewound.",
+    NoRewindsLeft => "There are no rewinds left.",
+    NothingToRewind => "There is nothing to rewind to yet.",
+    GameResumed => "Resumed at tick {tick}.",
+    CouldNotAutosave => "Could not autosave: {error}",
+    CouldNotSaveScore => "Could not save score: {error}",
+    CouldNotSaveFrame => "Could not save frame: {error}. No more frames will be saved.",
+
+    //The online leaderboard.
+    LeaderboardEmpty => "No scores on the leaderboard yet today.",
+    LeaderboardHeader => "Leaderboard for {date}:",
+    LeaderboardEntry => "{place}. {name} - {score} points in {ticks} ticks",
+    ScoreSubmitted => "Score submitted to the leaderboard.",
+    CouldNotSubmitScore => "Could not submit score: {error}",
+    CouldNotFetchLeaderboard => "Could not fetch the leaderboard: {error}",
+
+    //The timeline.
+    Timeline => "Timeline",
+    NothingHappenedYet => "Nothing has happened yet.",
+    TimelineRange => "{first}-{last} of {total} events.",
+    TimelineHelp => "Arrow keys and Page Up/Down scroll, Ctrl-T closes.",
+}
+
+impl Message {
+    ///The message in the chosen locale, before its placeholders are filled in.
+    fn template(self) -> &'static str {
+        TRANSLATIONS.get().and_then(|translations| translations.get(self.name())).map_or(self.english(), String::as_str)
+    }
+    ///The message in the chosen locale, with its placeholders filled in with `arguments`.
+    pub fn format(self, arguments: &[(&str, &dyn Display)]) -> String {
+        fill(self.template(), arguments)
+    }
+    ///The message in the chosen locale, with its placeholders filled in with styled `arguments`.
+    ///The rest of the message is unstyled.
+    pub fn spans(self, arguments: &[(&str, &[Span])]) -> Vec<Span> {
+        let mut spans = vec![];
+        for part in parts(self.template(), arguments) {
+            match part {
+                Part::Text(text) => spans.push(Span::plain(text)),
+                Part::Argument(argument) => spans.extend_from_slice(argument),
+            }
+        }
+        spans
+    }
+}
+
+///Shows a message in the chosen locale, such as `tr!(PlaneNotFound, plane = callsign)`.
+macro_rules! tr {
+    ($message:ident $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::Message::$message.format(&[$((stringify!($name), &$value as &dyn std::fmt::Display)),*])
+    };
+}
+pub(crate) use tr;
+
+///Shows a message in the chosen locale with styled arguments, such as
+///`tr_spans!(In, command = spans, ticks = [Span::new("#5", style)])`.
+macro_rules! tr_spans {
+    ($message:ident $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::Message::$message.spans(&[$((stringify!($name), &$value[..])),*])
+    };
+}
+pub(crate) use tr_spans;
+
+///A piece of a message: either its own text, or the argument for one of its placeholders.
+enum Part<'a, T: ?Sized> {
+    Text(&'a str),
+    Argument(&'a T),
+}
+
+///Splits the template at each `{name}` which has an argument of that name. Placeholders without an
+///argument are left in the text.
+fn parts<'a, T: ?Sized>(template: &'a str, arguments: &'a [(&str, &T)]) -> Vec<Part<'a, T>> {
+    let mut parts = vec![];
+    let mut rest = template;
+    let mut text_start = 0;
+    while let Some(start) = rest[text_start..].find('{').map(|start| text_start + start) {
+        let placeholder = &rest[start..];
+        let argument = placeholder.find('}').and_then(|end| {
+            arguments.iter().find(|(name, _)| *name == &placeholder[1..end]).map(|(_, value)| (end, *value))
+        });
+        match argument {
+            Some((end, value)) => {
+                if start > 0 {
+                    parts.push(Part::Text(&rest[..start]));
+                }
+                parts.push(Part::Argument(value));
+                rest = &placeholder[end + 1..];
+                text_start = 0;
+            },
+            None => text_start = start + 1,
+        }
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    parts
+}
+
+///Replaces each `{name}` in the template with the argument of that name. Placeholders without an
+///argument are left as they are.
+fn fill(template: &str, arguments: &[(&str, &dyn Display)]) -> String {
+    parts(template, arguments).into_iter().map(|part| match part {
+        Part::Text(text) => text.to_string(),
+        Part::Argument(value) => value.to_string(),
+    }).collect()
+}
+
+///The locales to look for translations in, most specific first: `de_AT.UTF-8` is looked for as
+///`de_AT`, then `de`.
+fn candidates(locale: &str) -> Vec<String> {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    let mut candidates = vec![locale.to_string()];
+    if let Some((language, _)) = locale.split_once(['_', '-']) {
+        candidates.push(language.to_string());
+    }
+    candidates
+}
+
+///Chooses the locale messages are shown in: `locale`, or else the one set by the `LC_ALL`,
+///`LC_MESSAGES` or `LANG` environment variable. Translations are read from `locales/<locale>.json`
+///in the config directory, which maps message names (listed by `atc messages`) to their text. A
+///locale without translations is shown in English.
+pub fn set_locale(locale: Option<&str>) -> Result<()> {
+    let locale = locale.map(str::to_string).or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].into_iter().find_map(|variable| env::var(variable).ok().filter(|value| !value.is_empty()))
+    });
+    let (Some(locale), Some(dir)) = (locale, paths::config_dir()) else { return Ok(()) };
+    for candidate in candidates(&locale) {
+        let Ok(text) = fs::read(dir.join("locales").join(format!("{candidate}.json"))) else { continue };
+        let translations = serde_json::de::from_slice(&text)?;
+        let _ = TRANSLATIONS.set(translations);
+        break;
+    }
+    Ok(())
+}
+
+///Every message in English, in the order they are declared, as a starting point for a translation.
+pub fn catalog() -> Result<String> {
+    let mut lines = vec![];
+    for message in Message::ALL {
+        lines.push(format!("    {}: {}", serde_json::to_string(message.name())?, serde_json::to_string(message.english())?));
+    }
+    Ok(format!("{{\n{}\n}}", lines.join(",\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::styled::Style;
+
+    #[test]
+    fn fills_in_placeholders() {
+        assert_eq!(fill("Plane {plane} crashed into plane {other}.", &[("plane", &'a'), ("other", &'b')]), "Plane a crashed into plane b.");
+        assert_eq!(fill("{command} in {ticks} ticks", &[("command", &"turn to 90")]), "turn to 90 in {ticks} ticks");
+        assert_eq!(fill("{ {plane}", &[("plane", &'c')]), "{ c");
+        assert_eq!(candidates("de_AT.UTF-8"), vec![format!("de_AT"), format!("de")]);
+    }
+
+    #[test]
+    fn fills_in_styled_placeholders() {
+        let ticks = [Span::new("#5", Style::colored(6))];
+        let spans = Message::In.spans(&[("command", &[Span::plain("turn to 90")]), ("ticks", &ticks)]);
+        assert_eq!(spans, vec![Span::plain("turn to 90"), Span::plain(" in "), ticks[0].clone(), Span::plain(" ticks")]);
+    }
+}
@@ -0,0 +1,61 @@
+use std::io::Read;
+
+use termion::{event::{Event, Key}, input::TermRead};
+
+///A key press, as the game understands it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    ///A character for the command input or a shortcut. Control keys are given as control
+    ///characters, such as `'\x03'` for Ctrl-C, and Backspace as `'\x7f'`.
+    Char(char),
+    ///Escape on its own, which cancels what is being entered.
+    Escape,
+    ///An arrow key, as a step in each direction.
+    Arrow(i16, i16),
+    ///Page Up (-1) or Page Down (1).
+    Scroll(isize),
+}
+
+///Reads key presses from the terminal as whole events, so that escape sequences and characters
+///longer than a byte are never typed into the command a byte at a time.
+pub struct InputReader<R> {
+    events: termion::input::Events<R>,
+} impl<R: Read> InputReader<R> {
+    pub fn new(source: R) -> Self {
+        InputReader { events: source.events() }
+    }
+    ///Everything the next event means, waiting for one if the source blocks, or None once the
+    ///source has ended. Keys the game has no use for, and sequences the terminal sent which could
+    ///not be read, mean nothing.
+    pub fn read(&mut self) -> Option<Vec<Input>> {
+        let Ok(event) = self.events.next()? else { return Some(vec![]) };
+        let Event::Key(key) = event else { return Some(vec![]) };
+        Some(match key {
+            Key::Char(c) => vec![Input::Char(c)],
+            Key::Backspace => vec![Input::Char('\x7f')],
+            Key::Ctrl(c) if c.is_ascii_lowercase() => vec![Input::Char((c as u8 - b'a' + 1) as char)],
+            Key::Esc => vec![Input::Escape],
+            //Escape pressed just before another key arrives along with it.
+            Key::Alt(c) => vec![Input::Escape, Input::Char(c)],
+            Key::Up => vec![Input::Arrow(0, -1)],
+            Key::Down => vec![Input::Arrow(0, 1)],
+            Key::Right => vec![Input::Arrow(1, 0)],
+            Key::Left => vec![Input::Arrow(-1, 0)],
+            Key::PageUp => vec![Input::Scroll(-1)],
+            Key::PageDown => vec![Input::Scroll(1)],
+            _ => vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_sequences_instead_of_typing_them() {
+        let mut reader = InputReader::new(&b"a\x1b[A\xc3\xa9\x1b[24;5~\x03\x7fb"[..]);
+        let inputs = std::iter::from_fn(|| reader.read()).flatten().collect::<Vec<_>>();
+        assert_eq!(inputs, vec![Input::Char('a'), Input::Arrow(0, -1), Input::Char('é'), Input::Char('\x03'), Input::Char('\x7f'), Input::Char('b')]);
+    }
+}
@@ -0,0 +1,134 @@
+use std::{fs, thread::{self, JoinHandle}, time::Duration};
+
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+
+use crate::{daily::Daily, events::Event, i18n::tr, map::Map, paths, styled::{Span, Style}};
+
+///How long to wait for the server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+///Most entries of the top list shown in the message log.
+const SHOWN_ENTRIES: usize = 5;
+
+///Where to submit scores, and under what name. Read from `leaderboard.json` in the config
+///directory; without it, nothing is sent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaderboardConfig {
+    ///Base URL of the server, without a trailing `/`.
+    pub endpoint: String,
+    ///Name shown beside your scores.
+    pub name: String,
+} impl LeaderboardConfig {
+    ///Reads the configuration, if the leaderboard has been set up.
+    pub fn load() -> Option<Self> {
+        let file = paths::config_dir()?.join("leaderboard.json");
+        let text = fs::read(file).ok()?;
+        serde_json::de::from_slice(&text).ok()
+    }
+    fn daily_url(&self, date: &str) -> String {
+        format!("{}/daily/{date}", self.endpoint.trim_end_matches('/'))
+    }
+}
+
+///A score sent to the server at the end of a daily challenge, with `POST <endpoint>/daily/<date>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Submission {
+    pub name: String,
+    pub date: String,
+    pub score: u32,
+    pub ticks: u32,
+    ///The shared summary, with a square for each plane.
+    pub summary: String,
+}
+
+///One line of the top list. `GET <endpoint>/daily/<date>` returns a list of these, best first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub score: u32,
+    pub ticks: u32,
+}
+
+///An HTTP client which gives up on the server after `TIMEOUT`. Error statuses are returned as
+///errors.
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder().timeout_global(Some(TIMEOUT)).build().into()
+}
+
+pub fn submit(config: &LeaderboardConfig, submission: &Submission) -> Result<()> {
+    let body = serde_json::ser::to_vec(submission)?;
+    agent().post(config.daily_url(&submission.date)).header("Content-Type", "application/json").send(&body[..])?;
+    Ok(())
+}
+
+pub fn top_list(config: &LeaderboardConfig, date: &str) -> Result<Vec<Entry>> {
+    let output = agent().get(config.daily_url(date)).call()?.body_mut().read_to_vec()?;
+    Ok(serde_json::de::from_slice(&output)?)
+}
+
+///The best scores of the day's challenge as lines for the message log, or why they could not be
+///fetched.
+fn top_list_lines(config: &LeaderboardConfig, date: &str) -> Vec<Vec<Span>> {
+    match top_list(config, date) {
+        Ok(entries) if entries.is_empty() => vec![vec![Span::plain(tr!(LeaderboardEmpty))]],
+        Ok(entries) => {
+            let mut lines = vec![vec![Span::plain(tr!(LeaderboardHeader, date = date))]];
+            for (place, entry) in entries.iter().take(SHOWN_ENTRIES).enumerate() {
+                lines.push(vec![Span::plain(tr!(LeaderboardEntry, place = place + 1, name = entry.name, score = entry.score, ticks = entry.ticks))]);
+            }
+            lines
+        },
+        Err(e) => vec![vec![Span::new(tr!(CouldNotFetchLeaderboard, error = e), Style::colored(1))]],
+    }
+}
+
+///Talks to the server on other threads, so that the game carries on while it waits for an answer.
+///What the server says is sent back to the game loop as lines for the message log.
+pub struct Client {
+    config: LeaderboardConfig,
+    events: Sender<Event>,
+    workers: Vec<JoinHandle<()>>,
+} impl Client {
+    ///A client for the server, if the leaderboard has been set up.
+    pub fn new(events: &Sender<Event>) -> Option<Self> {
+        Some(Client { config: LeaderboardConfig::load()?, events: events.clone(), workers: vec![] })
+    }
+    fn spawn(&mut self, work: impl FnOnce(&LeaderboardConfig) -> Vec<Vec<Span>> + Send + 'static) {
+        let (config, events) = (self.config.clone(), self.events.clone());
+        self.workers.retain(|worker| !worker.is_finished());
+        self.workers.push(thread::spawn(move || {
+            //Sending fails once the game loop has finished, when there is no log to show it in.
+            let _ = events.send(Event::Leaderboard(work(&config)));
+        }));
+    }
+    ///Fetches the best scores of the day's challenge, to be logged.
+    pub fn show_top_list(&mut self, daily: &Daily) {
+        let date = daily.date.clone();
+        self.spawn(move |config| top_list_lines(config, &date));
+    }
+    ///Submits the result of a daily challenge, then fetches the updated top list.
+    pub fn submit_daily(&mut self, daily: &Daily, map: &Map) {
+        let submission = Submission {
+            name: self.config.name.clone(),
+            date: daily.date.clone(),
+            score: map.score(),
+            ticks: map.tick_no(),
+            summary: daily.summary(map),
+        };
+        self.spawn(move |config| match submit(config, &submission) {
+            Ok(()) => {
+                let mut lines = vec![vec![Span::plain(tr!(ScoreSubmitted))]];
+                lines.extend(top_list_lines(config, &submission.date));
+                lines
+            },
+            Err(e) => vec![vec![Span::new(tr!(CouldNotSubmitScore, error = e), Style::colored(1))]],
+        });
+    }
+    ///Waits for the server to answer, so that a score submitted on quitting isn't lost.
+    pub fn finish(self) {
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+}
@@ -1,15 +1,71 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{direction::OrdinalDirection, map_objects::{Airport, Exit, GridRenderable}};
+use crate::{direction::OrdinalDirection, glyphs::glyphs, map::MapStatic, map_objects::{Airport, CrossingRestriction, Exit, GridRenderable, Layer, SpawnWeights}, styled::{Span, Style}, units::altitude_unit};
 use std::{fmt::Display, ops::Add};
 
 ///Also used to represent a path marker.
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct GroundLocation(pub u16, pub u16);
 impl From<AirLocation> for GroundLocation {
     fn from(value: AirLocation) -> Self {
         GroundLocation(value.0, value.1)
     }
+} impl GroundLocation {
+    ///Distance in moves, where a diagonal move counts as one.
+    pub fn chebyshev_distance(&self, other: GroundLocation) -> u16 {
+        self.0.abs_diff(other.0).max(self.1.abs_diff(other.1))
+    }
+    ///Straight-line distance, in cells.
+    #[allow(dead_code)]
+    pub fn euclidean_distance(&self, other: GroundLocation) -> f32 {
+        (self.0.abs_diff(other.0) as f32).hypot(self.1.abs_diff(other.1) as f32)
+    }
+    ///Heading of a straight line toward another location, in degrees clockwise from north, if it
+    ///is elsewhere.
+    pub fn degrees_to(&self, other: GroundLocation) -> Option<f32> {
+        if *self == other {
+            return None;
+        }
+        let (dx, dy) = (other.0 as f32 - self.0 as f32, other.1 as f32 - self.1 as f32);
+        //Clockwise from north, as y grows southward.
+        Some(dx.atan2(-dy).to_degrees().rem_euclid(360.0))
+    }
+    ///The direction closest to a straight line toward another location, if it is elsewhere.
+    pub fn bearing_to(&self, other: GroundLocation) -> Option<OrdinalDirection> {
+        self.degrees_to(other).map(|degrees| OrdinalDirection::from_deg(degrees.round() as u16))
+    }
+    ///Cells along a straight line to another location, including both ends.
+    pub fn line_to(&self, other: GroundLocation) -> Vec<GroundLocation> {
+        let (mut x, mut y) = (self.0 as i32, self.1 as i32);
+        let (to_x, to_y) = (other.0 as i32, other.1 as i32);
+        let (dx, dy) = ((to_x - x).abs(), -(to_y - y).abs());
+        let (step_x, step_y) = ((to_x - x).signum(), (to_y - y).signum());
+        //Bresenham's algorithm: steps along whichever axes keep the line closest to straight.
+        let mut error = dx + dy;
+        let mut cells = vec![];
+        loop {
+            cells.push(GroundLocation(x as u16, y as u16));
+            if (x, y) == (to_x, to_y) {
+                break cells;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+    pub fn is_inside(&self, map: &MapStatic) -> bool {
+        self.0 < map.width && self.1 < map.height
+    }
+    ///Whether the location is in the outermost row or column of the map, where planes leave it.
+    pub fn is_on_edge(&self, map: &MapStatic) -> bool {
+        self.is_inside(map) && (self.0 == 0 || self.1 == 0 || self.0 == map.width - 1 || self.1 == map.height - 1)
+    }
 } impl Add<(i16, i16)> for GroundLocation {
     type Output = GroundLocation;
     fn add(self, rhs: (i16, i16)) -> Self::Output {
@@ -22,15 +78,29 @@ impl From<AirLocation> for GroundLocation {
     fn location(&self) -> Option<GroundLocation> {
         Some(*self)
     }
-    fn render(&self, _command: &crate::command::Command) -> String {
-        "+ ".to_string()
+    fn spans(&self, _command: &crate::command::Command) -> Vec<Span> {
+        vec![Span::new(format!("{} ", glyphs().path_marker), Style::default())]
+    }
+    fn layer(&self) -> Layer {
+        Layer::Background
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct AirLocation(pub u16, pub u16, pub u16);
+impl AirLocation {
+    ///Distance in moves, counting a flight level as one cell, where a diagonal move counts as one.
+    pub fn chebyshev_distance(&self, other: AirLocation) -> u16 {
+        GroundLocation::from(*self).chebyshev_distance(other.into()).max(self.2.abs_diff(other.2))
+    }
+    ///Straight-line distance, counting a flight level as one cell.
+    #[allow(dead_code)]
+    pub fn euclidean_distance(&self, other: AirLocation) -> f32 {
+        GroundLocation::from(*self).euclidean_distance(other.into()).hypot(self.2.abs_diff(other.2) as f32)
+    }
+}
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Location {
     Airport(Airport),
     Flight(AirLocation),
@@ -44,7 +114,7 @@ pub enum Location {
 }
 
 ///Also represents a start location
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Destination {
     Airport(Airport),
     Exit(Exit),
@@ -55,7 +125,6 @@ pub enum Destination {
             Destination::Exit(Exit { entry_location, .. }) => Location::Flight(*entry_location),
         }
     }
-    #[allow(dead_code)]
     pub fn exit(&self) -> Location {
         match self {
             Destination::Airport(a) => Location::Airport(*a),
@@ -88,10 +157,46 @@ pub enum Destination {
             Destination::Exit(Exit { exit_location: AirLocation(_, _, height), .. }) => *height,
         }
     }
-    pub fn to_display_string(&self, colorize: bool, show_exit_char: bool) -> String {
+    pub fn label(&self, colorize: bool, show_exit_char: bool) -> Span {
         match self {
-            Destination::Airport(a) => a.to_display_string(colorize),
-            Destination::Exit(e) => e.to_display_string(colorize, show_exit_char),
+            Destination::Airport(a) => a.label(colorize),
+            Destination::Exit(e) => e.label(colorize, show_exit_char),
+        }
+    }
+    pub fn weights(&self) -> SpawnWeights {
+        match self {
+            Destination::Airport(a) => a.weights,
+            Destination::Exit(e) => e.weights,
+        }.unwrap_or_default()
+    }
+    ///The flight levels planes must leave at, if the exit allows more than one, and the level they
+    ///must leave at or above, or at or below.
+    pub fn requirement_spans(&self) -> Vec<Span> {
+        let Destination::Exit(exit) = self else { return vec![] };
+        let mut requirements = vec![];
+        if let Some((lowest, highest)) = exit.exit_flight_levels {
+            requirements.push(format!("fl{}-{}", altitude_unit().short(lowest), altitude_unit().short(highest)));
+        }
+        match exit.crossing {
+            Some(CrossingRestriction::AtOrAbove(level)) => requirements.push(format!("{}{}", glyphs().at_or_above, altitude_unit().short(level))),
+            Some(CrossingRestriction::AtOrBelow(level)) => requirements.push(format!("{}{}", glyphs().at_or_below, altitude_unit().short(level))),
+            None => {},
+        }
+        requirements.into_iter()
+            .flat_map(|requirement| [Span::plain(" "), Span::new(requirement, Style { dim: true, ..Style::default() })])
+            .collect()
+    }
+} impl GridRenderable for Destination {
+    fn location(&self) -> Option<GroundLocation> {
+        match self {
+            Destination::Airport(airport) => airport.location(),
+            Destination::Exit(exit) => exit.location(),
+        }
+    }
+    fn spans(&self, command: &crate::command::Command) -> Vec<Span> {
+        match self {
+            Destination::Airport(airport) => airport.spans(command),
+            Destination::Exit(exit) => exit.spans(command),
         }
     }
 } impl Display for Destination {
@@ -102,3 +207,64 @@ pub enum Destination {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_generator::generate_map;
+
+    #[test]
+    fn chebyshev_distance_counts_diagonals_as_one() {
+        assert_eq!(GroundLocation(2, 3).chebyshev_distance(GroundLocation(2, 3)), 0);
+        assert_eq!(GroundLocation(2, 3).chebyshev_distance(GroundLocation(5, 4)), 3);
+        assert_eq!(GroundLocation(5, 4).chebyshev_distance(GroundLocation(2, 3)), 3);
+        assert_eq!(AirLocation(0, 0, 9).chebyshev_distance(AirLocation(1, 1, 2)), 7);
+    }
+
+    #[test]
+    fn euclidean_distance() {
+        assert_eq!(GroundLocation(0, 0).euclidean_distance(GroundLocation(3, 4)), 5.0);
+        assert_eq!(GroundLocation(3, 4).euclidean_distance(GroundLocation(0, 0)), 5.0);
+        assert_eq!(AirLocation(0, 0, 0).euclidean_distance(AirLocation(2, 3, 6)), 7.0);
+    }
+
+    #[test]
+    fn bearing_rounds_to_nearest_direction() {
+        let origin = GroundLocation(5, 5);
+        assert_eq!(origin.bearing_to(origin), None);
+        assert_eq!(origin.bearing_to(GroundLocation(5, 0)), Some(OrdinalDirection::North));
+        assert_eq!(origin.bearing_to(GroundLocation(9, 1)), Some(OrdinalDirection::NorthEast));
+        assert_eq!(origin.bearing_to(GroundLocation(9, 6)), Some(OrdinalDirection::East));
+        assert_eq!(origin.bearing_to(GroundLocation(6, 9)), Some(OrdinalDirection::South));
+        assert_eq!(origin.bearing_to(GroundLocation(0, 9)), Some(OrdinalDirection::SouthWest));
+        assert_eq!(origin.bearing_to(GroundLocation(0, 4)), Some(OrdinalDirection::West));
+        assert_eq!(origin.bearing_to(GroundLocation(4, 0)), Some(OrdinalDirection::North));
+        assert_eq!(origin.bearing_to(GroundLocation(2, 2)), Some(OrdinalDirection::NorthWest));
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        assert_eq!(GroundLocation(1, 1).line_to(GroundLocation(1, 1)), vec![GroundLocation(1, 1)]);
+        assert_eq!(GroundLocation(0, 0).line_to(GroundLocation(2, 2)), vec![GroundLocation(0, 0), GroundLocation(1, 1), GroundLocation(2, 2)]);
+        assert_eq!(GroundLocation(3, 0).line_to(GroundLocation(0, 0)).len(), 4);
+        let line = GroundLocation(4, 2).line_to(GroundLocation(0, 0));
+        assert_eq!((line.first(), line.last(), line.len()), (Some(&GroundLocation(4, 2)), Some(&GroundLocation(0, 0)), 5));
+        assert!(line.windows(2).all(|pair| pair[0].chebyshev_distance(pair[1]) == 1));
+    }
+
+    #[test]
+    fn bounds() {
+        let map = generate_map(0);
+        let (right, bottom) = (map.width - 1, map.height - 1);
+        assert!(GroundLocation(0, 0).is_inside(&map));
+        assert!(GroundLocation(right, bottom).is_inside(&map));
+        assert!(!GroundLocation(right + 1, 0).is_inside(&map));
+        assert!(!GroundLocation(0, bottom + 1).is_inside(&map));
+
+        assert!(GroundLocation(0, 3).is_on_edge(&map));
+        assert!(GroundLocation(3, bottom).is_on_edge(&map));
+        assert!(GroundLocation(right, 3).is_on_edge(&map));
+        assert!(!GroundLocation(3, 3).is_on_edge(&map));
+        assert!(!GroundLocation(right + 1, 3).is_on_edge(&map));
+    }
+}
@@ -0,0 +1,99 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{command::CompleteCommandSegment, paths, versioned::{self, FORMAT_VERSION}};
+
+///A command in the macro library, to be loaded into a command slot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Macro {
+    ///Only load the macro on the map with this name. Without one, it is loaded on every map.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map: Option<String>,
+    ///The slot to load the macro into.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digit: Option<u16>,
+    ///The slot's name. Without a digit, the macro is loaded into the slot with this name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub command: CompleteCommandSegment,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MacroLibrary {
+    ///Format of the commands. Libraries without one are from before versions were recorded.
+    #[serde(default)]
+    version: u32,
+    #[serde(default, rename = "macro")]
+    macros: Vec<Macro>,
+}
+
+pub fn library_file() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("macros.toml"))
+}
+
+///Every macro in the library, or none if there is no library.
+pub fn load_library() -> Result<Vec<Macro>> {
+    let Some(file) = library_file() else { return Ok(vec![]) };
+    if !fs::exists(&file)? {
+        return Ok(vec![]);
+    }
+    let library: MacroLibrary = toml::from_str(&fs::read_to_string(file)?)?;
+    versioned::check_version(library.version)?;
+    Ok(library.macros)
+}
+
+///Replaces the library's macros for one map with `macros`, keeping those for every other map and
+///those for all maps. Returns the file written to.
+pub fn save_library(map: &str, macros: Vec<Macro>) -> Result<PathBuf> {
+    let Some(file) = library_file() else { anyhow::bail!("Could not find a directory to save macros to.") };
+    let mut library = MacroLibrary {
+        version: FORMAT_VERSION,
+        macros: load_library()?.into_iter().filter(|m| m.map.as_deref() != Some(map)).collect(),
+    };
+    library.macros.extend(macros);
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&file, toml::to_string(&library)?)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+
+    #[test]
+    fn commands_survive_a_round_trip() {
+        let mut command = Command::default();
+        for key in "%1ce@$?4:ta;a+2#3".chars() {
+            command.input(key);
+        }
+        let command = command.to_complete().expect("command to be complete").head;
+        let library = MacroLibrary {
+            version: FORMAT_VERSION,
+            macros: vec![Macro { map: Some(format!("Crossing")), digit: Some(1), name: None, command: command.clone() }],
+        };
+        let text = toml::to_string(&library).expect("library to serialize");
+        let loaded: MacroLibrary = toml::from_str(&text).expect("library to deserialize");
+        assert_eq!(format!("{:?}", loaded.macros[0].command), format!("{command:?}"));
+        assert_eq!(loaded.macros[0].map.as_deref(), Some("Crossing"));
+    }
+
+    #[test]
+    fn finds_commands_planes_cannot_be_given() {
+        let library: MacroLibrary = toml::from_str(r#"
+            [[macro]]
+            digit = 1
+            command = { And = { left = "Cancel", right = { Hold = 2 } } }
+
+            [[macro]]
+            digit = 2
+            command = { In = { tail = "Cancel", time = 3 } }
+        "#).expect("library to deserialize");
+        assert_eq!(library.macros.iter().map(|m| m.command.is_for_planes()).collect::<Vec<_>>(), vec![false, true]);
+        assert!(toml::from_str::<MacroLibrary>("[[macro]]\ndigit = 1\ncommand = { SlotAction = \"Delete\" }").is_err());
+    }
+}
@@ -1,7 +1,9 @@
-use std::{fmt::Display, io::{self, IsTerminal, Read, Write}, time::{Duration, Instant}};
+use std::{fmt::Display, io::{self, IsTerminal, Write}, panic, path::{Path, PathBuf}, process, time::{Duration, Instant}};
 use clap::Parser;
 
 use anyhow::Result;
+use crossbeam_channel::{select, Sender};
+use serde::{Deserialize, Serialize};
 use termion::{raw::IntoRawMode, screen::IntoAlternateScreen};
 
 mod direction;
@@ -10,154 +12,1062 @@ mod map_objects;
 mod command;
 mod plane;
 mod map;
+mod paths;
+mod glyphs;
+mod menu;
+mod map_resolver;
+mod map_generator;
+mod map_format;
+mod map_art;
+mod map_watcher;
+mod spawn_pattern;
+mod input;
+mod events;
+mod scores;
+mod stats;
+mod macros;
+mod versioned;
+mod daily;
+mod profiling;
+mod styled;
+mod export;
+mod audio;
+mod i18n;
+mod units;
+mod recorder;
+mod timeline;
+mod diagnosis;
+mod drill;
+mod autosave;
+mod crash_report;
+#[cfg(feature = "snapshot")]
+mod bitmap_font;
+#[cfg(feature = "leaderboard")]
+mod leaderboard;
 
-use map::{Map, MapStatic};
+use drill::Drill;
+use autosave::Autosave;
+use map::{Clock, Goal, Map, Situation};
+use glyphs::GlyphSet;
+use menu::{Menu, MenuChoice};
+use map_resolver::MapResolver;
+use daily::Daily;
+use map_watcher::MapWatcher;
+use spawn_pattern::SpawnPattern;
+use input::Input;
+use export::FrameFormat;
+use recorder::Recorder;
+use events::Event;
+use i18n::tr;
+use units::AltitudeUnit;
+
+const DEFAULT_PLANE_SPAWN_RATE: u32 = 30;
+const DEFAULT_TICK_RATE: f32 = 1.0;
+const DEFAULT_STEP_INTERVAL: f32 = 0.2;
+const DEFAULT_REWINDS: u32 = 3;
+const DEFAULT_AUTOSAVE_INTERVAL: u32 = 10;
+///Time between frames when planes are animated.
+const ANIMATION_FRAME: Duration = Duration::from_millis(100);
+///Shortest time between frames, so that a burst of input, like a held key, is drawn once instead
+///of for every key.
+const MIN_FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 60);
+///Longest the game loop sleeps without an event, so that it notices the terminal being resized.
+const RESIZE_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Debug, Clone, Copy)]
 pub enum GameStatus {
     PlanesCrashed(char, char),
     PlaneExited(char),
     PlaneFailedLanding(char),
+    ///The plane reached its exit outside the flight levels it allows.
+    PlaneMissedExitLevels(char),
+    ///The plane landed at an airport with no free stand.
+    PlaneFoundNoStand(char),
+    ///The plane landed at night without being cleared to land in time.
+    PlaneLandedUncleared(char),
+    ///Two planes came into conflict while solving a situation.
+    PlanesInConflict(char, char),
+    ///Every plane of a situation was cleared, in this many ticks.
+    SituationCleared(u32),
+    ///The map's goal was reached.
+    Completed(Goal),
+} impl GameStatus {
+    ///Whether the game was won, rather than lost.
+    pub fn is_success(self) -> bool {
+        matches!(self, GameStatus::SituationCleared(_) | GameStatus::Completed(_))
+    }
 } impl Display for GameStatus {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            GameStatus::PlanesCrashed(a, b) => write!(f, "Plane {a} crashed into plane {b}."),
-            GameStatus::PlaneExited(p) => write!(f, "Plane {p} exited improperly."),
-            GameStatus::PlaneFailedLanding(p) => write!(f, "Plane {p} landed improperly."),
-        }
+        let message = match self {
+            GameStatus::PlanesCrashed(a, b) => tr!(PlanesCrashed, plane = a, other = b),
+            GameStatus::PlaneExited(p) => tr!(PlaneExited, plane = p),
+            GameStatus::PlaneFailedLanding(p) => tr!(PlaneFailedLanding, plane = p),
+            GameStatus::PlaneMissedExitLevels(p) => tr!(PlaneMissedExitLevels, plane = p),
+            GameStatus::PlaneFoundNoStand(p) => tr!(PlaneFoundNoStand, plane = p),
+            GameStatus::PlaneLandedUncleared(p) => tr!(PlaneLandedUncleared, plane = p),
+            GameStatus::PlanesInConflict(a, b) => tr!(PlanesInConflict, plane = a, other = b),
+            GameStatus::SituationCleared(ticks) => tr!(SituationCleared, ticks = ticks),
+            GameStatus::Completed(Goal::Traffic(planes)) => tr!(CompletedTraffic, planes = planes),
+            GameStatus::Completed(Goal::Planes(planes)) => tr!(CompletedPlanes, planes = planes),
+            GameStatus::Completed(Goal::Ticks(ticks)) => tr!(CompletedTicks, ticks = ticks),
+        };
+        write!(f, "{message}")
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+///Which events ring the terminal bell.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, Serialize, Deserialize)]
+pub enum AlertPolicy {
+    Off,
+    ///Only conflict alerts.
+    Conflicts,
+    ///Conflict alerts, new planes, and emergencies.
+    All,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct GameSettings {
     ///In ticks per spawn
     plane_spawn_rate: u32,
+    spawn_pattern: SpawnPattern,
+    ///Most planes there can be at once, counting those announced. There can never be more than 26
+    max_planes: Option<u32>,
     ///In (unit of time) per tick
     tick_rate: Duration,
     allow_landing: bool,
+    ///If set, pilots read back commands, which are carried out a tick later.
+    readback: bool,
+    ///If set, a command for all marked planes is given to none of them if any would reject it.
+    strict: bool,
+    ///If set, commands are sometimes not received, and take `transmission_delay` ticks to arrive.
+    congested: bool,
+    transmission_delay: u32,
+    alerts: AlertPolicy,
+    ///If set, planes glide between cells during each tick.
+    animate: bool,
+    ///If set, the game starts in single-step mode.
+    step: bool,
+    ///Shortest time between ticks advanced by pressing Enter or Space, so that a held key can't
+    ///run the game ahead.
+    step_interval: Duration,
+    ///If set, planes which collide are removed with a penalty instead of ending the game.
+    casual: bool,
+    ///Times a casual game can be rewound.
+    rewinds: u32,
+    ///Chance, from 0 to 1, that a pilot misreads an instruction.
+    pilot_error_chance: f64,
+    ///If set, planes sometimes lose their radio for a while.
+    radio_failures: bool,
+    ///If set, planes' altitudes are only known every this many ticks, unless they are marked.
+    transponder_interval: Option<u32>,
+    ///If set, the radar only sees this many cells from its center.
+    radar_range: Option<u16>,
+    ///If set, planes must be cleared to land on final approach, and only path markers near airports
+    ///are shown.
+    night: bool,
+    ///If set, two players share the game, each scoring for half of the callsigns.
+    hot_seat: bool,
+    frame_format: FrameFormat,
+}
+
+#[derive(Debug, Clone, clap::Subcommand)]
+enum Subcommand {
+    ///Manage maps
+    Map {
+        #[command(subcommand)]
+        action: MapSubcommand,
+    },
+    ///Print every message the game shows, by name, as a starting point for a translation
+    Messages,
+}
+
+#[derive(Debug, Clone, clap::Subcommand)]
+enum MapSubcommand {
+    ///Check a map and copy it into the user's maps directory
+    Install {
+        ///Path or http(s) URL of the map file
+        source: String,
+    },
+    ///Write a map, in any format, as JSON laid out consistently
+    Convert {
+        ///Name of the map or path to its file. `random:<seed>` converts a generated map
+        source: String,
+        ///File to write the JSON to. If absent, it is printed
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    ///List maps
+    List {
+        ///Only list maps installed for the current user
+        #[arg(long)]
+        installed: bool,
+    },
 }
 
 #[derive(Debug, Clone, Parser)]
 #[command(version, about)]
 struct Args {
+    #[command(subcommand)]
+    subcommand: Option<Subcommand>,
     ///Lists maps
     #[arg(short, long)]
     list: bool,
-    ///Select which map to play on
-    #[arg(short, long, default_value_t = String::from("crossing"))]
-    map: String,
-    ///Set number of ticks between plane spawns
-    #[arg(short, long, default_value_t = 30)]
-    plane_spawn_rate: u32,
-    ///Set delay between ticks in seconds, decimals allowed
-    #[arg(short, long, default_value_t = 1.0)]
-    tick_rate: f32,
+    ///Select which map to play on. If absent, a list of maps is shown to choose from. `random` or
+    ///`random:<seed>` generates a new one
+    #[arg(short, long)]
+    map: Option<String>,
+    ///Search this directory for maps before the default search path. Can be given more than once
+    #[arg(long = "maps-dir")]
+    maps_dirs: Vec<PathBuf>,
+    ///Set number of ticks between plane spawns [default: the map's recommendation, or 30]
+    #[arg(short, long)]
+    plane_spawn_rate: Option<u32>,
+    ///How planes are spaced out: `regular`, `poisson` (at random), `bursts` (rushes between quiet
+    ///spells), or `gaps:<shortest>-<longest>` (a random number of ticks in this range) [default: the
+    ///map's recommendation, or regular]
+    #[arg(long)]
+    spawn_pattern: Option<SpawnPattern>,
+    ///Stop announcing planes while there are this many, until some leave [default: the map's
+    ///recommendation, or 26]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=26))]
+    max_planes: Option<u32>,
+    ///Set delay between ticks in seconds, decimals allowed [default: the map's recommendation, or 1]
+    #[arg(short, long, value_parser = seconds)]
+    tick_rate: Option<f32>,
     ///If present, planes' destinations will always be airports
     #[arg(short = 'L', long = "disallow-landing", default_value_t = true, action = clap::ArgAction::SetFalse)]
     allow_landing: bool,
+    ///If present, pilots read back each command before carrying it out a tick later
+    #[arg(long)]
+    readback: bool,
+    ///If present, a command for all marked planes is rejected if any of them would reject it,
+    ///instead of being given to the rest
+    #[arg(long)]
+    strict: bool,
+    ///If present, the radio frequency is congested: commands are sometimes not received and must be
+    ///given again
+    #[arg(long)]
+    congested: bool,
+    ///Set how many ticks commands take to arrive on a congested frequency
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=2))]
+    transmission_delay: u32,
+    ///Set which events ring the terminal bell
+    #[arg(long, value_enum, default_value_t = AlertPolicy::Off)]
+    alerts: AlertPolicy,
+    ///If present, planes glide smoothly between cells instead of jumping once per tick
+    #[arg(long)]
+    animate: bool,
+    ///If present, the game only advances when Enter is pressed. Press "." to switch modes
+    #[arg(long)]
+    step: bool,
+    ///Set the shortest time in seconds between presses of Enter or Space that advance the game, so
+    ///that a held or stuck key can't run it ahead. Presses in between are ignored
+    #[arg(long, default_value_t = DEFAULT_STEP_INTERVAL, value_parser = seconds)]
+    step_interval: f32,
+    ///If present, planes which collide are removed and cost points, instead of ending the game
+    #[arg(long)]
+    casual: bool,
+    ///Set how many times a casual game can be rewound by pressing Ctrl-Z, which puts it back as it
+    ///was 10 ticks before, even once it is over
+    #[arg(long, default_value_t = DEFAULT_REWINDS)]
+    rewinds: u32,
+    ///Set the chance, in percent, that a pilot misreads an instruction, turning the wrong way or
+    ///leveling off at the wrong flight level until corrected
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pilot_errors: u8,
+    ///If present, planes sometimes lose their radio for a while, and commands for them wait until
+    ///it is back
+    #[arg(long)]
+    radio_failures: bool,
+    ///Set how many ticks apart unmarked planes report their altitude, which is shown out of date
+    ///in between. Marked planes always show their own
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    transponder_interval: Option<u32>,
+    ///Set how many cells the radar sees from its center, which is in the middle of the map unless
+    ///the map puts it elsewhere. Planes further away show only where they are [default: the map's
+    ///radar, or the whole map]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    radar_range: Option<u16>,
+    ///If present, the game is played at night: planes must be cleared to land (`F`) within the
+    ///last 3 cells before their runway, and path markers away from airports are not lit
+    #[arg(long)]
+    night: bool,
+    ///If present, two players share the keyboard: planes `a` to `m` score for the first and planes
+    ///`n` to `z` for the second, and both scores are shown
+    #[arg(long)]
+    hot_seat: bool,
+    ///Set the seed for plane spawns. If absent, a random seed is used
+    #[arg(long)]
+    seed: Option<u64>,
+    ///Play today's daily challenge: a map, seed, and spawn rate chosen by the date, the same for
+    ///everyone, with the default difficulty settings. A summary to share is printed when you quit
+    #[arg(long, conflicts_with_all = ["map", "seed", "plane_spawn_rate", "spawn_pattern"])]
+    daily: bool,
+    ///Save statistics on each plane and tick to this file when the game ends, as CSV if it ends in
+    ///".csv" and JSON otherwise. Press Ctrl-E to save them during the game
+    #[arg(long)]
+    stats: Option<PathBuf>,
+    ///Solve a situation saved with Ctrl-X: clear its planes without a conflict, as quickly as
+    ///possible. No other planes appear
+    #[arg(long, conflicts_with_all = ["map", "daily", "watch"])]
+    situation: Option<PathBuf>,
+    ///Practice one kind of traffic on a small map of its own: each round sets up the same
+    ///geometry again, and is scored by how few commands resolved it
+    #[arg(long, conflicts_with_all = ["map", "daily", "watch", "situation"])]
+    drill: Option<Drill>,
+    ///Set how many ticks apart the game is saved, so that it can be resumed with --resume if the
+    ///terminal is closed. The latest 5 games are kept. 0 turns autosaving off
+    #[arg(long, default_value_t = DEFAULT_AUTOSAVE_INTERVAL)]
+    autosave_interval: u32,
+    ///Resume an autosaved game: "last" for the latest, or the autosave's file
+    #[arg(long, value_name = "last|FILE", conflicts_with_all = ["map", "seed", "daily", "situation", "drill"])]
+    resume: Option<String>,
+    ///Reload the map whenever its file changes, keeping the planes that still fit on it. For
+    ///trying out a map while writing it
+    #[arg(long)]
+    watch: bool,
+    ///Save the board to this directory after every tick, one file per tick, to make a replay of the
+    ///game. Press Ctrl-P to save the board once
+    #[arg(long)]
+    frames: Option<PathBuf>,
+    ///Select the format boards are saved in
+    #[arg(long, value_enum, default_value_t = FrameFormat::Svg)]
+    frame_format: FrameFormat,
+    ///Record the session to this file as an asciinema cast, which can be played back with
+    ///`asciinema play` or shared
+    #[arg(long)]
+    record: Option<PathBuf>,
+    ///Show altitudes in feet (`5000ft`), flight levels (`FL050`), or meters (`1520m`). Datablocks
+    ///and the plane list shorten them to `5`, `050`, or `1.5` (kilometers)
+    #[arg(long, value_enum, default_value_t = AltitudeUnit::Feet)]
+    altitudes: AltitudeUnit,
+    ///Show the game in this language, using the translations in `locales/<locale>.json` in the
+    ///config directory [default: from LC_ALL, LC_MESSAGES, or LANG]
+    #[arg(long)]
+    locale: Option<String>,
+    ///Select which characters the game is drawn with
+    #[arg(long, value_enum, default_value_t = GlyphSet::Ascii)]
+    glyphs: GlyphSet,
     ///Enter a sequence of keypresses to be entered before the game starts. Use ":" to finish a
     ///command entry.
     #[arg(short = 'i', long = "initialize", default_value_t = String::new())]
     initialize: String,
+    ///Write the time spent in each part of the game to this file as a Chrome trace, which can be
+    ///opened in Perfetto, when the game ends
+    #[cfg(feature = "profiling")]
+    #[arg(long)]
+    profile_trace: Option<PathBuf>,
+    ///Save the final board as a GIF image to this file when the game is lost, for sharing
+    #[cfg(feature = "snapshot")]
+    #[arg(long)]
+    snapshot: Option<PathBuf>,
+    ///Set the volume of sound effects, from 0 to 100. Press Ctrl-A to mute them
+    #[cfg(feature = "sound")]
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    volume: u8,
+    ///If present, the game starts with sound effects muted
+    #[cfg(feature = "sound")]
+    #[arg(long)]
+    mute: bool,
 } impl Into<GameSettings> for Args {
     fn into(self) -> GameSettings {
         GameSettings {
-            plane_spawn_rate: self.plane_spawn_rate,
-            tick_rate: Duration::from_secs_f32(self.tick_rate),
+            plane_spawn_rate: self.plane_spawn_rate.unwrap_or(DEFAULT_PLANE_SPAWN_RATE),
+            spawn_pattern: self.spawn_pattern.unwrap_or_default(),
+            max_planes: self.max_planes,
+            tick_rate: Duration::from_secs_f32(self.tick_rate.unwrap_or(DEFAULT_TICK_RATE)),
             allow_landing: self.allow_landing,
+            readback: self.readback,
+            strict: self.strict,
+            congested: self.congested,
+            transmission_delay: self.transmission_delay,
+            alerts: self.alerts,
+            animate: self.animate,
+            step: self.step,
+            step_interval: Duration::from_secs_f32(self.step_interval),
+            casual: self.casual,
+            rewinds: self.rewinds,
+            pilot_error_chance: self.pilot_errors as f64 / 100.0,
+            radio_failures: self.radio_failures,
+            transponder_interval: self.transponder_interval,
+            radar_range: self.radar_range,
+            night: self.night,
+            hot_seat: self.hot_seat,
+            frame_format: self.frame_format,
         }
     }
 }
 
+///Parses a time in seconds, which must be one that a `Duration` can hold.
+fn seconds(text: &str) -> Result<f32, String> {
+    let seconds = text.parse::<f32>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f32(seconds).map_err(|_| format!("{text} is not a number of seconds zero or over"))?;
+    Ok(seconds)
+}
+
+///What is needed to start a game, so that it can be restarted or switched to another map.
+#[derive(Clone)]
+struct Session {
+    args: Args,
+    maps: MapResolver,
+    map: String,
+    seed: u64,
+    ///The situation being solved, which is set up again on restarting.
+    situation: Option<Situation>,
+    ///The drill being practiced, which starts again from its first round on restarting.
+    drill: Option<Drill>,
+    ///The autosaved game being resumed. Restarting starts its map afresh instead.
+    resume: Option<Autosave>,
+} impl Session {
+    ///The file the map is read from, or None if it is generated.
+    fn map_file(&self) -> Result<Option<PathBuf>> {
+        if map_generator::parse_random_map(&self.map).is_some() {
+            return Ok(None);
+        }
+        match self.maps.resolve(&self.map) {
+            Some(map_file) => Ok(Some(map_file)),
+            None => anyhow::bail!("Could not find map \"{}\".", self.map),
+        }
+    }
+    ///Watches the map file for changes, if `--watch` was given and the map has a file.
+    fn watch(&self, events: &Sender<Event>) -> Option<MapWatcher> {
+        self.args.watch.then(|| self.map_file().ok().flatten()).flatten().map(|path| MapWatcher::new(path, events.clone()))
+    }
+    fn start(&self) -> Result<Map> {
+        let map_data = if let Some(situation) = &self.situation {
+            situation.map.clone()
+        } else if let Some(autosave) = &self.resume {
+            autosave.situation.map.clone()
+        } else if let Some(drill) = self.drill {
+            drill.map()
+        } else if let Some(map_seed) = map_generator::parse_random_map(&self.map) {
+            //Without a seed of its own, the map changes along with the game's seed.
+            map_generator::generate_map(map_seed.unwrap_or(self.seed))
+        } else {
+            let Some(map_file) = self.map_file()? else { unreachable!("only generated maps have no file") };
+            let map_data = map_format::load_map(&map_file)?;
+            let problems = map_data.problems();
+            if !problems.is_empty() {
+                anyhow::bail!("{} is not a valid map:\n{}", map_file.display(), problems.join("\n"));
+            }
+            map_data
+        };
+        let mut settings: GameSettings = self.args.clone().into();
+        //Settings given as arguments take priority over the map's recommendations.
+        if let (None, Some(rate)) = (self.args.plane_spawn_rate, map_data.recommended.plane_spawn_rate) {
+            settings.plane_spawn_rate = rate;
+        }
+        if let (None, Some(pattern)) = (self.args.spawn_pattern, map_data.recommended.spawn_pattern) {
+            settings.spawn_pattern = pattern;
+        }
+        if let (None, Some(max)) = (self.args.max_planes, map_data.recommended.max_planes) {
+            settings.max_planes = Some(max);
+        }
+        if let (None, Some(Ok(rate))) = (self.args.tick_rate, map_data.recommended.tick_rate.map(Duration::try_from_secs_f32)) {
+            settings.tick_rate = rate;
+        }
+        //A resumed game carries on as it was being played, whatever it was started again with.
+        if let Some(autosave) = &self.resume {
+            settings = autosave.settings;
+        }
+        let mut map = Map::new(settings, map_data, self.seed);
+        if let Some(situation) = &self.situation {
+            map.load_situation(situation.clone());
+        }
+        if let Some(drill) = self.drill {
+            map.start_drill(drill);
+        }
+        if let Some(autosave) = &self.resume {
+            map.resume(autosave.situation.clone(), autosave.progress);
+        }
+        if let Err(e) = map.load_slots() {
+            map.log_error(tr!(CouldNotLoadSlots, error = e));
+        }
+        if let Err(e) = map.load_macros() {
+            map.log_error(tr!(CouldNotLoadMacros, error = e));
+        }
+        Ok(map)
+    }
+}
+
+///Saves the game's score if it is the best on its map. Situations aren't scored by points, so
+///they are left out.
+fn record_score(map: &mut Map) {
+    if map.is_puzzle() {
+        return;
+    }
+    if let Err(e) = scores::record_score(map.name(), map.score()) {
+        map.log_error(tr!(CouldNotSaveScore, error = e));
+    }
+}
+
+///The day's challenge, while it is being played.
+struct DailyRun {
+    daily: Daily,
+    ///The result of the first game of the challenge to end, printed on quitting.
+    result: Option<String>,
+    #[cfg(feature = "leaderboard")]
+    leaderboard: Option<leaderboard::Client>,
+} impl DailyRun {
+    ///Keeps the result of the first daily challenge game to end. Games on other maps or seeds,
+    ///chosen from the menu, aren't the daily challenge and are left out.
+    fn record(&mut self, session: &Session, map: &Map) {
+        if self.result.is_none() && session.map == self.daily.map && map.seed == self.daily.seed {
+            self.result = Some(self.daily.summary(map));
+            #[cfg(feature = "leaderboard")]
+            if let Some(leaderboard) = &mut self.leaderboard {
+                leaderboard.submit_daily(&self.daily, map);
+            }
+        }
+    }
+}
+
+///Where statistics are saved when Ctrl-E is pressed without `--stats`.
+fn default_stats_file() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("stats.json"))
+}
+
+///Plays a tick of the game, then saves the board to `frames`, if given. Ticks played ahead on a
+///copy of the game, to find the next event, are never saved.
+fn advance(map: &mut Map, frames: &mut Option<PathBuf>) {
+    let tick = map.tick_no();
+    map.tick();
+    let Some(dir) = frames else { return };
+    if map.tick_no() == tick {
+        return;
+    }
+    if let Err(e) = map.save_board(dir) {
+        map.log_error(tr!(CouldNotSaveFrame, error = e));
+        *frames = None;
+    }
+}
+
+///Where the board is saved when Ctrl-P is pressed without `--frames`.
+fn default_frames_dir() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("frames"))
+}
+
+///Where situations are saved when Ctrl-X is pressed.
+fn default_situations_dir() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("situations"))
+}
+
+///Saves the game's statistics, logging where they were saved.
+fn save_stats(map: &mut Map, path: &Path) {
+    match map.stats.save(path) {
+        Ok(paths) => {
+            let paths = paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" and ");
+            map.log(tr!(StatisticsSaved, files = paths));
+        },
+        Err(e) => map.log_error(tr!(CouldNotSaveStatistics, error = e)),
+    }
+}
+
+#[cfg(feature = "snapshot")]
+fn save_snapshot(map: &mut Map, path: &Path) {
+    match std::fs::write(path, export::to_gif(&map.board())) {
+        Ok(()) => map.log(tr!(FinalBoardSaved, file = path.display())),
+        Err(e) => map.log_error(tr!(CouldNotSaveFinalBoard, error = e)),
+    }
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse();
+    match panic::catch_unwind(run) {
+        //Another thread may have panicked as the game was ending.
+        Ok(result) => {
+            crash_report::report();
+            result
+        },
+        //By now the terminal has been restored, as the game was unwound, but what restored it is
+        //still to be flushed.
+        Err(_) => {
+            print!("{}", termion::cursor::Show);
+            io::stdout().flush()?;
+            crash_report::report();
+            process::exit(101);
+        },
+    }
+}
+
+fn run() -> Result<()> {
+    let mut args = Args::parse();
+    let daily = args.daily.then(Daily::today);
+    if let Some(daily) = &daily {
+        args.map = Some(daily.map.clone());
+        args.seed = Some(daily.seed);
+        args.plane_spawn_rate = Some(daily.plane_spawn_rate);
+        //Everything else that changes how hard the game is stays as it is by default, so that
+        //everyone's results can be compared.
+        args.spawn_pattern = None;
+        args.max_planes = None;
+        args.tick_rate = None;
+        args.allow_landing = true;
+        args.congested = false;
+        args.transmission_delay = 1;
+        args.casual = false;
+        args.pilot_errors = 0;
+        args.radio_failures = false;
+        args.transponder_interval = None;
+        args.radar_range = None;
+        args.night = false;
+        args.step = false;
+    }
+    if let Err(e) = i18n::set_locale(args.locale.as_deref()) {
+        eprintln!("Could not load translations: {e}");
+    }
+    let maps = MapResolver::new(&args.maps_dirs);
+    match &args.subcommand {
+        Some(Subcommand::Map { action: MapSubcommand::Install { source } }) => {
+            let name = map_resolver::install_map(source)?;
+            println!("Installed map {name}. Play it with --map {name}.");
+            return Ok(());
+        },
+        Some(Subcommand::Map { action: MapSubcommand::List { installed: true } }) => {
+            for name in map_resolver::installed_maps() {
+                println!("{name}");
+            }
+            return Ok(());
+        },
+        Some(Subcommand::Map { action: MapSubcommand::Convert { source, output } }) => {
+            let map = if let Some(map_seed) = map_generator::parse_random_map(source) {
+                map_generator::generate_map(map_seed.unwrap_or_default())
+            } else {
+                let Some(map_file) = maps.resolve(source) else { anyhow::bail!("Could not find map \"{source}\".") };
+                map_format::load_map(&map_file)?
+            };
+            let problems = map.problems();
+            if !problems.is_empty() {
+                anyhow::bail!("{source} is not a valid map:\n{}", problems.join("\n"));
+            }
+            let json = map_format::to_normalized_json(&map)?;
+            match output {
+                Some(path) => std::fs::write(path, json)?,
+                None => print!("{json}"),
+            }
+            return Ok(());
+        },
+        Some(Subcommand::Messages) => {
+            println!("{}", i18n::catalog()?);
+            return Ok(());
+        },
+        Some(Subcommand::Map { action: MapSubcommand::List { installed: false } }) => {
+            println!("{}", tabled::Table::new(menu::map_listings(&maps)).with(tabled::settings::Style::blank()));
+            return Ok(());
+        },
+        None => {},
+    }
     if args.list {
-        use std::fs::{read_dir, read};
-        let maps = read_dir("maps")?.map(|f| -> Result<MapStatic> {
-            let file = f?;
-            let contents = read(file.path())?;
-            Ok(serde_json::de::from_slice(&contents)?)
-        }).filter_map(Result::ok).collect::<Vec<_>>();
-
-        println!("{}", tabled::Table::new(maps).with(tabled::settings::Style::blank()));
+        println!("{}", tabled::Table::new(menu::map_listings(&maps)).with(tabled::settings::Style::blank()));
         return Ok(());
     }
 
     if !io::stdout().is_terminal() {
         panic!("Not an interactive terminal.");
     }
-    use std::fs::{exists, read};
+    glyphs::set_glyphs(args.glyphs);
+    units::set_altitude_unit(args.altitudes);
+    let settings: GameSettings = args.clone().into();
+    let situation = args.situation.as_deref().map(Situation::load).transpose()?;
+    let (autosave_file, resumed) = args.resume.as_deref().map(autosave::load).transpose()?.unzip();
+    //A game resumed from a file of its own is autosaved alongside the others instead of over it.
+    let mut autosave_file = autosave_file.filter(|file| file.parent() == autosave::dir().as_deref()).or_else(autosave::new_file);
 
-    let map_file = if exists(&args.map)? { format!("{}", args.map) }
-    else if exists(&format!("{}.json", args.map))? { format!("{}.json", args.map) }
-    else { format!("maps/{}.json", args.map) };
-
-    let map_text = read(&map_file)?;
-    let map_data: MapStatic = serde_json::de::from_slice(&map_text)?;
-    let settings = args.clone().into();
-    let mut map = Map::new(settings, map_data);
-
-    let mut stdout = io::stdout().into_raw_mode()?.into_alternate_screen()?;
+    //Opened before the game is drawn, as the audio system may print its complaints to the terminal.
+    #[cfg(feature = "sound")]
+    let mut player = audio::Player::new(args.volume as f32 / 100.0, args.mute);
+    crash_report::install();
+    let mut stdout = Recorder::new(io::stdout().into_raw_mode()?.into_alternate_screen()?, args.record.as_deref())?;
     write!(stdout, "{}", termion::cursor::Hide)?;
     stdout.flush()?;
-    let mut input = termion::async_stdin();
+
+    let map_name = match (&resumed, &situation, args.drill, args.map.clone()) {
+        (Some(autosave), _, _, _) => autosave.map_name.clone(),
+        (None, Some(situation), _, _) => situation.map.name.clone(),
+        (None, None, Some(drill), _) => drill.map().name,
+        (None, None, None, Some(name)) => name,
+        (None, None, None, None) => match menu::pick_map(&mut stdout, io::stdin(), &maps)? {
+            Some(name) => name,
+            None => {
+                drop(stdout);
+                print!("{}", termion::cursor::Show);
+                return Ok(());
+            },
+        },
+    };
+    let mut session = Session {
+        args: args.clone(),
+        maps,
+        map: map_name,
+        seed: resumed.as_ref().map(|autosave| autosave.seed).or(args.seed).unwrap_or_else(rand::random),
+        situation,
+        drill: args.drill,
+        resume: resumed,
+    };
+    let mut map = session.start()?;
+    crash_report::start_game(&session.map, map.seed, &map.settings);
+    let (events, received) = crossbeam_channel::unbounded();
+    let mut watcher = session.watch(&events);
+    let mut daily = daily.map(|daily| DailyRun {
+        daily,
+        result: None,
+        #[cfg(feature = "leaderboard")]
+        leaderboard: leaderboard::Client::new(&events),
+    });
+    if let Some(run) = &mut daily {
+        map.log(tr!(DailyChallenge, date = run.daily.date));
+        #[cfg(feature = "leaderboard")]
+        if let Some(leaderboard) = &mut run.leaderboard {
+            leaderboard.show_top_list(&run.daily);
+        }
+    }
+    events::spawn_input(io::stdin(), events.clone());
 
     for ch in args.initialize.chars() {
         if ch == ':' {
-            if let Some(c) = map.current_command.to_complete() {
-                map.exec(c);
-                map.current_command.reset();
-            }
+            map.submit_command();
         } else {
             map.current_command.input(ch);
         }
     }
 
-    map.render(&mut stdout)?;
+    map.render(&mut stdout, 0.0)?;
 
-    let mut char_buf = [0u8];
     let mut last_tick = Instant::now();
+    let mut last_step = Instant::now();
+    let mut last_frame = Instant::now();
     let mut is_dirty = true;
+    let mut menu: Option<Menu> = None;
+    let mut last_autosave = map.tick_no();
+    let mut frames = args.frames.clone();
     
     'game: loop {
-        if let Ok(count) = input.read(&mut char_buf) {
-            if count > 0 {
+        crash_report::check_threads();
+        //Sleeps until something happens, the next tick is due, or it is time to draw a frame or look
+        //for the terminal being resized.
+        let mut wake = Instant::now() + RESIZE_POLL_INTERVAL;
+        if is_dirty {
+            wake = wake.min(last_frame + MIN_FRAME_INTERVAL);
+        }
+        if settings.animate {
+            wake = wake.min(last_frame + ANIMATION_FRAME);
+        }
+        let next_tick = if menu.is_none() && !map.timeline.is_open && map.clock == Clock::Running {
+            crossbeam_channel::at(last_tick + map.settings.tick_rate)
+        } else {
+            crossbeam_channel::never()
+        };
+        let event = select! {
+            recv(received) -> event => event.ok(),
+            //Whether a tick is due is worked out below, so timers only wake the loop.
+            recv(next_tick) -> _ => None,
+            recv(crossbeam_channel::at(wake)) -> _ => None,
+        };
+        match event {
+            Some(Event::Input(inputs)) => for input in inputs {
+                let _span = profiling::span("input");
                 is_dirty = true;
-                let ch = char_buf[0] as char;
+                crash_report::record_input(input);
+                let ch = match input {
+                    Input::Char(ch) => ch,
+                    Input::Escape if map.timeline.is_open => {
+                        map.timeline.toggle();
+                        continue;
+                    },
+                    Input::Escape => {
+                        map.current_command.reset();
+                        map.measurement = None;
+                        if !map.is_over() { menu = None; }
+                        continue;
+                    },
+                    Input::Scroll(by) if map.timeline.is_open => {
+                        map.timeline.scroll(-by * map.terminal_size.1.saturating_sub(2) as isize);
+                        continue;
+                    },
+                    Input::Scroll(by) => {
+                        map.scroll_list(by);
+                        continue;
+                    },
+                    Input::Arrow(_, y) if map.timeline.is_open => {
+                        map.timeline.scroll(-y as isize);
+                        continue;
+                    },
+                    //While measuring, the arrow keys move the end of the line instead.
+                    Input::Arrow(x, y) if map.measurement.is_some() => {
+                        map.move_measurement_cursor(x, y);
+                        continue;
+                    },
+                    Input::Arrow(x, y) => {
+                        map.pan_view(x, y);
+                        continue;
+                    },
+                };
                 if ch == '\x03' {
                     break 'game;
-                } else if ch == '\x1b' {
-                    map.current_command.reset();
+                } else if ch == '\x14' {
+                    map.timeline.toggle();
+                } else if map.timeline.is_open {
+                    //Only scrolling and closing it do anything while the timeline is open.
+                } else if ch == '\x1a' {
+                    //A game which is over can be rewound from its menu, and played on.
+                    if map.rewind() {
+                        menu = None;
+                        last_tick = Instant::now();
+                    }
+                } else if let Some(open_menu) = &mut menu {
+                    match open_menu.input(ch, &session.maps) {
+                        None => {},
+                        Some(MenuChoice::Quit) => break 'game,
+                        Some(choice) => {
+                            let mut next = session.clone();
+                            next.resume = None;
+                            match choice {
+                                MenuChoice::RestartWithNewSeed => next.seed = rand::random(),
+                                MenuChoice::SwitchMap(name) => {
+                                    next.map = name;
+                                    next.situation = None;
+                                    next.drill = None;
+                                },
+                                _ => {},
+                            }
+                            //A map which can't be started leaves the menu open to choose another.
+                            let next_map = match next.start() {
+                                Ok(next_map) => next_map,
+                                Err(e) => {
+                                    map.log_error(tr!(CouldNotStartGame, error = e));
+                                    continue;
+                                },
+                            };
+                            record_score(&mut map);
+                            if let Some(daily) = &mut daily {
+                                daily.record(&session, &map);
+                            }
+                            if let Some(path) = &args.stats {
+                                save_stats(&mut map, path);
+                            }
+                            if let Some(file) = &autosave_file {
+                                autosave::remove(file);
+                            }
+                            autosave_file = autosave::new_file();
+                            session = next;
+                            map = next_map;
+                            crash_report::start_game(&session.map, map.seed, &map.settings);
+                            watcher = session.watch(&events);
+                            menu = None;
+                            last_tick = Instant::now();
+                            last_autosave = map.tick_no();
+                        },
+                    }
+                } else if ch == '\x12' {
+                    menu = Some(Menu::Main);
+                } else if ch == '\x13' {
+                    match map.save_macros() {
+                        Ok(file) => map.log(tr!(SlotsSaved, file = file.display())),
+                        Err(e) => map.log_error(tr!(CouldNotSaveMacros, error = e)),
+                    }
+                } else if ch == '\x10' {
+                    if let Some(dir) = args.frames.clone().or_else(default_frames_dir) {
+                        match map.save_board(&dir) {
+                            Ok(file) => map.log(tr!(BoardSaved, file = file.display())),
+                            Err(e) => map.log_error(tr!(CouldNotSaveBoard, error = e)),
+                        }
+                    }
+                } else if ch == '\x18' {
+                    if let Some(dir) = default_situations_dir() {
+                        match map.save_situation(&dir) {
+                            Ok(file) => map.log(tr!(SituationSaved, file = file.display())),
+                            Err(e) => map.log_error(tr!(CouldNotSaveSituation, error = e)),
+                        }
+                    }
+                } else if ch == '\x01' {
+                    #[cfg(feature = "sound")]
+                    {
+                        player.muted = !player.muted;
+                        if !player.is_available() {
+                            map.log_error(tr!(SoundUnavailable));
+                        } else if player.muted {
+                            map.log(tr!(SoundMuted));
+                        } else {
+                            map.log(tr!(SoundUnmuted));
+                        }
+                    }
+                } else if ch == '\x05' {
+                    if let Some(path) = args.stats.clone().or_else(default_stats_file) {
+                        save_stats(&mut map, &path);
+                    }
+                } else if map.measurement.is_some() {
+                    match ch {
+                        '#' => map.toggle_measurement(),
+                        '\n' | '\r' => map.mark_measurement(),
+                        _ => map.measure_to_plane(ch),
+                    }
+                } else if ch == '#' && map.current_command.is_empty() {
+                    map.toggle_measurement();
+                } else if ch == '\t' && map.current_command.is_empty() {
+                    map.list_view.sort = map.list_view.sort.next();
+                } else if ch == '/' && map.current_command.is_empty() {
+                    map.list_view.marked_only = !map.list_view.marked_only;
+                } else if ch == '|' && map.current_command.is_empty() {
+                    map.list_view.split = !map.list_view.split;
+                } else if ch == '\\' && map.current_command.is_empty() {
+                    map.list_view.strips = !map.list_view.strips;
+                } else if ch == ' ' && map.current_command.is_empty() {
+                    if last_step.elapsed() >= map.settings.step_interval {
+                        last_step = Instant::now();
+                        last_tick = Instant::now();
+                        for _ in 0..map.ticks_until_event() {
+                            advance(&mut map, &mut frames);
+                        }
+                    }
+                } else if ch == '.' && map.current_command.is_empty() {
+                    last_tick = Instant::now();
+                    map.clock = map.clock.toggled();
+                } else if ch == '<' && map.current_command.is_empty() {
+                    map.change_tick_rate(false);
+                } else if ch == '>' && map.current_command.is_empty() {
+                    map.change_tick_rate(true);
+                } else if ch == '~' && map.current_command.is_empty() {
+                    map.show_trails = !map.show_trails;
+                } else if ch == '=' && map.current_command.is_empty() {
+                    map.show_flow = !map.show_flow;
+                } else if ch == '+' && map.current_command.is_empty() {
+                    map.change_zoom(1);
+                } else if ch == '-' && map.current_command.is_empty() {
+                    map.change_zoom(-1);
                 } else if ch == '\n' || ch == '\r' {
                     if map.current_command.is_empty() {
-                        last_tick = Instant::now();
-                        map.tick();
-                        is_dirty = true;
-                    } else if let Some(c) = map.current_command.to_complete() {
-                        map.exec(c);
-                        map.current_command.reset();
+                        if last_step.elapsed() >= map.settings.step_interval {
+                            last_step = Instant::now();
+                            last_tick = Instant::now();
+                            advance(&mut map, &mut frames);
+                        }
+                    } else {
+                        map.submit_command();
                     }
                 } else {
                     map.current_command.input(ch);
                 }
+            },
+            Some(Event::MapChanged(path, update)) if watcher.as_ref().is_some_and(|watcher| watcher.path == path) => {
+                match (*update).and_then(|data| map.reload_static(data)) {
+                    Ok(()) => map.log(tr!(Reloaded, file = path.display())),
+                    Err(e) => map.log_error(tr!(CouldNotReload, file = path.display(), error = e)),
+                }
+                is_dirty = true;
+            },
+            //A file changed by an old watcher, since replaced, is no longer of interest.
+            Some(Event::MapChanged(..)) => {},
+            #[cfg(feature = "leaderboard")]
+            Some(Event::Leaderboard(lines)) => {
+                for line in lines {
+                    map.log_spans(line);
+                }
+                is_dirty = true;
+            },
+            //Nothing happened before it was time to wake up.
+            None => {},
+        }
+
+        if let Ok(size) = termion::terminal_size() {
+            if size != map.terminal_size {
+                map.terminal_size = size;
+                is_dirty = true;
             }
         }
-        
-        if Instant::now().duration_since(last_tick) >= settings.tick_rate {
+
+        if menu.is_none() && !map.timeline.is_open && map.clock == Clock::Running && Instant::now().duration_since(last_tick) >= map.settings.tick_rate {
             last_tick = Instant::now();
-            map.tick();
+            advance(&mut map, &mut frames);
             is_dirty = true;
         }
+
+        crash_report::set_tick(map.tick_no());
+
+        if args.autosave_interval > 0 && !map.is_over() && !map.is_puzzle() && map.tick_no().abs_diff(last_autosave) >= args.autosave_interval {
+            last_autosave = map.tick_no();
+            if let Some(file) = &autosave_file {
+                if let Err(e) = autosave::save(file, &Autosave::of(&map, &session.map)) {
+                    map.log_error(tr!(CouldNotAutosave, error = e));
+                    //Trying again every few ticks would only fill the log.
+                    autosave_file = None;
+                }
+            }
+        }
+
+        if map.is_over() && menu.is_none() {
+            //A game which is over can't be resumed.
+            if let Some(file) = &autosave_file {
+                autosave::remove(file);
+            }
+            record_score(&mut map);
+            if let Some(daily) = &mut daily {
+                daily.record(&session, &map);
+            }
+            if let Some(path) = &args.stats {
+                save_stats(&mut map, path);
+            }
+            #[cfg(feature = "snapshot")]
+            if let Some(path) = &args.snapshot {
+                save_snapshot(&mut map, path);
+            }
+            menu = Some(Menu::Main);
+        }
         
-        if is_dirty {
-            map.render(&mut stdout)?;
+        if settings.animate && Instant::now().duration_since(last_frame) >= ANIMATION_FRAME {
+            is_dirty = true;
+        }
+
+        if is_dirty && last_frame.elapsed() >= MIN_FRAME_INTERVAL {
+            last_frame = Instant::now();
+            let progress = Instant::now().duration_since(last_tick).as_secs_f32() / map.settings.tick_rate.as_secs_f32();
+            if map.timeline.is_open {
+                map.timeline.render(&mut stdout, map.terminal_size)?;
+            } else {
+                map.render(&mut stdout, progress)?;
+                if let Some(menu) = &menu {
+                    menu.render(&mut stdout, map.seed, !map.is_over())?;
+                }
+            }
+            if map.bell {
+                write!(stdout, "\x07")?;
+                stdout.flush()?;
+                map.bell = false;
+            }
+            #[cfg(feature = "sound")]
+            for &sound in &map.sounds {
+                player.play(sound);
+            }
+            map.sounds.clear();
             is_dirty = false;
         }
     }
 
+    record_score(&mut map);
+    if let Some(daily) = &mut daily {
+        daily.record(&session, &map);
+    }
+    if let Some(path) = &args.stats {
+        save_stats(&mut map, path);
+    }
     drop(stdout);
-    drop(input);
     print!("{}", termion::cursor::Show);
+    if let Some(daily) = daily {
+        #[cfg(feature = "leaderboard")]
+        if let Some(leaderboard) = daily.leaderboard {
+            leaderboard.finish();
+        }
+        if let Some(result) = daily.result {
+            println!("{result}");
+        }
+    }
+    #[cfg(feature = "profiling")]
+    {
+        print!("{}", profiling::summary());
+        if let Some(path) = &args.profile_trace {
+            if let Err(e) = profiling::write_trace(path) {
+                eprintln!("Could not write trace: {e}");
+            }
+        }
+    }
 
     Ok(())
 }
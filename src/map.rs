@@ -1,250 +1,2463 @@
-use crate::{command::{Command, CompleteAnd, CompleteAt, CompleteCommand, CompleteCommandSegment, CompleteCommandTarget, CompleteIn, CompleteRef}, direction::{CardinalDirection, OrdinalDirection}, location::{AirLocation, Destination, GroundLocation, Location}, map_objects::{Airport, Beacon, Exit, ListItemPartRenderable, ListRenderable, RenderGrid}, plane::{Plane, Visibility}, GameSettings, GameStatus};
+use crate::{audio::Sound, diagnosis, drill::{Drill, DrillProgress}, command::{Argument, Command, CommandRejection, CommandSegment, CommandTarget, CompleteAltitude, CompleteAnd, CompleteAt, CompleteCommand, CompleteCommandSegment, CompleteCommandTarget, CompleteElse, CompleteEvery, CompleteIn, CompletePointOfInterest, CompleteRef, CompleteTurn, PointOfInterest, SlotAction, SlotKey}, direction::{CardinalDirection, OrdinalDirection}, location::{AirLocation, Destination, GroundLocation, Location}, map_objects::{FINAL_APPROACH_LENGTH, Airport, Airway, Beacon, CrashSite, Exit, PrimaryTarget, Procedure, ProcedureKind, Radar, GridBuffer, GridRenderable, ListItemPartRenderable, ListRenderable, Minimap, RenderGrid}, glyphs::glyphs, i18n::{tr, tr_spans}, macros::{self, Macro}, paths, plane::{Plane, Player, Visibility}, profiling, spawn_pattern::SpawnPattern, stats::{GameStats, StatsMark}, styled::{self, Ansi, CellGrid, Span, Style}, timeline::{EventKind, Timeline}, units::altitude_unit, versioned, AlertPolicy, GameSettings, GameStatus};
 use anyhow::Result;
-use std::{collections::HashMap, io::Write};
-use serde::Deserialize;
-use tabled::Tabled;
-use rand::{random, random_range, rng, prelude::*};
+use std::{cell::RefCell, collections::{HashMap, VecDeque}, fmt::Display, fs, io::Write, path::{Path, PathBuf}, str::FromStr, time::Duration};
+use serde::{Deserialize, Serialize};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
 
-#[derive(Debug, Clone, Deserialize, Tabled)]
+///Version of the map format. Raise it when a change would stop older maps from loading, and upgrade
+///them in `MapStatic::from_value`.
+pub const MAP_VERSION: u32 = 1;
+
+///Highest flight level planes may be sent to, unless the map sets another.
+pub const DEFAULT_CEILING: u16 = 9;
+
+///Planes are named with letters, so there can be no more than this many at once.
+const CALLSIGN_COUNT: usize = 26;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MapStatic {
-    #[tabled(rename = "Map")]
+    ///Version of the format the map was written in. Maps without one are from before versions were
+    ///recorded.
+    #[serde(default)]
+    pub version: u32,
     pub name: String,
-    #[tabled(rename = "Author")]
     pub author: String,
     pub width: u16,
     pub height: u16,
-    #[tabled(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    ///From 1 (easiest) to 5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    ///Settings the map is meant to be played with, used unless others are given as arguments.
+    #[serde(default, skip_serializing_if = "RecommendedSettings::is_empty")]
+    pub recommended: RecommendedSettings,
     pub exits: Vec<Exit>,
-    #[tabled(skip)]
     pub beacons: Vec<Beacon>,
-    #[tabled(skip)]
     pub airports: Vec<Airport>,
-    #[tabled(skip)]
     pub path_markers: Vec<GroundLocation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub airways: Vec<Airway>,
+    ///Standard departures and arrivals.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub procedures: Vec<Procedure>,
+    ///Pairs of exits or airports, such as `["E0", "E1"]`, which planes are never sent between in
+    ///that direction.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden_routes: Vec<(String, String)>,
+    ///Highest flight level planes may be sent to. `DEFAULT_CEILING` if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ceiling: Option<u16>,
+    ///Where the radar is and how far it sees. Without one, it sees the whole map.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radar: Option<Radar>,
+    ///What completes the map. Without one, it is played until something goes wrong.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goal: Option<Goal>,
+} impl MapStatic {
+    ///Reads a map, as read from any of the formats in `map_format`, upgrading it if it was written
+    ///for an older version of the format.
+    pub fn from_value(mut map: serde_json::Value) -> Result<MapStatic> {
+        let version = map.get("version").and_then(serde_json::Value::as_u64).unwrap_or(0) as u32;
+        if version > MAP_VERSION {
+            anyhow::bail!("The map is for a newer version of ATC, in map format {version}; only up to {MAP_VERSION} can be read.");
+        }
+        //Each step upgrades the map by one version.
+        for from in version..MAP_VERSION {
+            match from {
+                //Version 1 only added the version field.
+                0 => {},
+                _ => unreachable!("every older version has a step"),
+            }
+        }
+        if let Some(map) = map.as_object_mut() {
+            map.insert(format!("version"), MAP_VERSION.into());
+        }
+        Ok(serde_json::from_value(map)?)
+    }
+    pub fn ceiling(&self) -> u16 {
+        self.ceiling.unwrap_or(DEFAULT_CEILING)
+    }
+    ///Characters across each cell of the radar view, which is wider when planes can fly at
+    ///two-digit flight levels.
+    pub fn cell_width(&self) -> u16 {
+        if self.ceiling() >= 10 { 3 } else { 2 }
+    }
+    ///Whether planes may be sent from one exit or airport to another.
+    pub fn allows_route(&self, from: &Destination, to: &Destination) -> bool {
+        let (from_name, to_name) = (from.to_string(), to.to_string());
+        from != to && !self.forbidden_routes.iter().any(|(from, to)| from.eq_ignore_ascii_case(&from_name) && to.eq_ignore_ascii_case(&to_name))
+    }
+    ///Describes everything wrong with the map that would stop it from being played.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.version != MAP_VERSION {
+            problems.push(format!("The map is in format {}, not the current format {MAP_VERSION}.", self.version));
+        }
+        if self.width == 0 || self.height == 0 {
+            problems.push(format!("The map has no area."));
+        }
+        if self.exits.is_empty() {
+            problems.push(format!("The map has no exits."));
+        } else {
+            let weights = || self.exits.iter().map(|exit| exit.weights).chain(self.airports.iter().map(|airport| airport.weights)).map(Option::unwrap_or_default);
+            if weights().all(|weights| weights.spawn == 0) {
+                problems.push(format!("No exit or airport has a spawn weight above 0."));
+            }
+            //Airports are not always destinations, as landing can be turned off.
+            if self.exits.iter().all(|exit| exit.weights.unwrap_or_default().destination == 0) {
+                problems.push(format!("No exit has a destination weight above 0."));
+            }
+            let starts = self.exits.iter().map(|exit| Destination::Exit(*exit)).chain(self.airports.iter().map(|airport| Destination::Airport(*airport)))
+                .filter(|start| start.weights().spawn > 0)
+                .collect::<Vec<_>>();
+            let finishes = self.exits.iter().map(|exit| Destination::Exit(*exit)).filter(|finish| finish.weights().destination > 0).collect::<Vec<_>>();
+            if !starts.iter().any(|start| finishes.iter().any(|finish| self.allows_route(start, finish))) {
+                problems.push(format!("No plane can be spawned, as every route from a spawn point to an exit is forbidden."));
+            }
+        }
+        let names = self.exits.iter().map(|exit| Destination::Exit(*exit).to_string()).chain(self.airports.iter().map(|airport| Destination::Airport(*airport).to_string())).collect::<Vec<_>>();
+        for (from, to) in &self.forbidden_routes {
+            if let Some(missing) = [from, to].into_iter().find(|name| !names.iter().any(|known| known.eq_ignore_ascii_case(name))) {
+                problems.push(format!("The forbidden route from {from} to {to} names {missing}, which does not exist."));
+            }
+        }
+        if let Some(problem) = self.recommended.spawn_pattern.and_then(SpawnPattern::problem) {
+            problems.push(problem);
+        }
+        if let Some(rate) = self.recommended.tick_rate.filter(|rate| Duration::try_from_secs_f32(*rate).is_err()) {
+            problems.push(format!("The recommended tick rate of {rate} is not a number of seconds zero or over."));
+        }
+        if let Some(radar) = self.radar {
+            if !radar.center.is_inside(self) {
+                problems.push(format!("The radar is outside the map."));
+            }
+            if radar.range == 0 {
+                problems.push(format!("The radar has no range."));
+            }
+        }
+        if self.goal.is_some_and(|goal| goal.target() == 0) {
+            problems.push(format!("The map's goal is already met when the game starts."));
+        }
+        if self.ceiling == Some(0) {
+            problems.push(format!("The ceiling is at the ground."));
+        }
+        if self.recommended.max_planes == Some(0) {
+            problems.push(format!("The map allows no planes at once."));
+        }
+        if let Some(difficulty) = self.difficulty.filter(|d| !(1..=5).contains(d)) {
+            problems.push(format!("Difficulty {difficulty} is not between 1 and 5."));
+        }
+        let in_bounds = |location: GroundLocation| location.is_inside(self);
+        for exit in &self.exits {
+            if !in_bounds(exit.entry_location.into()) || !in_bounds(exit.exit_location.into()) {
+                problems.push(format!("Exit {} is outside the map.", exit.index));
+            }
+            if exit.exit_flight_levels.is_some_and(|(lowest, highest)| lowest > highest) {
+                problems.push(format!("Exit {} has its lowest flight level above its highest.", exit.index));
+            }
+            let AirLocation(_, _, highest) = exit.exit_location;
+            let highest = exit.entry_location.2.max(exit.exit_flight_levels.map_or(highest, |(_, highest)| highest));
+            if highest > self.ceiling() {
+                problems.push(format!("Exit {} uses flight level {highest}, above the ceiling.", exit.index));
+            }
+            if exit.crossing.is_some() && !(1..=self.ceiling()).any(|level| exit.allows_level(level)) {
+                problems.push(format!("No flight level below the ceiling meets exit {}'s crossing restriction.", exit.index));
+            }
+        }
+        for beacon in &self.beacons {
+            if !in_bounds(beacon.location) {
+                problems.push(format!("Beacon {} is outside the map.", beacon.index));
+            }
+        }
+        for airport in &self.airports {
+            if !in_bounds(airport.location) {
+                problems.push(format!("Airport {} is outside the map.", airport.index));
+            }
+            if airport.stands == Some(0) {
+                problems.push(format!("Airport {} has no stands.", airport.index));
+            }
+        }
+        if self.path_markers.iter().any(|marker| !in_bounds(*marker)) {
+            problems.push(format!("A path marker is outside the map."));
+        }
+        for airway in &self.airways {
+            if airway.beacons.len() < 2 {
+                problems.push(format!("Airway {} does not connect two beacons.", airway.index));
+            } else if let Some(beacon) = airway.beacons.iter().find(|b| !self.beacons.iter().any(|beacon| beacon.index == **b)) {
+                problems.push(format!("Airway {} passes a beacon {beacon} which does not exist.", airway.index));
+            } else if self.airway_route(airway).is_none() {
+                problems.push(format!("Airway {} has beacons which are not in line with each other.", airway.index));
+            }
+        }
+        for procedure in &self.procedures {
+            let name = &procedure.name;
+            if !self.airports.iter().any(|a| a.index == procedure.airport) {
+                problems.push(format!("Procedure {name} is for an airport {} which does not exist.", procedure.airport));
+            }
+            if procedure.steps.is_empty() {
+                problems.push(format!("Procedure {name} has no steps."));
+            }
+            for step in &procedure.steps {
+                if step.altitude.is_some_and(|altitude| altitude > self.ceiling()) {
+                    problems.push(format!("Procedure {name} climbs above the ceiling."));
+                }
+                if step.altitude.is_none() && step.heading.is_none() && step.airway.is_none() {
+                    problems.push(format!("Procedure {name} has a step which does nothing."));
+                }
+                if let Some(beacon) = step.at.filter(|b| !self.beacons.iter().any(|beacon| beacon.index == *b)) {
+                    problems.push(format!("Procedure {name} waits for a beacon {beacon} which does not exist."));
+                }
+                if let Some(airway) = step.airway.filter(|a| !self.airways.iter().any(|airway| airway.index == *a)) {
+                    problems.push(format!("Procedure {name} follows an airway {airway} which does not exist."));
+                }
+            }
+            //Planes cannot turn on the ground, so a departure must wait for a beacon before turning.
+            let is_turning_on_ground = procedure.kind == ProcedureKind::Departure
+                && procedure.steps.iter().take_while(|s| s.at.is_none()).any(|s| s.heading.is_some());
+            if is_turning_on_ground {
+                problems.push(format!("Departure {name} turns before it reaches a beacon."));
+            }
+        }
+        //Commands refer to objects by index, so each must be unique.
+        for (kind, indices) in [
+            ("exit", self.exits.iter().map(|e| e.index).collect::<Vec<_>>()),
+            ("beacon", self.beacons.iter().map(|b| b.index).collect()),
+            ("airport", self.airports.iter().map(|a| a.index).collect()),
+            ("airway", self.airways.iter().map(|a| a.index).collect()),
+            ("procedure", self.procedures.iter().map(|p| p.index).collect()),
+        ] {
+            for (i, index) in indices.iter().enumerate() {
+                if indices[..i].contains(index) {
+                    problems.push(format!("There is more than one {kind} {index}."));
+                }
+            }
+        }
+        problems
+    }
+    ///Each leg of an airway, as the beacon it starts at and the direction to fly from there. None if
+    ///a beacon does not exist or is not in line with the next.
+    fn airway_route(&self, airway: &Airway) -> Option<Vec<(Beacon, OrdinalDirection)>> {
+        let beacons = airway.beacons.iter()
+            .map(|index| self.beacons.iter().find(|b| b.index == *index).copied())
+            .collect::<Option<Vec<_>>>()?;
+        beacons.windows(2)
+            .map(|leg| Some((leg[0], OrdinalDirection::between(leg[0].location, leg[1].location)?)))
+            .collect()
+    }
+    ///Cells along airways, which are drawn as path markers.
+    fn airway_markers(&self) -> Vec<GroundLocation> {
+        let mut markers = vec![];
+        for airway in &self.airways {
+            let Some(route) = self.airway_route(airway) else { continue };
+            for ((beacon, direction), next) in route.iter().zip(&airway.beacons[1..]) {
+                let Some(end) = self.beacons.iter().find(|b| b.index == *next) else { continue };
+                let mut location = beacon.location + direction.as_offset();
+                while location != end.location {
+                    markers.push(location);
+                    location = location + direction.as_offset();
+                }
+            }
+        }
+        markers
+    }
+    ///A clearance to turn onto each leg of an airway on reaching its first beacon.
+    pub fn airway_clearance(&self, index: u16) -> Option<CompleteCommandSegment> {
+        let airway = self.airways.iter().find(|a| a.index == index)?;
+        let legs = self.airway_route(airway)?.into_iter().map(|(beacon, direction)| CompleteCommandSegment::At(CompleteAt {
+            tail: Box::new(CompleteCommandSegment::Turn(CompleteTurn::ToHeading(direction))),
+            poi: CompletePointOfInterest::Beacon(Argument::Value(beacon.index)),
+        }));
+        chain(legs)
+    }
+    ///A clearance for each step of a procedure in turn.
+    pub fn procedure_clearance(&self, procedure: &Procedure) -> Option<CompleteCommandSegment> {
+        let steps = procedure.steps.iter().filter_map(|step| {
+            let instructions = [
+                step.altitude.map(|a| CompleteCommandSegment::Altitude(CompleteAltitude::To(Argument::Value(a)))),
+                step.heading.map(|h| CompleteCommandSegment::Turn(CompleteTurn::ToHeading(h))),
+                step.airway.map(CompleteCommandSegment::Airway),
+            ];
+            let instructions = chain(instructions.into_iter().flatten())?;
+            Some(match step.at {
+                Some(beacon) => CompleteCommandSegment::At(CompleteAt {
+                    tail: Box::new(instructions),
+                    poi: CompletePointOfInterest::Beacon(Argument::Value(beacon)),
+                }),
+                None => instructions,
+            })
+        }).collect::<Vec<_>>();
+        chain(steps.into_iter())
+    }
+}
+
+///A dim label, as set before values in the lines above the plane list.
+fn label(text: String) -> Span {
+    Span::new(text, Style { dim: true, ..Style::default() })
+}
+
+///Joins commands with `&`, so each waits for the one before it to finish.
+fn chain(commands: impl DoubleEndedIterator<Item = CompleteCommandSegment>) -> Option<CompleteCommandSegment> {
+    commands.rev().reduce(|right, left| CompleteCommandSegment::And(CompleteAnd { left: Box::new(left), right: Box::new(right) }))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecommendedSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plane_spawn_rate: Option<u32>,
+    ///In seconds per tick.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tick_rate: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spawn_pattern: Option<SpawnPattern>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_planes: Option<u32>,
+} impl RecommendedSettings {
+    pub fn is_empty(&self) -> bool {
+        self.plane_spawn_rate.is_none() && self.tick_rate.is_none() && self.spawn_pattern.is_none() && self.max_planes.is_none()
+    }
+}
+
+///What completes a map, ending the game in success.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Goal {
+    ///Only this many planes are announced, and the map is completed once every one has left it.
+    Traffic(u32),
+    ///Completed once this many planes have reached their destinations.
+    Planes(u32),
+    ///Completed once this many ticks have passed.
+    Ticks(u32),
+} impl Goal {
+    pub fn target(self) -> u32 {
+        match self {
+            Goal::Traffic(target) | Goal::Planes(target) | Goal::Ticks(target) => target,
+        }
+    }
+} impl FromStr for Goal {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((kind, target)) = s.split_once(' ') else { return Err(format!("expected traffic, planes, or ticks and a number")) };
+        let Ok(target) = target.trim().parse() else { return Err(format!("`{}` is not a number", target.trim())) };
+        match kind {
+            "traffic" => Ok(Goal::Traffic(target)),
+            "planes" => Ok(Goal::Planes(target)),
+            "ticks" => Ok(Goal::Ticks(target)),
+            _ => Err(format!("expected traffic, planes, or ticks and a number")),
+        }
+    }
+}
+
+///A stored command, referenced with `%n`, or `%` and its name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandSlot {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub command: CompleteCommandSegment,
+}
+
+///An entry in the message log.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub tick: u32,
+    pub text: Vec<Span>,
+}
+
+///A command which has been read back by its pilot, but not yet carried out.
+#[derive(Debug, Clone)]
+pub struct PendingInstruction {
+    pub command: CompleteCommand,
+    ///The tick on which the command is carried out.
+    pub due: u32,
+}
+
+///A plane which has been announced but has not yet appeared.
+#[derive(Debug, Clone)]
+pub struct PendingSpawn {
+    pub plane: Plane,
+    pub start: Destination,
+    ///The tick on which the plane appears.
+    pub due: u32,
+}
+
+///A plane which has landed and is taking up a stand at its airport.
+#[derive(Debug, Clone, Copy)]
+pub struct ParkedPlane {
+    ///Index of the airport.
+    pub airport: u16,
+    ///The tick on which the stand is freed.
+    pub until: u32,
+}
+
+///A plane announced in a situation, which appears `ticks` ticks after the situation is loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnnouncedPlane {
+    pub plane: Plane,
+    pub start: Destination,
+    pub ticks: u32,
+}
+
+///The traffic picture at one moment of a game, saved so that others can try to clear it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Situation {
+    ///The whole map, so that the situation can be played without having it installed.
+    pub map: MapStatic,
+    ///The tick the situation was saved on.
+    pub tick: u32,
+    pub planes: Vec<Plane>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub announced: Vec<AnnouncedPlane>,
+} impl Situation {
+    ///Reads a situation saved with `Map::save_situation`.
+    pub fn load(path: &Path) -> Result<Situation> {
+        let situation: Situation = versioned::from_json(&fs::read(path)?)?;
+        let problems = situation.map.problems();
+        if !problems.is_empty() {
+            anyhow::bail!("{} is not a valid situation:\n{}", path.display(), problems.join("\n"));
+        }
+        Ok(situation)
+    }
+}
+
+///The score and spawning of a game in progress, saved along with its situation so that the game
+///can be resumed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Progress {
+    pub planes_landed: u32,
+    pub planes_announced: u32,
+    pub score_bonus: u32,
+    pub score_penalty: u32,
+    #[serde(default)]
+    pub player_scores: [(u32, u32); 2],
+    pub next_spawn: u32,
+}
+
+///The order of the plane list.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ListSort {
+    ///In the order planes appeared.
+    #[default]
+    Spawn,
+    Callsign,
+    Altitude,
+    Destination,
+    ///Longest active first.
+    TimeActive,
+} impl ListSort {
+    pub fn next(self) -> ListSort {
+        match self {
+            ListSort::Spawn       => ListSort::Callsign,
+            ListSort::Callsign    => ListSort::Altitude,
+            ListSort::Altitude    => ListSort::Destination,
+            ListSort::Destination => ListSort::TimeActive,
+            ListSort::TimeActive  => ListSort::Spawn,
+        }
+    }
+} impl Display for ListSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ListSort::Spawn       => String::new(),
+            ListSort::Callsign    => tr!(SortedByCallsign),
+            ListSort::Altitude    => tr!(SortedByAltitude),
+            ListSort::Destination => tr!(SortedByDestination),
+            ListSort::TimeActive  => tr!(SortedByTimeActive),
+        })
+    }
+}
+
+///What advances the game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clock {
+    ///Ticks happen on a timer.
+    Running,
+    ///Ticks only happen when the player asks for one.
+    Stepping,
+} impl Clock {
+    pub fn toggled(self) -> Clock {
+        match self {
+            Clock::Running  => Clock::Stepping,
+            Clock::Stepping => Clock::Running,
+        }
+    }
+}
+
+///A range and bearing line between two cells of the radar, for judging how far apart things are.
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    ///Where the line starts, once it has been chosen.
+    pub anchor: Option<GroundLocation>,
+    ///Where the line ends, moved with the arrow keys or to a plane by entering its callsign.
+    pub cursor: GroundLocation,
+} impl Measurement {
+    ///The length and heading of the line, once it has both ends.
+    fn describe(&self) -> Option<String> {
+        let anchor = self.anchor?;
+        let degrees = anchor.degrees_to(self.cursor)?.round() as u16 % 360;
+        let range = anchor.chebyshev_distance(self.cursor);
+        Some(tr!(Measurement, range = range, bearing = format!("{degrees:03}"), direction = glyphs().direction(OrdinalDirection::from_deg(degrees)), prop_ticks = range * 2))
+    }
+}
+
+///How the plane list is shown.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListView {
+    pub sort: ListSort,
+    pub marked_only: bool,
+    ///If set, planes bound for airports and planes bound for exits are listed separately.
+    pub split: bool,
+    ///If set, planes are shown as flight strips.
+    pub strips: bool,
+    ///Index of the first line shown.
+    pub scroll: usize,
+}
+
+
+///How many messages are shown at once.
+const MESSAGE_LOG_LENGTH: usize = 5;
+///The largest zoom level of the radar view.
+const MAX_ZOOM: u16 = 3;
+///Bounds of the tick rate when changed during play.
+const MIN_TICK_RATE: Duration = Duration::from_millis(100);
+const MAX_TICK_RATE: Duration = Duration::from_secs(10);
+///A summary of the game which changes when something happens that the player should see: a plane
+///appears or leaves, a message is logged, a command is delivered or takes effect, or the game ends.
+#[derive(Debug, PartialEq)]
+struct EventSnapshot {
+    planes: usize,
+    messages: usize,
+    pending_instructions: usize,
+    is_over: bool,
+    ///Each plane's callsign, target flight level, target direction, and visibility.
+    targets: Vec<(char, u16, OrdinalDirection, Visibility)>,
+}
+
+///Most ticks skipped by fast-forwarding.
+const FAST_FORWARD_LIMIT: u32 = 100;
+///Columns kept free for the status panel when fitting the radar view to the terminal.
+const MIN_TABLE_WIDTH: u16 = 32;
+///Planes this close to each other, horizontally and in flight levels, are in conflict.
+const CONFLICT_DISTANCE: u16 = 2;
+///Fewest and most ticks of notice given before a plane appears.
+const ENTRY_NOTICE: (u32, u32) = (3, 5);
+///Ticks allowed on top of the straight-line flight time when working out a plane's par, for
+///climbing, descending, and turning.
+pub const PAR_ALLOWANCE: u32 = 10;
+///Points earned for a plane which reaches its destination within par.
+const PAR_BONUS: u32 = 1;
+///Chance that a plane loses its radio for a while, when radio failures are on.
+const RADIO_FAILURE_CHANCE: f64 = 0.05;
+///Fewest and most ticks after entering that a plane's radio fails.
+const RADIO_FAILURE_START: (u32, u32) = (3, 15);
+///Fewest and most ticks a plane's radio stays out.
+const RADIO_FAILURE_LENGTH: (u32, u32) = (5, 12);
+///Points lost each time a plane is held outside.
+const HOLD_PENALTY: u32 = 1;
+///Points lost each time a plane is diverted.
+const DIVERT_PENALTY: u32 = 3;
+///Points lost for each plane which leaves by an exit too soon after the last.
+const HANDOFF_PENALTY: u32 = 2;
+///Points lost for each collision, in casual mode.
+const CRASH_PENALTY: u32 = 10;
+///Ticks a crash site stays on the radar, in casual mode.
+const CRASH_SITE_DURATION: u32 = 20;
+///Ticks over which the traffic flow display counts planes reaching their destinations.
+const FLOW_WINDOW: u32 = 100;
+///Ticks a plane which has landed takes up a stand.
+const STAND_DURATION: u32 = 15;
+///Slots created by name, rather than by digit, are numbered from here, leaving the digits free.
+const FIRST_NAMED_SLOT: u16 = 10;
+///Most slots a reference can pass through, each referring to the next, before it is rejected.
+const MAX_SLOT_DEPTH: usize = 8;
+///Ticks ahead that a query looks for conflicts.
+const QUERY_LOOKAHEAD: u32 = 3;
+///Chance that a command is not received when the frequency is congested.
+const DROPPED_TRANSMISSION_CHANCE: f64 = 0.1;
+///Mixed into the seed for rolling dropped transmissions, so that they don't follow the planes.
+const RADIO_SEED_SALT: u64 = 0x7261_6469_6f00_0001;
+///Mixed into the seed for rolling pilot errors, so that they don't follow the planes either.
+const PILOT_ERROR_SEED_SALT: u64 = 0x7069_6c6f_7400_0001;
+///Ticks a rewind goes back, which is also how many ticks of the game are kept to rewind to.
+pub const REWIND_TICKS: usize = 10;
+
+///The parts of a game which change from tick to tick, kept for a few ticks so that casual games
+///can be rewound.
+#[derive(Debug, Clone)]
+struct GameState {
+    planes: Vec<Plane>,
+    exit_state: Vec<GameStatus>,
+    tick_no: u32,
+    planes_landed: u32,
+    planes_announced: u32,
+    score_bonus: u32,
+    score_penalty: u32,
+    player_scores: [(u32, u32); 2],
+    crash_sites: Vec<CrashSite>,
+    parked_planes: Vec<ParkedPlane>,
+    last_handoffs: HashMap<u16, u32>,
+    pending_instructions: Vec<PendingInstruction>,
+    pending_spawns: Vec<PendingSpawn>,
+    next_spawn: u32,
+    conflicts: Vec<(char, char)>,
+    rng: ChaCha8Rng,
+    radio_rng: ChaCha8Rng,
+    pilot_error_rng: ChaCha8Rng,
+    stats: StatsMark,
+    puzzle: Option<u32>,
+    drill: Option<DrillProgress>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Map {
     info: MapStatic,
-    settings: GameSettings,
+    pub settings: GameSettings,
     pub current_command: Command,
     pub planes: Vec<Plane>,
-    exit_state: Option<GameStatus>,
+    ///Everything that ended the game, all of which happened on the same tick.
+    exit_state: Vec<GameStatus>,
     tick_no: u32,
     planes_landed: u32,
-    command_slots: HashMap<u16, CompleteCommand>,
+    ///Planes announced so far, which stop being announced once a `Goal::Traffic` is reached.
+    planes_announced: u32,
+    ///Points earned for planes reaching their destinations within par, added to `planes_landed` to
+    ///give the score.
+    score_bonus: u32,
+    ///Points lost for holding planes outside and for collisions, taken from `planes_landed` to give
+    ///the score.
+    score_penalty: u32,
+    ///Points earned and lost by each player, for hot-seat mode.
+    player_scores: [(u32, u32); 2],
+    ///Where planes have recently crashed, in casual mode.
+    crash_sites: Vec<CrashSite>,
+    ///Planes which have landed at airports with a limited number of stands.
+    parked_planes: Vec<ParkedPlane>,
+    ///The tick on which a plane last left by each exit, by the exit's index.
+    last_handoffs: HashMap<u16, u32>,
+    ///Tiles of the radar view, kept between frames.
+    grid_buffer: RefCell<GridBuffer>,
+    command_slots: HashMap<u16, CommandSlot>,
+    show_slot_details: bool,
+    ///Whether each plane's trail is drawn on the radar.
+    pub show_trails: bool,
+    ///Whether the traffic flow display is shown above the plane list.
+    pub show_flow: bool,
+    ///The range and bearing line, while one is being measured.
+    pub measurement: Option<Measurement>,
+    ///Why the last command was rejected, if it was.
+    pub rejection: Option<CommandRejection>,
+    pub messages: Vec<Message>,
+    pending_instructions: Vec<PendingInstruction>,
+    pending_spawns: Vec<PendingSpawn>,
+    ///The tick on which the next plane is announced.
+    next_spawn: u32,
+    pub list_view: ListView,
+    ///Size of the terminal in columns and rows, used to fit the radar view and plane list.
+    pub terminal_size: (u16, u16),
+    ///Top left cell of the radar view, when the map is too large to show all of it.
+    view_origin: (u16, u16),
+    pub clock: Clock,
+    ///How many terminal rows each cell of the radar view takes up.
+    pub zoom: u16,
+    ///Pairs of planes that were in conflict last tick.
+    conflicts: Vec<(char, char)>,
+    ///Set when an event should ring the terminal bell.
+    pub bell: bool,
+    ///Sounds for what happened since they were last played, each at most once.
+    pub sounds: Vec<Sound>,
+    ///Seeds `rng`, so that a game can be played again with the same planes.
+    pub seed: u64,
+    ///Portable, unlike `StdRng`, so that a seed gives the same planes on every platform.
+    rng: ChaCha8Rng,
+    ///Rolls whether transmissions are dropped, apart from `rng` so that a congested frequency
+    ///doesn't change which planes come.
+    radio_rng: ChaCha8Rng,
+    ///Rolls pilot errors, apart from `rng` so that they don't change which planes come.
+    pilot_error_rng: ChaCha8Rng,
+    pub stats: GameStats,
+    pub timeline: Timeline,
+    ///The tick a situation was loaded on, in puzzle mode: no more planes are announced, and the
+    ///game is won by clearing the planes there are without a conflict.
+    puzzle: Option<u32>,
+    ///The drill being practiced, in which each situation cleared, or lost, is followed by another.
+    drill: Option<DrillProgress>,
+    ///The game as it was at the start of each of the last `REWIND_TICKS` ticks, oldest first.
+    history: VecDeque<GameState>,
+    ///Rewinds which can still be used, in casual mode.
+    pub rewinds_left: u32,
+    ///Whether this is a copy of the game being ticked ahead to see what happens, whose ticks are
+    ///profiled apart from the game's own.
+    is_forecast: bool,
 } impl Map {
-    pub fn new(settings: GameSettings, data: MapStatic) -> Self {
+    pub fn new(settings: GameSettings, data: MapStatic, seed: u64) -> Self {
+        let step = settings.step;
+        let rewinds_left = settings.rewinds;
         Map {
             info: data,
             settings,
             current_command: Default::default(),
             planes: vec![],
-            exit_state: None,
+            exit_state: vec![],
             tick_no: 0,
             planes_landed: 0,
+            planes_announced: 0,
+            score_bonus: 0,
+            score_penalty: 0,
+            player_scores: [(0, 0); 2],
+            crash_sites: vec![],
+            parked_planes: vec![],
+            last_handoffs: HashMap::new(),
+            grid_buffer: RefCell::default(),
             command_slots: HashMap::new(),
+            show_slot_details: false,
+            show_trails: false,
+            show_flow: false,
+            measurement: None,
+            rejection: None,
+            messages: vec![],
+            pending_instructions: vec![],
+            pending_spawns: vec![],
+            next_spawn: 0,
+            list_view: ListView::default(),
+            terminal_size: termion::terminal_size().unwrap_or((80, 24)),
+            view_origin: (0, 0),
+            clock: if step { Clock::Stepping } else { Clock::Running },
+            zoom: 1,
+            conflicts: vec![],
+            bell: false,
+            sounds: vec![],
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            radio_rng: ChaCha8Rng::seed_from_u64(seed ^ RADIO_SEED_SALT),
+            pilot_error_rng: ChaCha8Rng::seed_from_u64(seed ^ PILOT_ERROR_SEED_SALT),
+            stats: GameStats::default(),
+            timeline: Timeline::default(),
+            puzzle: None,
+            drill: None,
+            history: VecDeque::new(),
+            rewinds_left,
+            is_forecast: false,
+        }
+    }
+    ///Planes in the plane list, filtered and sorted according to `list_view`.
+    fn listed_planes(&self) -> Vec<&Plane> {
+        let mut listed_planes = self.planes.iter()
+            .filter(|plane| self.is_on_radar(plane))
+            .filter(|plane| !self.list_view.marked_only || plane.show == Visibility::Marked)
+            .collect::<Vec<_>>();
+        match self.list_view.sort {
+            ListSort::Spawn       => {},
+            ListSort::Callsign    => listed_planes.sort_by_key(|plane| plane.callsign.to_ascii_lowercase()),
+            ListSort::Altitude    => listed_planes.sort_by_key(|plane| plane.flight_level()),
+            ListSort::Destination => listed_planes.sort_by_key(|plane| plane.destination.to_string()),
+            ListSort::TimeActive  => listed_planes.sort_by_key(|plane| std::cmp::Reverse(plane.ticks_active)),
+        }
+        listed_planes
+    }
+    ///Rendered lines of the plane list, split into arrivals and departures if `list_view.split` is
+    ///set.
+    fn list_lines(&self) -> Vec<Vec<Span>> {
+        let render_planes = |planes: Vec<&Plane>| planes.into_iter().flat_map(|plane| {
+            if self.list_view.strips {
+                plane.render_strip(&self.current_command)
+            } else {
+                vec![<Plane as ListRenderable>::spans(plane, &self.current_command)]
+            }
+        }).collect::<Vec<_>>();
+        let listed_planes = self.listed_planes();
+        if !self.list_view.split {
+            return render_planes(listed_planes);
         }
+        let (arrivals, departures) = listed_planes.into_iter()
+            .partition::<Vec<_>, _>(|plane| matches!(plane.destination, Destination::Airport(_)));
+        let heading = |text: String, planes: usize| vec![
+            Span::new(text, Style { underline: true, ..Style::default() }),
+            Span::plain(" "),
+            Span::new(format!("({planes})"), Style { dim: true, ..Style::default() }),
+        ];
+        let mut lines = vec![heading(tr!(Arrivals), arrivals.len())];
+        lines.extend(render_planes(arrivals));
+        lines.push(heading(tr!(Departures), departures.len()));
+        lines.extend(render_planes(departures));
+        lines
+    }
+    ///How many planes fit in the plane list, leaving room for the header and message log.
+    fn list_rows(&self) -> usize {
+        let header = 3 + self.status_lines().len();
+        (self.terminal_size.1 as usize).saturating_sub(header + 1 + MESSAGE_LOG_LENGTH).max(1)
+    }
+    ///Lines shown between the plane list's heading and the list itself.
+    fn status_lines(&self) -> Vec<Vec<Span>> {
+        [self.goal_line(), self.flow_line(), self.stands_line()].into_iter().flatten().collect()
+    }
+    ///How far the game is from completing the map's goal.
+    fn goal_line(&self) -> Option<Vec<Span>> {
+        let goal = self.goal()?;
+        let (progress, target) = (self.goal_progress(goal), goal.target());
+        let what = match goal {
+            Goal::Traffic(_) => tr!(GoalTraffic, progress = progress, target = target),
+            Goal::Planes(_) => tr!(GoalPlanes, progress = progress, target = target),
+            Goal::Ticks(_) => tr!(GoalTicks, progress = progress, target = target),
+        };
+        Some(vec![label(tr!(Goal)), Span::plain(format!(" {what}"))])
+    }
+    ///Arrivals and departures in the last `FLOW_WINDOW` ticks, planes in the sector, average
+    ///handling time and conflict alerts so far, when shown.
+    fn flow_line(&self) -> Option<Vec<Span>> {
+        if !self.show_flow {
+            return None;
+        }
+        let (arrivals, departures) = self.stats.throughput(self.tick_no, FLOW_WINDOW);
+        let handling_time = self.stats.average_handling_time().map_or(format!("-"), |time| format!("{time:.0}"));
+        Some(vec![
+            label(tr!(FlowWindow, ticks = FLOW_WINDOW)), Span::plain(format!(" {}  ", tr!(FlowThroughput, arrivals = arrivals, departures = departures))),
+            label(tr!(FlowPlanes)), Span::plain(format!(" {}  ", self.planes.len())),
+            label(tr!(FlowAverage)), Span::plain(format!(" {}  ", tr!(FlowHandlingTime, ticks = handling_time))),
+            label(tr!(FlowAlerts)), Span::plain(format!(" {}", self.stats.conflict_alerts())),
+        ])
+    }
+    ///How many stands are in use at each airport which has a limited number, shown above the plane
+    ///list. Airports with none free are shown in yellow.
+    fn stands_line(&self) -> Option<Vec<Span>> {
+        let airports = self.info.airports.iter().filter_map(|airport| {
+            let stands = airport.stands?;
+            let in_use = self.stands_in_use(airport);
+            let style = Style::colored(3).when(in_use >= stands as usize);
+            Some(vec![airport.label(true), Span::plain(" "), Span::new(format!("{in_use}/{stands}"), style)])
+        }).collect::<Vec<_>>();
+        if airports.is_empty() {
+            return None;
+        }
+        Some([vec![label(tr!(Stands)), Span::plain(" ")], airports.join(&Span::plain("  "))].concat())
+    }
+    ///Scrolls the plane list by a number of pages.
+    pub fn scroll_list(&mut self, pages: isize) {
+        let rows = self.list_rows();
+        let max_scroll = self.list_lines().len().saturating_sub(rows);
+        self.list_view.scroll = self.list_view.scroll.min(max_scroll)
+            .saturating_add_signed(pages * rows as isize)
+            .min(max_scroll);
+    }
+    fn sound(&mut self, sound: Sound) {
+        if !self.sounds.contains(&sound) {
+            self.sounds.push(sound);
+        }
+    }
+    ///Rings the bell if the alert policy allows. `is_conflict` is set for conflict alerts.
+    fn alert(&mut self, is_conflict: bool) {
+        match self.settings.alerts {
+            AlertPolicy::Off => {},
+            AlertPolicy::Conflicts => self.bell |= is_conflict,
+            AlertPolicy::All => self.bell = true,
+        }
+    }
+    ///Size of the part of the map that fits in the radar view, in cells.
+    fn view_size(&self) -> (u16, u16) {
+        let (columns, rows) = self.terminal_size;
+        let width = columns.saturating_sub(MIN_TABLE_WIDTH) / (self.info.cell_width() * self.zoom);
+        //Leave room for the command input and rejection beneath the radar view.
+        let height = rows.saturating_sub(3) / self.zoom;
+        (width.clamp(1, self.info.width), height.clamp(1, self.info.height))
+    }
+    ///Top left cell of the radar view, kept within the map.
+    fn view_origin(&self) -> (u16, u16) {
+        let (width, height) = self.view_size();
+        (self.view_origin.0.min(self.info.width - width), self.view_origin.1.min(self.info.height - height))
+    }
+    ///Whether the map is too large to show all at once, so the radar view only shows part of it.
+    pub fn is_viewport_active(&self) -> bool {
+        self.view_size() != (self.info.width, self.info.height)
+    }
+    ///Moves the radar view a quarter of its size in each direction given.
+    pub fn pan_view(&mut self, x: i16, y: i16) {
+        let (width, height) = self.view_size();
+        let (origin_x, origin_y) = self.view_origin();
+        self.view_origin = (
+            origin_x.saturating_add_signed(x * (width / 4).max(1) as i16).min(self.info.width - width),
+            origin_y.saturating_add_signed(y * (height / 4).max(1) as i16).min(self.info.height - height),
+        );
+    }
+    ///Number of planes with a command waiting for them to reach a point.
+    fn planes_waiting_on(&self, poi: CompletePointOfInterest) -> usize {
+        self.planes.iter().filter(|plane| plane.command.as_ref().is_some_and(|command| command.conditions().contains(&poi))).count()
+    }
+    ///Where the beacon or exit the command being entered waits for is, and how many planes already
+    ///wait for it, to help avoid choosing the wrong one.
+    fn command_target_info(&self) -> Option<Vec<Span>> {
+        let (poi, GroundLocation(x, y)) = match self.current_command.current_segment().target()? {
+            PointOfInterest::Beacon(Some(Argument::Value(index))) | PointOfInterest::Default(index) => {
+                let beacon = self.info.beacons.iter().find(|beacon| beacon.index == index)?;
+                (CompletePointOfInterest::Beacon(Argument::Value(index)), beacon.location)
+            },
+            PointOfInterest::Exit(Some(Argument::Value(index))) => {
+                let exit = self.info.exits.iter().find(|exit| exit.index == index)?;
+                (CompletePointOfInterest::Exit(Argument::Value(index)), exit.entry_location.into())
+            },
+            _ => return None,
+        };
+        let waiting = self.planes_waiting_on(poi);
+        let (point, x, y) = (poi.spans(true), [Span::plain(x.to_string())], [Span::plain(y.to_string())]);
+        Some(match waiting {
+            1 => tr_spans!(TargetWithOnePlane, point = point, x = x, y = y),
+            _ => tr_spans!(TargetWithPlanes, point = point, x = x, y = y, planes = [Span::plain(waiting.to_string())]),
+        })
+    }
+    ///Starts measuring from the middle of the radar view, or stops.
+    pub fn toggle_measurement(&mut self) {
+        let ((left, top), (width, height)) = (self.view_origin(), self.view_size());
+        self.measurement = match self.measurement {
+            Some(_) => None,
+            None => Some(Measurement { anchor: None, cursor: GroundLocation(left + width / 2, top + height / 2) }),
+        };
+    }
+    ///Moves the end of the range and bearing line, panning the radar view to keep it in sight.
+    pub fn move_measurement_cursor(&mut self, x: i16, y: i16) {
+        let Some(measurement) = &mut self.measurement else { return };
+        let GroundLocation(cursor_x, cursor_y) = measurement.cursor;
+        measurement.cursor = GroundLocation(
+            cursor_x.saturating_add_signed(x).min(self.info.width - 1),
+            cursor_y.saturating_add_signed(y).min(self.info.height - 1),
+        );
+        let GroundLocation(cursor_x, cursor_y) = measurement.cursor;
+        let ((left, top), (width, height)) = (self.view_origin(), self.view_size());
+        self.view_origin = (
+            left.min(cursor_x).max((cursor_x + 1).saturating_sub(width)),
+            top.min(cursor_y).max((cursor_y + 1).saturating_sub(height)),
+        );
+    }
+    ///Moves the end of the range and bearing line to a plane, if there is one with this callsign.
+    pub fn measure_to_plane(&mut self, callsign: char) {
+        let Some(plane) = self.planes.iter().find(|plane| plane.callsign.eq_ignore_ascii_case(&callsign)) else { return };
+        let location = plane.location.into();
+        if let Some(measurement) = &mut self.measurement {
+            measurement.cursor = location;
+        }
+    }
+    ///Starts the range and bearing line where the cursor is. If the line had already been started,
+    ///its measurement is logged first, so that several can be compared.
+    pub fn mark_measurement(&mut self) {
+        let Some(measurement) = &mut self.measurement else { return };
+        let description = measurement.describe();
+        measurement.anchor = Some(measurement.cursor);
+        if let Some(description) = description {
+            self.log(tr!(Measured, measurement = description));
+        }
+    }
+    fn event_snapshot(&self) -> EventSnapshot {
+        EventSnapshot {
+            planes: self.planes.len(),
+            messages: self.messages.len(),
+            pending_instructions: self.pending_instructions.len(),
+            is_over: self.is_over(),
+            targets: self.planes.iter()
+                //Circling planes change direction every tick.
+                .filter(|plane| !matches!(plane.command, Some(CompleteCommandSegment::Circle(_))))
+                .map(|plane| (plane.callsign, plane.target_flight_level, plane.target_direction, plane.show))
+                .collect(),
+        }
+    }
+    ///How many ticks until the next event, up to `FAST_FORWARD_LIMIT`, found by playing ahead on a
+    ///copy of the game.
+    pub fn ticks_until_event(&self) -> u32 {
+        let mut future = self.clone();
+        future.is_forecast = true;
+        let snapshot = self.event_snapshot();
+        for ticks in 1..=FAST_FORWARD_LIMIT {
+            future.tick();
+            if future.event_snapshot() != snapshot {
+                return ticks;
+            }
+        }
+        FAST_FORWARD_LIMIT
+    }
+    ///Makes ticks 1.5 times shorter or longer, within bounds.
+    pub fn change_tick_rate(&mut self, faster: bool) {
+        let rate = if faster { self.settings.tick_rate.div_f32(1.5) } else { self.settings.tick_rate.mul_f32(1.5) };
+        self.settings.tick_rate = rate.clamp(MIN_TICK_RATE, MAX_TICK_RATE);
+    }
+    ///Changes the zoom level of the radar view, within its bounds.
+    pub fn change_zoom(&mut self, by: i16) {
+        self.zoom = self.zoom.saturating_add_signed(by).clamp(1, MAX_ZOOM);
+    }
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+    pub fn tick_no(&self) -> u32 {
+        self.tick_no
+    }
+    ///Number of planes safely landed or directed to an exit.
+    pub fn score(&self) -> u32 {
+        (self.planes_landed + self.score_bonus).saturating_sub(self.score_penalty)
+    }
+    ///The score of one player in hot-seat mode, counting only the planes they own.
+    pub fn player_score(&self, player: Player) -> u32 {
+        let (earned, lost) = self.player_scores[player.index()];
+        earned.saturating_sub(lost)
+    }
+    ///Adds points earned and lost to the score of the player who owns a plane.
+    fn credit(&mut self, callsign: char, earned: u32, lost: u32) {
+        let (player_earned, player_lost) = &mut self.player_scores[Player::of(callsign).index()];
+        *player_earned += earned;
+        *player_lost += lost;
+    }
+    ///Why the game ended, if it has.
+    pub fn exit_state(&self) -> &[GameStatus] {
+        &self.exit_state
+    }
+    pub fn is_over(&self) -> bool {
+        !self.exit_state.is_empty()
+    }
+    ///The map's goal. Situations have a goal of their own, so the map's is set aside for them.
+    pub fn goal(&self) -> Option<Goal> {
+        self.info.goal.filter(|_| !self.is_puzzle())
+    }
+    ///How much of a goal has been done, up to its target.
+    fn goal_progress(&self, goal: Goal) -> u32 {
+        let progress = match goal {
+            Goal::Traffic(_) => self.planes_announced - (self.planes.len() + self.pending_spawns.len()) as u32,
+            Goal::Planes(_) => self.planes_landed,
+            Goal::Ticks(_) => self.tick_no,
+        };
+        progress.min(goal.target())
+    }
+    ///Whether every plane a `Goal::Traffic` allows has been announced.
+    fn traffic_announced(&self) -> bool {
+        matches!(self.goal(), Some(Goal::Traffic(target)) if self.planes_announced >= target)
+    }
+    ///The radar, with its range as set by `--radar-range` if it was given. A map without a radar of
+    ///its own gets one in its middle.
+    pub fn radar_coverage(&self) -> Option<Radar> {
+        let Some(range) = self.settings.radar_range else { return self.info.radar };
+        let center = self.info.radar.map_or(GroundLocation(self.info.width / 2, self.info.height / 2), |radar| radar.center);
+        Some(Radar { center, range })
+    }
+    ///Whether a plane can be seen in full: it is on the ground or within radar range.
+    fn is_on_radar(&self, plane: &Plane) -> bool {
+        match (plane.location(), self.radar_coverage()) {
+            (Some(location), Some(radar)) => radar.covers(location),
+            _ => true,
+        }
+    }
+    ///Whether the game is a situation being solved, rather than a game with planes of its own.
+    pub fn is_puzzle(&self) -> bool {
+        self.puzzle.is_some()
+    }
+    ///The planes as they are now, along with those announced, for others to solve.
+    pub fn situation(&self) -> Situation {
+        Situation {
+            map: self.info.clone(),
+            tick: self.tick_no,
+            planes: self.planes.clone(),
+            announced: self.pending_spawns.iter().map(|spawn| AnnouncedPlane {
+                plane: spawn.plane.clone(),
+                start: spawn.start,
+                ticks: spawn.due.saturating_sub(self.tick_no),
+            }).collect(),
+        }
+    }
+    ///Saves the situation to a file in `dir` named for the map and tick. Returns the file written
+    ///to.
+    pub fn save_situation(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let name = self.name().chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' }).collect::<String>();
+        let file = dir.join(format!("{name}-{:05}.json", self.tick_no));
+        fs::write(&file, versioned::to_json(&self.situation())?)?;
+        Ok(file)
+    }
+    ///Puts a situation's planes on the map and starts puzzle mode. Planes which don't fit the map
+    ///are left out.
+    pub fn load_situation(&mut self, situation: Situation) {
+        self.puzzle = Some(situation.tick);
+        self.place_situation(situation);
+        self.log(tr!(SituationLoaded, planes = self.planes.len() + self.pending_spawns.len()));
+    }
+    ///Carries on a game saved with its progress, as it was when saved.
+    pub fn resume(&mut self, situation: Situation, progress: Progress) {
+        self.place_situation(situation);
+        Progress {
+            planes_landed: self.planes_landed,
+            planes_announced: self.planes_announced,
+            score_bonus: self.score_bonus,
+            score_penalty: self.score_penalty,
+            player_scores: self.player_scores,
+            next_spawn: self.next_spawn,
+        } = progress;
+        //The planes to come can't be those the game would have had, so they are drawn afresh.
+        self.rng = ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(self.tick_no as u64));
+        self.radio_rng = ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(self.tick_no as u64) ^ RADIO_SEED_SALT);
+        self.pilot_error_rng = ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(self.tick_no as u64) ^ PILOT_ERROR_SEED_SALT);
+        self.log(tr!(GameResumed, tick = self.tick_no));
+    }
+    pub fn progress(&self) -> Progress {
+        Progress {
+            planes_landed: self.planes_landed,
+            planes_announced: self.planes_announced,
+            score_bonus: self.score_bonus,
+            score_penalty: self.score_penalty,
+            player_scores: self.player_scores,
+            next_spawn: self.next_spawn,
+        }
+    }
+    fn place_situation(&mut self, situation: Situation) {
+        self.tick_no = situation.tick;
+        self.planes = situation.planes.into_iter().filter_map(|plane| refit_plane(plane, &self.info)).collect();
+        self.pending_spawns = situation.announced.into_iter()
+            .filter_map(|announced| Some(PendingSpawn {
+                plane: refit_plane(announced.plane, &self.info)?,
+                start: refreshed_destination(announced.start, &self.info)?,
+                due: situation.tick + announced.ticks,
+            }))
+            .collect();
+    }
+    ///Practices a drill on its own map, one round after another.
+    pub fn start_drill(&mut self, drill: Drill) {
+        self.drill = Some(DrillProgress { drill, round: 0, start: self.tick_no });
+        self.advance_drill();
+    }
+    ///Scores the drill's round once it is over, whether its planes were all cleared or something
+    ///went wrong, and sets up the next.
+    fn advance_drill(&mut self) {
+        let Some(progress) = self.drill else { return };
+        let cleared = self.planes.is_empty() && self.pending_spawns.is_empty();
+        if !cleared && !self.is_over() {
+            return;
+        }
+        if self.is_over() {
+            for status in std::mem::take(&mut self.exit_state) {
+                self.log_error(tr!(RoundFailed, round = progress.round, status = status));
+            }
+        } else if progress.round > 0 {
+            let commands = self.stats.planes.iter().filter(|plane| plane.entered > progress.start).map(|plane| plane.commands).sum();
+            let points = Drill::points(commands);
+            self.score_bonus += points;
+            let (round, ticks) = (progress.round, self.tick_no - progress.start);
+            let text = match commands {
+                1 => tr!(RoundResolvedWithOneCommand, round = round, ticks = ticks),
+                _ => tr!(RoundResolved, round = round, ticks = ticks, commands = commands),
+            };
+            self.log_bonus(points, text.clone());
+            self.timeline.record(self.tick_no, EventKind::Landing, vec![Span::plain(text)]);
+        }
+        //Commands waiting for the last round's planes would go to the next round's.
+        self.pending_instructions.clear();
+        let situation = progress.drill.round(&self.info, self.tick_no, &mut self.rng);
+        self.load_situation(situation);
+        self.drill = Some(DrillProgress { round: progress.round + 1, start: self.tick_no, ..progress });
+    }
+    ///Replaces the map's objects with those of a new version of it, as when its file is edited.
+    ///Planes whose location or destination is no longer on the map are removed. If the new version
+    ///has problems, it is not used.
+    pub fn reload_static(&mut self, data: MapStatic) -> Result<()> {
+        let problems = data.problems();
+        if !problems.is_empty() {
+            anyhow::bail!("{}", problems.join(" "));
+        }
+        let mut removed = vec![];
+        for plane in std::mem::take(&mut self.planes) {
+            let callsign = plane.callsign;
+            match refit_plane(plane, &data) {
+                Some(plane) => self.planes.push(plane),
+                None => removed.push(callsign),
+            }
+        }
+        self.pending_spawns = std::mem::take(&mut self.pending_spawns).into_iter()
+            .filter_map(|mut spawn| {
+                spawn.start = refreshed_destination(spawn.start, &data)?;
+                //The plane has not appeared yet, so it appears wherever its start now is.
+                spawn.plane.location = spawn.start.entry();
+                spawn.plane = refit_plane(spawn.plane, &data)?;
+                Some(spawn)
+            })
+            .collect();
+        self.parked_planes.retain(|parked| data.airports.iter().any(|airport| airport.index == parked.airport && airport.stands.is_some()));
+        self.info = data;
+        for callsign in removed {
+            self.log(tr!(PlaneRemoved, plane = callsign));
+        }
+        Ok(())
+    }
+    fn state(&self) -> GameState {
+        GameState {
+            planes: self.planes.clone(),
+            exit_state: self.exit_state.clone(),
+            tick_no: self.tick_no,
+            planes_landed: self.planes_landed,
+            planes_announced: self.planes_announced,
+            score_bonus: self.score_bonus,
+            score_penalty: self.score_penalty,
+            player_scores: self.player_scores,
+            crash_sites: self.crash_sites.clone(),
+            parked_planes: self.parked_planes.clone(),
+            last_handoffs: self.last_handoffs.clone(),
+            pending_instructions: self.pending_instructions.clone(),
+            pending_spawns: self.pending_spawns.clone(),
+            next_spawn: self.next_spawn,
+            conflicts: self.conflicts.clone(),
+            rng: self.rng.clone(),
+            radio_rng: self.radio_rng.clone(),
+            pilot_error_rng: self.pilot_error_rng.clone(),
+            stats: self.stats.mark(self.tick_no),
+            puzzle: self.puzzle,
+            drill: self.drill,
+        }
+    }
+    ///Puts the game back as it was `REWIND_TICKS` ticks ago, or as far back as has been kept, using
+    ///up one of the rewinds. Only casual games can be rewound, even once they are over. Returns
+    ///whether the game was rewound.
+    pub fn rewind(&mut self) -> bool {
+        if !self.settings.casual {
+            self.log_error(tr!(RewindCasualOnly));
+            return false;
+        }
+        if self.rewinds_left == 0 {
+            self.log_error(tr!(NoRewindsLeft));
+            return false;
+        }
+        let Some(state) = self.history.pop_front() else {
+            self.log_error(tr!(NothingToRewind));
+            return false;
+        };
+        self.history.clear();
+        let ticks = self.tick_no - state.tick_no;
+        let stats;
+        GameState {
+            planes: self.planes,
+            exit_state: self.exit_state,
+            tick_no: self.tick_no,
+            planes_landed: self.planes_landed,
+            planes_announced: self.planes_announced,
+            score_bonus: self.score_bonus,
+            score_penalty: self.score_penalty,
+            player_scores: self.player_scores,
+            crash_sites: self.crash_sites,
+            parked_planes: self.parked_planes,
+            last_handoffs: self.last_handoffs,
+            pending_instructions: self.pending_instructions,
+            pending_spawns: self.pending_spawns,
+            next_spawn: self.next_spawn,
+            conflicts: self.conflicts,
+            rng: self.rng,
+            radio_rng: self.radio_rng,
+            pilot_error_rng: self.pilot_error_rng,
+            stats,
+            puzzle: self.puzzle,
+            drill: self.drill,
+        } = state;
+        self.stats.rewind(stats);
+        self.rewinds_left -= 1;
+        self.rejection = None;
+        self.log(tr!(Rewound, ticks = ticks, tick = self.tick_no, left = self.rewinds_left));
+        self.timeline.record(self.tick_no, EventKind::Command, vec![Span::plain(tr!(RewoundEvent, ticks = ticks))]);
+        true
+    }
+    pub fn log(&mut self, text: String) {
+        self.log_spans(vec![Span::plain(text)]);
+    }
+    ///Logs something which went wrong, in red.
+    pub fn log_error(&mut self, text: String) {
+        self.log_spans(vec![Span::new(text, Style::colored(1))]);
+    }
+    ///Logs a warning, in yellow.
+    fn log_warning(&mut self, text: String) {
+        self.log_spans(vec![Span::new(text, Style::colored(3))]);
+    }
+    ///Logs points gained, in green before the text.
+    fn log_bonus(&mut self, points: u32, text: String) {
+        self.log_spans(vec![Span::new(format!("+{points}"), Style::colored(2)), Span::plain(format!(" {text}"))]);
+    }
+    ///Logs points lost, in red before the text.
+    fn log_penalty(&mut self, points: u32, text: String) {
+        self.log_spans(vec![Span::new(format!("-{points}"), Style::colored(1)), Span::plain(format!(" {text}"))]);
+    }
+    pub fn log_spans(&mut self, text: Vec<Span>) {
+        self.messages.push(Message { tick: self.tick_no, text });
     }
     pub fn tick(&mut self) {
-        if self.exit_state.is_some() { return; }
+        if self.is_over() { return; }
+        let _span = profiling::span(if self.is_forecast { "forecast tick" } else { "tick" });
+        if self.settings.casual && self.rewinds_left > 0 {
+            if self.history.len() == REWIND_TICKS {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.state());
+        }
 
+        let (due, waiting) = std::mem::take(&mut self.pending_instructions).into_iter()
+            .partition::<Vec<_>, _>(|instruction| instruction.due <= self.tick_no);
+        self.pending_instructions = waiting;
+        for instruction in due {
+            if let Err(rejection) = self.deliver(instruction.command) {
+                self.rejection = Some(rejection);
+            }
+        }
+
+        self.parked_planes.retain(|parked| parked.until > self.tick_no);
         let mut planes_to_remove = vec![];
+        //Planes which land this tick, which take up stands as soon as they do.
+        let mut landed = vec![];
+        let mut free_stands = self.info.airports.iter()
+            .filter_map(|airport| Some((airport.index, self.free_stands(airport)?)))
+            .collect::<HashMap<_, _>>();
+        //Planes diverted by commands which were waiting for their time to come.
+        let mut diversions = vec![];
+        let mut handoffs = vec![];
         for (i, plane) in self.planes.iter_mut().enumerate() {
-            plane.tick(&self.info);
+            let (destination, level) = (plane.destination, plane.flight_level());
+            if let Err(rejection) = plane.tick(&self.info) {
+                self.rejection = Some(rejection);
+            }
+            //Each plane's transponder reports on its own ticks, so that they don't all go stale at once.
+            if let Some(interval) = self.settings.transponder_interval {
+                plane.reported_flight_level = if (self.tick_no + plane.callsign as u32).is_multiple_of(interval) {
+                    None
+                } else {
+                    Some(plane.reported_flight_level.unwrap_or(level))
+                };
+            }
+            if plane.destination != destination {
+                diversions.push((plane.callsign, plane.destination));
+            }
             if let Location::Flight(loc) = plane.location {
                 let AirLocation(x, y, level) = loc;
                 if level == 0 {
-                    let mut success = false;
-                    for airport in &self.info.airports {
-                        if airport.location == GroundLocation(x, y) {
-                            if <CardinalDirection as Into<OrdinalDirection>>::into(airport.launch_direction) == plane.current_direction {
-                                success = true;
-                                break;
-                            }
-                        }
+                    let airport = self.info.airports.iter().find(|airport| airport.location == GroundLocation(x, y)
+                        && <CardinalDirection as Into<OrdinalDirection>>::into(airport.launch_direction) == plane.current_direction);
+                    let Some(airport) = airport else {
+                        self.exit_state.push(GameStatus::PlaneFailedLanding(plane.callsign));
+                        continue;
+                    };
+                    if self.settings.night && !plane.cleared_to_land {
+                        self.exit_state.push(GameStatus::PlaneLandedUncleared(plane.callsign));
+                        continue;
                     }
-                    if success {
-                        planes_to_remove.push(i);
-                    } else {
-                        self.exit_state = Some(GameStatus::PlaneFailedLanding(plane.callsign));
+                    match free_stands.get_mut(&airport.index) {
+                        Some(0) => self.exit_state.push(GameStatus::PlaneFoundNoStand(plane.callsign)),
+                        free => {
+                            planes_to_remove.push(i);
+                            if let Some(free) = free {
+                                *free -= 1;
+                                landed.push(ParkedPlane { airport: airport.index, until: self.tick_no + STAND_DURATION });
+                            }
+                        },
                     }
                 } else {
                     let mut exited_correctly = false;
+                    let mut missed_levels = false;
                     for exit in &self.info.exits {
-                        if exit.exit_location == loc && exit.exit_direction == plane.current_direction {
+                        if exit.exit_direction != plane.current_direction {
+                            continue;
+                        }
+                        if exit.accepts(loc) {
                             planes_to_remove.push(i);
+                            handoffs.push((plane.callsign, *exit));
                             exited_correctly = true;
                             break;
                         }
+                        missed_levels |= exit.restricts_levels() && GroundLocation::from(exit.exit_location) == GroundLocation(x, y);
                     }
-                    if !exited_correctly && (x == 0 || x == self.info.width-1 || y == 0 || y == self.info.height-1) {
-                        self.exit_state = Some(GameStatus::PlaneExited(plane.callsign));
+                    if !exited_correctly && GroundLocation(x, y).is_on_edge(&self.info) {
+                        self.exit_state.push(if missed_levels { GameStatus::PlaneMissedExitLevels(plane.callsign) } else { GameStatus::PlaneExited(plane.callsign) });
                     }
                 }
             }
         }
-        'check_collision: for plane_a in &self.planes {
-            for plane_b in &self.planes {
-                if !std::ptr::eq(plane_a, plane_b) {
-                    match (plane_a.location, plane_b.location) {
-                        (Location::Flight(AirLocation(ax, ay, az)), Location::Flight(AirLocation(bx, by, bz))) => {
-                            let dx = bx.abs_diff(ax);
-                            let dy = by.abs_diff(ay);
-                            let dz = bz.abs_diff(az);
-                            if dx <= 1 && dy <= 1 && dz <= 1 {
-                                self.exit_state = Some(GameStatus::PlanesCrashed(plane_a.callsign, plane_b.callsign));
-                                break 'check_collision;
-                            }
+        let span = profiling::span(if self.is_forecast { "forecast collisions" } else { "collision detection" });
+        let mut collisions = vec![];
+        for (i, plane_a) in self.planes.iter().enumerate() {
+            for plane_b in &self.planes[i+1..] {
+                if let (Location::Flight(a), Location::Flight(b)) = (plane_a.location, plane_b.location) {
+                    if a.chebyshev_distance(b) <= 1 {
+                        if self.settings.casual {
+                            collisions.push((plane_a.callsign, plane_b.callsign, GroundLocation::from(a)));
+                        } else {
+                            self.exit_state.push(GameStatus::PlanesCrashed(plane_a.callsign, plane_b.callsign));
                         }
-                        _ => {}
                     }
                 }
             }
         }
+        drop(span);
+        for (callsign, destination) in diversions {
+            self.charge_diversion(callsign, destination);
+        }
+        for (callsign, exit) in handoffs {
+            if self.is_saturated(&exit) {
+                self.score_penalty += HANDOFF_PENALTY;
+                self.credit(callsign, 0, HANDOFF_PENALTY);
+                self.log_penalty(HANDOFF_PENALTY, tr!(LeftTooSoon, plane = callsign, exit = Destination::Exit(exit)));
+            }
+            self.last_handoffs.insert(exit.index, self.tick_no);
+        }
+        self.parked_planes.extend(landed);
         for (j, plane) in planes_to_remove.into_iter().enumerate() {
-            self.planes.remove(plane - j);
+            let plane = self.planes.remove(plane - j);
             self.planes_landed += 1;
+            self.credit(plane.callsign, 1, 0);
+            self.timeline.record(self.tick_no, EventKind::Landing, vec![Span::plain(tr!(PlaneReached, plane = plane.callsign, destination = plane.destination, ticks = plane.ticks_active))]);
+            self.sound(Sound::Landing);
+            if let Some(stats) = self.stats.plane(plane.callsign) {
+                stats.finished = Some(self.tick_no);
+                stats.handling_time = plane.ticks_active;
+            }
+            if plane.ticks_active <= plane.par {
+                self.score_bonus += PAR_BONUS;
+                self.credit(plane.callsign, PAR_BONUS, 0);
+                self.log_bonus(PAR_BONUS, tr!(WithinPar, plane = plane.callsign, destination = plane.destination, ticks = plane.ticks_active, par = plane.par));
+            }
+        }
+        //In casual mode, planes which collide are removed and play continues.
+        self.crash_sites.retain(|site| site.until > self.tick_no);
+        let mut crashed = vec![];
+        for (a, b, location) in collisions {
+            //A plane can collide with several others at once, but only crashes once.
+            let newly_crashed = [a, b].into_iter().filter(|callsign| !crashed.contains(callsign)).collect::<Vec<_>>();
+            if newly_crashed.is_empty() {
+                continue;
+            }
+            crashed.extend(&newly_crashed);
+            self.score_penalty += CRASH_PENALTY;
+            //Each player loses the points once, however many of their planes crashed.
+            if Player::of(a) == Player::of(b) {
+                self.credit(a, 0, CRASH_PENALTY);
+            } else {
+                self.credit(a, 0, CRASH_PENALTY);
+                self.credit(b, 0, CRASH_PENALTY);
+            }
+            self.crash_sites.push(CrashSite { location, until: self.tick_no + CRASH_SITE_DURATION });
+            self.log_penalty(CRASH_PENALTY, tr!(Crashed, plane = a, other = b));
+            self.timeline.record(self.tick_no, EventKind::Crash, vec![Span::plain(tr!(Crashed, plane = a, other = b))]);
+            self.alert(false);
+            self.sound(Sound::Crash);
+        }
+        self.planes.retain(|plane| !crashed.contains(&plane.callsign));
+        if self.is_over() {
+            self.alert(false);
+            for status in self.exit_state.clone() {
+                self.timeline.record(self.tick_no, EventKind::Crash, vec![Span::plain(status.to_string())]);
+            }
+        }
+        if self.exit_state.iter().any(|status| matches!(status, GameStatus::PlanesCrashed(..))) {
+            self.sound(Sound::Crash);
         }
-        if self.tick_no % self.settings.plane_spawn_rate == 0 {
+
+        let span = profiling::span(if self.is_forecast { "forecast conflicts" } else { "conflict detection" });
+        let mut conflicts = vec![];
+        for (i, plane_a) in self.planes.iter().enumerate() {
+            for plane_b in &self.planes[i+1..] {
+                if in_conflict(plane_a, plane_b) {
+                    conflicts.push((plane_a.callsign, plane_b.callsign));
+                }
+            }
+        }
+        drop(span);
+        for &(a, b) in &conflicts {
+            if !self.conflicts.contains(&(a, b)) {
+                self.log_warning(tr!(Conflict, plane = a, other = b));
+                self.timeline.record(self.tick_no, EventKind::Conflict, vec![Span::plain(tr!(Conflict, plane = a, other = b))]);
+                self.alert(true);
+                self.sound(Sound::Conflict);
+                if self.is_puzzle() {
+                    let status = GameStatus::PlanesInConflict(a, b);
+                    self.timeline.record(self.tick_no, EventKind::Crash, vec![Span::plain(status.to_string())]);
+                    self.exit_state.push(status);
+                }
+                for callsign in [a, b] {
+                    if let Some(stats) = self.stats.plane(callsign) {
+                        stats.conflicts += 1;
+                    }
+                }
+                self.stats.conflict_began(self.tick_no, a, b);
+            }
+        }
+        self.conflicts = conflicts;
+
+        let (due, waiting) = std::mem::take(&mut self.pending_spawns).into_iter()
+            .partition::<Vec<_>, _>(|spawn| spawn.due <= self.tick_no);
+        self.pending_spawns = waiting;
+        for spawn in due {
+            self.stats.plane_entered(&spawn.plane, spawn.start, self.tick_no);
+            let plane = &spawn.plane;
+            let kind = if plane.is_jet { tr!(Jet) } else { tr!(Prop) };
+            self.timeline.record(self.tick_no, EventKind::Spawn, vec![Span::plain(tr!(PlaneEntered, plane = plane.callsign, kind = kind, start = spawn.start, destination = plane.destination))]);
+            self.planes.push(spawn.plane);
+            self.alert(false);
+            self.sound(Sound::Spawn);
+        }
+        //A held spawn waits for a plane to leave, rather than being skipped.
+        if !self.is_puzzle() && !self.traffic_announced() && self.tick_no >= self.next_spawn && !self.spawn_held() {
             self.generate_plane();
+            self.next_spawn = self.tick_no + self.settings.spawn_pattern.gap(self.settings.plane_spawn_rate, self.tick_no, &mut self.rng);
+        }
+        if self.drill.is_some() {
+            self.advance_drill();
+        } else if let (Some(start), false) = (self.puzzle, self.is_over()) {
+            if self.planes.is_empty() && self.pending_spawns.is_empty() {
+                let status = GameStatus::SituationCleared(self.tick_no + 1 - start);
+                self.timeline.record(self.tick_no, EventKind::Landing, vec![Span::plain(status.to_string())]);
+                self.exit_state.push(status);
+            }
+        }
+        self.log_plane_warnings();
+        self.stats.record_tick(self.tick_no, &self.planes, self.conflicts.len(), self.score());
+        //Explained once, as nothing happens after the game is over.
+        for status in self.exit_state.clone().into_iter().filter(|status| !status.is_success()) {
+            for finding in diagnosis::diagnose(status, self.tick_no, &self.stats, &self.planes) {
+                self.log_spans(vec![Span::new(finding.to_string(), Style::colored(5))]);
+                self.timeline.record(self.tick_no, EventKind::Diagnosis, vec![Span::plain(finding)]);
+            }
         }
         self.tick_no += 1;
+        if let (Some(goal), false) = (self.goal(), self.is_over()) {
+            if self.goal_progress(goal) >= goal.target() {
+                let status = GameStatus::Completed(goal);
+                self.timeline.record(self.tick_no, EventKind::Landing, vec![Span::plain(status.to_string())]);
+                self.exit_state.push(status);
+            }
+        }
+    }
+    ///Whether there are as many planes as there may be, counting those announced, so that no more
+    ///are announced until some leave.
+    fn spawn_held(&self) -> bool {
+        let max_planes = self.settings.max_planes.map_or(CALLSIGN_COUNT, |max| (max as usize).min(CALLSIGN_COUNT));
+        self.planes.len() + self.pending_spawns.len() >= max_planes
+    }
+    ///Whether the next sector is not yet ready for another plane from an exit, as one left by it too
+    ///recently.
+    fn is_saturated(&self, exit: &Exit) -> bool {
+        let (Some(interval), Some(&last)) = (exit.handoff_interval, self.last_handoffs.get(&exit.index)) else { return false };
+        self.tick_no < last + interval as u32
+    }
+    ///Planes on the ground at an airport, which are waiting to take off, have been announced to do
+    ///so, or have landed there.
+    pub fn stands_in_use(&self, airport: &Airport) -> usize {
+        let is_here = |location: Location| matches!(location, Location::Airport(a) if a.index == airport.index);
+        self.planes.iter().filter(|plane| is_here(plane.location)).count()
+            + self.pending_spawns.iter().filter(|spawn| is_here(spawn.plane.location)).count()
+            + self.parked_planes.iter().filter(|parked| parked.airport == airport.index).count()
+    }
+    ///How many more planes there is room for on the ground at an airport, or None if it has no
+    ///limit.
+    fn free_stands(&self, airport: &Airport) -> Option<usize> {
+        Some((airport.stands? as usize).saturating_sub(self.stands_in_use(airport)))
     }
+    ///Announces a plane which will appear a few ticks later.
     fn generate_plane(&mut self) {
-        if self.planes.len() >= 26 {
-            return;
-        }
-        let start = self.generate_location(None, false);
-        let finish = self.generate_location(Some(start), true);
-        let is_jet = random();
+        let Some(start) = self.generate_location(None) else { return };
+        let finish = self.generate_location(Some(start)).expect("planes to only start where they have somewhere to go");
+        let is_jet = self.rng.random();
         let callsign = 'generate: loop {
-            let c = random_range(if is_jet { b'a' ..= b'z' } else { b'A' ..= b'Z' }) as char;
-            for plane in &self.planes {
-                if plane.callsign.to_ascii_lowercase() == c.to_ascii_lowercase() {
+            let c = self.rng.random_range(if is_jet { b'a' ..= b'z' } else { b'A' ..= b'Z' }) as char;
+            for plane in self.planes.iter().chain(self.pending_spawns.iter().map(|spawn| &spawn.plane)) {
+                if plane.callsign.eq_ignore_ascii_case(&c) {
                     continue 'generate;
                 }
             }
             break c;
         };
-        self.planes.push(Plane {
+        //Planes move diagonally as fast as straight.
+        let (start_location, finish_location): (GroundLocation, GroundLocation) = (start.entry().into(), finish.exit().into());
+        let distance = start_location.chebyshev_distance(finish_location) as u32;
+        let mut plane = Plane {
             location: start.entry(),
             destination: finish,
             target_flight_level: start.entry_height(),
             callsign,
             is_jet,
             ticks_active: 0,
+            //Props move every other tick.
+            par: distance * if is_jet { 1 } else { 2 } + PAR_ALLOWANCE,
             current_direction: start.entry_dir(),
             target_direction: start.entry_dir(),
             show: Visibility::Marked,
             command: None,
-        });
+            cleared_for_takeoff: false,
+            cleared_to_land: false,
+            diverted: false,
+            radio_failure: None,
+            note: String::new(),
+            previous_location: start.entry(),
+            warnings: vec![],
+            reported_flight_level: None,
+            trail: Default::default(),
+        };
+        //Chance is only drawn on with radio failures on, so that the seed gives the same planes either
+        //way.
+        if self.settings.radio_failures && self.rng.random_bool(RADIO_FAILURE_CHANCE) {
+            let from = self.rng.random_range(RADIO_FAILURE_START.0..=RADIO_FAILURE_START.1);
+            plane.radio_failure = Some((from, from + self.rng.random_range(RADIO_FAILURE_LENGTH.0..=RADIO_FAILURE_LENGTH.1)));
+        }
+        let notice = self.rng.random_range(ENTRY_NOTICE.0..=ENTRY_NOTICE.1);
+        let kind = if is_jet { tr!(Jet) } else { tr!(Prop) };
+        self.log(tr!(PlaneAnnounced, plane = callsign, kind = kind, start = start, ticks = notice, destination = finish));
+        self.pending_spawns.push(PendingSpawn { plane, start, due: self.tick_no + notice });
+        self.planes_announced += 1;
     }
-    fn generate_location(&self, exclude: Option<Destination>, is_dest: bool) -> Destination {
-        let mut pool = vec![];
-        for exit in &self.info.exits {
-            let candidate = Destination::Exit(*exit);
-            if let Some(exclude) = exclude {
-                if candidate == exclude {
-                    continue;
-                }
-            }
-            pool.push(candidate);
+    ///Exits and airports planes may start at, or finish at if `is_dest`.
+    fn location_pool(&self, is_dest: bool) -> Vec<Destination> {
+        let mut pool = self.info.exits.iter().map(|exit| Destination::Exit(*exit)).collect::<Vec<_>>();
+        if !is_dest || self.settings.allow_landing {
+            pool.extend(self.info.airports.iter().map(|airport| Destination::Airport(*airport)));
         }
-        if !is_dest || self.settings.allow_landing { for airport in &self.info.airports {
-            let candidate = Destination::Airport(*airport);
-            if let Some(exclude) = exclude {
-                if candidate == exclude {
-                    continue;
-                }
-            }
-            pool.push(candidate);
-        } }
+        pool
+    }
+    ///Chooses where a plane starts, or where it finishes if its start is given, according to the
+    ///map's weights and avoiding its forbidden routes. Returns None if no plane can start anywhere.
+    fn generate_location(&mut self, start: Option<Destination>) -> Option<Destination> {
+        //A weight of 0 means never, so such candidates are left out rather than weighted.
+        let finishes = self.location_pool(true).into_iter().filter(|finish| finish.weights().destination > 0).collect::<Vec<_>>();
+        let pool = match start {
+            Some(start) => finishes.into_iter().filter(|finish| self.info.allows_route(&start, finish)).collect::<Vec<_>>(),
+            //Planes only start where they have somewhere to go, and at airports with a free stand.
+            None => self.location_pool(false).into_iter()
+                .filter(|start| start.weights().spawn > 0)
+                .filter(|start| finishes.iter().any(|finish| self.info.allows_route(start, finish)))
+                .filter(|start| !matches!(start, Destination::Airport(airport) if self.free_stands(airport) == Some(0)))
+                .collect(),
+        };
 
-        *pool.choose(&mut rng()).expect("location pool to be non-empty")
+        let is_dest = start.is_some();
+        let weight = |candidate: &Destination| if is_dest { candidate.weights().destination } else { candidate.weights().spawn };
+        pool.choose_weighted(&mut self.rng, weight).ok().copied()
     }
-    ///Searches a command and replaces references with command slots.
-    fn traverse_command(&self, command: &mut CompleteCommandSegment) {
+    ///Searches a command and replaces references with command slots, filling in their placeholders
+    ///with the reference's arguments. Slots referring to other slots are expanded in turn, up to
+    ///`MAX_SLOT_DEPTH` deep.
+    fn traverse_command(&self, command: &mut CompleteCommandSegment) -> Result<(), CommandRejection> {
+        self.expand_refs(command, 0)
+    }
+    fn expand_refs(&self, command: &mut CompleteCommandSegment, depth: usize) -> Result<(), CommandRejection> {
         match command {
-            CompleteCommandSegment::In(CompleteIn { tail, .. }) => self.traverse_command(tail),
-            CompleteCommandSegment::At(CompleteAt { tail, .. }) => self.traverse_command(tail),
+            CompleteCommandSegment::In(CompleteIn { tail, .. }) => self.expand_refs(tail, depth)?,
+            CompleteCommandSegment::Every(CompleteEvery { tail, .. }) => self.expand_refs(tail, depth)?,
+            CompleteCommandSegment::At(CompleteAt { tail, .. }) => self.expand_refs(tail, depth)?,
+            CompleteCommandSegment::Else(CompleteElse { at: CompleteAt { tail, .. }, fallback, .. }) => {
+                self.expand_refs(tail, depth)?;
+                self.expand_refs(fallback, depth)?;
+            },
             CompleteCommandSegment::And(CompleteAnd { left, right }) => {
-                self.traverse_command(left);
-                self.traverse_command(right);
+                self.expand_refs(left, depth)?;
+                self.expand_refs(right, depth)?;
             },
-            CompleteCommandSegment::Ref(CompleteRef(ref r)) => {
-                if let Some(c) = self.command_slots.get(r) {
-                    *command = c.head.clone();
-                } else {
-                    *command = CompleteCommandSegment::None;
+            CompleteCommandSegment::Ref(CompleteRef(key, arguments)) => {
+                //Slots which refer to each other would otherwise be expanded forever.
+                if depth == MAX_SLOT_DEPTH {
+                    return Err(CommandRejection::SlotTooDeep(key.clone()));
                 }
+                let Some(slot) = self.slot_number(key).and_then(|n| self.command_slots.get(&n)) else {
+                    return Err(CommandRejection::EmptySlot(key.clone()));
+                };
+                if let CompleteCommandSegment::None = slot.command {
+                    return Err(CommandRejection::EmptySlot(key.clone()));
+                }
+                let mut expanded = slot.command.clone();
+                let mut arguments = arguments.iter().copied();
+                if !expanded.bind_arguments(&mut arguments) || arguments.next().is_some() {
+                    return Err(CommandRejection::WrongArgumentCount(key.clone()));
+                }
+                *command = expanded;
+                self.expand_refs(command, depth + 1)?;
             },
             _ => {},
         }
+        Ok(())
     }
-    pub fn exec(&mut self, mut command: CompleteCommand) {
-        self.traverse_command(&mut command.head);
-        eprintln!("{command:?}");
+    ///The number of the slot a key refers to. A digit refers to its slot even if it is empty.
+    fn slot_number(&self, key: &SlotKey) -> Option<u16> {
+        match key {
+            SlotKey::Number(n) => Some(*n),
+            SlotKey::Name(_) => self.command_slots.iter()
+                .find(|(_, slot)| key.names(slot.name.as_deref()))
+                .map(|(n, _)| *n),
+        }
+    }
+    ///The first free slot after the digits, for a new name.
+    fn free_named_slot(&self) -> u16 {
+        (FIRST_NAMED_SLOT..).find(|n| !self.command_slots.contains_key(n)).expect("a free slot")
+    }
+    ///Carries out the command being entered, if it is complete.
+    pub fn submit_command(&mut self) {
+        let Some(command) = self.current_command.to_complete() else { return };
+        let mut text = self.current_command.spans();
+        self.current_command.reset();
+        self.rejection = self.exec(command).err();
+        if let Some(rejection) = &self.rejection {
+            text.push(Span::plain(" "));
+            text.push(Span::new(tr!(Rejected, reason = rejection), Style { dim: true, ..Style::default() }));
+        }
+        self.timeline.record(self.tick_no, EventKind::Command, text);
+        self.log_plane_warnings();
+    }
+    fn log_plane_warnings(&mut self) {
+        let warnings = self.planes.iter_mut().flat_map(|plane| std::mem::take(&mut plane.warnings)).collect::<Vec<_>>();
+        for warning in warnings {
+            self.log_warning(warning);
+        }
+    }
+    ///Carries out a command. Rejected commands are not applied to planes.
+    pub fn exec(&mut self, mut command: CompleteCommand) -> Result<(), CommandRejection> {
+        self.traverse_command(&mut command.head)?;
+        //Placeholders are only filled in by references, so any left over in a plane's command have no value.
+        if let CompleteCommandTarget::Plane(_) | CompleteCommandTarget::All = command.target {
+            if !command.head.bind_arguments(&mut std::iter::empty()) {
+                return Err(CommandRejection::UnfilledPlaceholder);
+            }
+        }
+        match command.target {
+            CompleteCommandTarget::Plane(_) | CompleteCommandTarget::All if self.settings.readback || self.settings.congested => {
+                if let CompleteCommandTarget::Plane(p) = command.target {
+                    if !self.planes.iter().any(|plane| plane.callsign.eq_ignore_ascii_case(&p)) {
+                        return Err(CommandRejection::PlaneNotFound(p));
+                    }
+                }
+                let recipient = match command.target {
+                    CompleteCommandTarget::Plane(p) => p.to_string(),
+                    _ => tr!(AllPlanes),
+                };
+                if self.settings.congested && self.radio_rng.random_bool(DROPPED_TRANSMISSION_CHANCE) {
+                    self.log_error(tr!(NoResponse, plane = recipient));
+                    return Ok(());
+                }
+                if self.settings.readback {
+                    let description: CommandSegment = command.head.clone().into();
+                    self.log_spans(tr_spans!(Readback, command = description.preview(), plane = [Span::plain(recipient)]));
+                }
+                let delay = if self.settings.congested { self.settings.transmission_delay } else { 1 };
+                self.pending_instructions.push(PendingInstruction { command, due: self.tick_no + delay });
+            },
+            CompleteCommandTarget::Plane(_) | CompleteCommandTarget::All => return self.deliver(command),
+            CompleteCommandTarget::SlotAction(key, action) => {
+                let number = self.slot_number(&key);
+                match action {
+                    SlotAction::Delete => {
+                        let Some(s) = number else { return Err(CommandRejection::EmptySlot(key)) };
+                        self.command_slots.remove(&s);
+                    },
+                    SlotAction::Edit => {
+                        let Some(slot) = number.and_then(|s| self.command_slots.get(&s)) else { return Err(CommandRejection::EmptySlot(key)) };
+                        self.current_command = Command {
+                            head: slot.command.clone().into(),
+                            target: CommandTarget::Slot(Some(key)),
+                        };
+                        return Ok(());
+                    },
+                    SlotAction::Name(name) => {
+                        let Some(s) = number else { return Err(CommandRejection::EmptySlot(key)) };
+                        let name = if name.is_empty() { None } else { Some(name) };
+                        self.command_slots.entry(s)
+                            .or_insert(CommandSlot { name: None, command: CompleteCommandSegment::None })
+                            .name = name;
+                    },
+                }
+                if let Err(e) = self.save_slots() {
+                    self.log_error(tr!(CouldNotSaveSlots, error = e));
+                }
+            },
+            CompleteCommandTarget::Slot(key) => {
+                let number = self.slot_number(&key);
+                let s = number.unwrap_or_else(|| self.free_named_slot());
+                let name = match key {
+                    SlotKey::Name(name) if number.is_none() => Some(name),
+                    _ => self.command_slots.get(&s).and_then(|slot| slot.name.clone()),
+                };
+                self.command_slots.insert(s, CommandSlot { name, command: command.head });
+                if let Err(e) = self.save_slots() {
+                    self.log_error(tr!(CouldNotSaveSlots, error = e));
+                }
+            },
+            CompleteCommandTarget::SlotList => {
+                self.show_slot_details = !self.show_slot_details;
+            },
+            CompleteCommandTarget::Pending(p) => {
+                let CompleteCommandSegment::Hold(ticks) = command.head else { return Ok(()) };
+                let Some(spawn) = self.pending_spawns.iter_mut().find(|spawn| spawn.plane.callsign.eq_ignore_ascii_case(&p)) else {
+                    return Err(CommandRejection::PendingPlaneNotFound(p));
+                };
+                spawn.due += ticks as u32;
+                let remaining = spawn.due - self.tick_no;
+                self.score_penalty += HOLD_PENALTY;
+                self.credit(p, 0, HOLD_PENALTY);
+                self.log(tr!(Holding, plane = p, ticks = remaining));
+            },
+            CompleteCommandTarget::Query(p) => self.query(p)?,
+        }
+        Ok(())
+    }
+    ///Logs how far a plane is from its destination, and which planes it will come into conflict with
+    ///soon if nothing changes. Nothing in the game is changed.
+    fn query(&mut self, callsign: char) -> Result<(), CommandRejection> {
+        let Some(plane) = self.planes.iter().find(|plane| plane.callsign.eq_ignore_ascii_case(&callsign)) else {
+            return Err(CommandRejection::PlaneNotFound(callsign));
+        };
+        let callsign = plane.callsign;
+        let here: GroundLocation = plane.location.into();
+        let there: GroundLocation = plane.destination.exit().into();
+        let distance = here.chebyshev_distance(there);
+        let ticks = distance as u32 * if plane.is_jet { 1 } else { 2 };
+        let altitude = altitude_unit().long(plane.flight_level());
+        let mut lines = vec![Span::plain(match here.bearing_to(there) {
+            Some(bearing) => tr!(QueryRoute, plane = callsign, destination = plane.destination, distance = distance, bearing = glyphs().direction(bearing), ticks = ticks, altitude = altitude),
+            None => tr!(QueryRouteHere, plane = callsign, destination = plane.destination, distance = distance, ticks = ticks, altitude = altitude),
+        })];
+
+        //Flies copies of every plane ahead with their current commands.
+        let mut future = self.planes.clone();
+        let mut conflicts: Vec<(char, u32)> = vec![];
+        for ticks in 1..=QUERY_LOOKAHEAD {
+            for plane in &mut future {
+                let _ = plane.tick(&self.info);
+            }
+            let Some(plane) = future.iter().find(|plane| plane.callsign == callsign) else { break };
+            for other in &future {
+                if other.callsign != callsign && in_conflict(plane, other) && conflicts.iter().all(|(c, _)| *c != other.callsign) {
+                    conflicts.push((other.callsign, ticks));
+                }
+            }
+        }
+        if conflicts.is_empty() {
+            lines.push(Span::plain(tr!(QueryNoConflicts, plane = callsign, ticks = QUERY_LOOKAHEAD)));
+        }
+        for (other, ticks) in conflicts {
+            lines.push(Span::new(tr!(QueryConflict, plane = callsign, other = other, ticks = ticks), Style::colored(3)));
+        }
+        for line in lines {
+            self.log_spans(vec![line]);
+        }
+        Ok(())
+    }
+    ///Gives a command to the planes it targets.
+    fn deliver(&mut self, command: CompleteCommand) -> Result<(), CommandRejection> {
         match command.target {
             CompleteCommandTarget::Plane(p) => {
+                let Some(plane) = self.planes.iter_mut().find(|plane| plane.callsign.eq_ignore_ascii_case(&p)) else {
+                    return Err(CommandRejection::PlaneNotFound(p));
+                };
+                //Commands for a plane without a radio wait until it can hear them.
+                if plane.is_nordo() {
+                    let (callsign, due) = (plane.callsign, self.tick_no + plane.ticks_until_radio());
+                    self.pending_instructions.push(PendingInstruction { command, due });
+                    self.log(tr!(WaitingForRadio, plane = callsign));
+                    return Ok(());
+                }
+                let mut updated = plane.clone();
+                updated.exec(command.head, &self.info)?;
+                if self.pilot_error_rng.random_bool(self.settings.pilot_error_chance) {
+                    updated.misread(plane, &self.info, &mut self.pilot_error_rng);
+                }
+                let diversion = (updated.destination != plane.destination).then_some((plane.callsign, updated.destination));
+                *plane = updated;
+                if let Some(stats) = self.stats.plane(plane.callsign) {
+                    stats.record_command(self.tick_no);
+                }
+                if let Some((callsign, destination)) = diversion {
+                    self.charge_diversion(callsign, destination);
+                }
+                Ok(())
+            },
+            CompleteCommandTarget::All => {
+                //In strict mode, the command is checked against every plane before any is told.
+                if self.settings.strict {
+                    for plane in self.planes.iter().filter(|plane| plane.show == Visibility::Marked && !plane.is_nordo()) {
+                        plane.clone().exec(command.head.clone(), &self.info)?;
+                    }
+                }
+                let mut result = Ok(());
+                let mut diversions = vec![];
                 for plane in &mut self.planes {
-                    if plane.callsign.to_ascii_lowercase() == p.to_ascii_lowercase() {
-                        plane.exec(command.head, &self.info);
-                        return;
+                    if plane.show == Visibility::Marked && plane.is_nordo() {
+                        let command = CompleteCommand { target: CompleteCommandTarget::Plane(plane.callsign), head: command.head.clone() };
+                        self.pending_instructions.push(PendingInstruction { command, due: self.tick_no + plane.ticks_until_radio() });
+                    } else if plane.show == Visibility::Marked {
+                        let mut updated = plane.clone();
+                        match updated.exec(command.head.clone(), &self.info) {
+                            Ok(_) => {
+                                if self.pilot_error_rng.random_bool(self.settings.pilot_error_chance) {
+                                    updated.misread(plane, &self.info, &mut self.pilot_error_rng);
+                                }
+                                if updated.destination != plane.destination {
+                                    diversions.push((plane.callsign, updated.destination));
+                                }
+                                *plane = updated;
+                                if let Some(stats) = self.stats.plane(plane.callsign) {
+                                    stats.record_command(self.tick_no);
+                                }
+                            },
+                            Err(rejection) => result = result.and(Err(rejection)),
+                        }
                     }
                 }
-                eprintln!("Plane {p} not found.");
+                for (callsign, destination) in diversions {
+                    self.charge_diversion(callsign, destination);
+                }
+                result
             },
-            CompleteCommandTarget::Slot(s) => {
-                self.command_slots.insert(s, command);
+            CompleteCommandTarget::Slot(_) | CompleteCommandTarget::SlotAction(..) | CompleteCommandTarget::SlotList | CompleteCommandTarget::Pending(_) | CompleteCommandTarget::Query(_) => unreachable!("only commands for planes are delivered"),
+        }
+    }
+    fn charge_diversion(&mut self, callsign: char, destination: Destination) {
+        self.score_penalty += DIVERT_PENALTY;
+        self.credit(callsign, 0, DIVERT_PENALTY);
+        self.log_penalty(DIVERT_PENALTY, tr!(Diverted, plane = callsign, destination = destination));
+        if let Some(stats) = self.stats.plane(callsign) {
+            stats.destination = destination.to_string();
+        }
+    }
+    fn slots_file() -> Option<PathBuf> {
+        paths::config_dir().map(|dir| dir.join("slots.json"))
+    }
+    ///Loads command slots saved by a previous session, if there are any.
+    pub fn load_slots(&mut self) -> Result<()> {
+        let Some(file) = Self::slots_file() else { return Ok(()) };
+        if !fs::exists(&file)? {
+            return Ok(());
+        }
+        let slots: HashMap<u16, CommandSlot> = versioned::from_json(&fs::read(file)?)?;
+        let (slots, unusable) = slots.into_iter().partition::<HashMap<_, _>, _>(|(_, slot)| slot.command.is_for_planes());
+        self.command_slots = slots;
+        if !unusable.is_empty() {
+            let mut numbers = unusable.keys().map(|n| format!("%{n}")).collect::<Vec<_>>();
+            numbers.sort();
+            anyhow::bail!("slots {} hold commands which can't be given to planes, and were left empty", numbers.join(", "));
+        }
+        Ok(())
+    }
+    ///Loads the macro library into command slots, replacing what was in them. Macros for every map
+    ///are loaded first, then those for this map, so that they take priority.
+    pub fn load_macros(&mut self) -> Result<()> {
+        let (general, specific) = macros::load_library()?.into_iter()
+            .partition::<Vec<_>, _>(|m| m.map.is_none());
+        let specific = specific.into_iter().filter(|m| m.map.as_ref() == Some(&self.info.name)).collect::<Vec<_>>();
+        let mut unusable = vec![];
+        for m in general.into_iter().chain(specific) {
+            let key = match (m.digit, &m.name) {
+                (Some(digit), _) => SlotKey::Number(digit),
+                (None, Some(name)) => SlotKey::Name(name.clone()),
+                (None, None) => continue,
+            };
+            //The library is edited by hand, so it can hold commands which could never be carried out.
+            if !m.command.is_for_planes() {
+                unusable.push(format!("%{key}"));
+                continue;
             }
+            let s = self.slot_number(&key).unwrap_or_else(|| self.free_named_slot());
+            let name = m.name.or_else(|| self.command_slots.get(&s).and_then(|slot| slot.name.clone()));
+            self.command_slots.insert(s, CommandSlot { name, command: m.command });
         }
+        if !unusable.is_empty() {
+            anyhow::bail!("macros for {} hold commands which can't be given to planes, and were skipped", unusable.join(", "));
+        }
+        Ok(())
+    }
+    ///Saves every command slot to the macro library, as macros for this map. Returns the file
+    ///written to.
+    pub fn save_macros(&self) -> Result<PathBuf> {
+        let mut slots = self.command_slots.iter()
+            .filter(|(_, slot)| !matches!(slot.command, CompleteCommandSegment::None))
+            .collect::<Vec<_>>();
+        slots.sort_by_key(|(n, _)| **n);
+        let macros = slots.into_iter().map(|(n, slot)| Macro {
+            map: Some(self.name().to_string()),
+            digit: (*n < FIRST_NAMED_SLOT).then_some(*n),
+            name: slot.name.clone(),
+            command: slot.command.clone(),
+        }).collect();
+        macros::save_library(self.name(), macros)
+    }
+    fn save_slots(&self) -> Result<()> {
+        let Some(file) = Self::slots_file() else { return Ok(()) };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(file, versioned::to_json(&self.command_slots)?)?;
+        Ok(())
+    }
+    ///Writes the whole board, as it is now, to a file named for the tick in `dir`, in the format
+    ///`settings.frame_format`.
+    pub fn save_board(&self, dir: &Path) -> Result<PathBuf> {
+        let format = self.settings.frame_format;
+        let board = self.board();
+        fs::create_dir_all(dir)?;
+        let file = dir.join(format!("{:05}.{}", self.tick_no, format.extension()));
+        fs::write(&file, format.export(&board, &format!("{}, tick {}", self.name(), self.tick_no)))?;
+        Ok(file)
     }
-    pub fn render(&self, output: &mut impl Write) -> Result<()> {
-        let mut grid = RenderGrid::new(self.info.width, self.info.height, &self.current_command);
-        for mark in &self.info.path_markers {
-            grid.add(mark);
+    ///The whole radar, planes and all, as it is drawn.
+    pub fn board(&self) -> CellGrid {
+        let grid = self.radar(true);
+        let board = grid.render((0, 0), (self.info.width, self.info.height));
+        self.grid_buffer.replace(grid.into_buffer());
+        board
+    }
+    ///The radar's grid, with everything but the planes if `with_planes` is not set.
+    fn radar(&self, with_planes: bool) -> RenderGrid<'_> {
+        let cell_width = self.info.cell_width();
+        let mut grid = RenderGrid::new(self.info.width, self.info.height, cell_width, self.zoom, &self.current_command, self.grid_buffer.take());
+        //At night, only the lights around each runway can be seen.
+        let is_lit = |mark: &GroundLocation| self.info.airports.iter().any(|airport| airport.location.chebyshev_distance(*mark) <= FINAL_APPROACH_LENGTH as u16);
+        for mark in self.info.path_markers.iter().chain(&self.info.airway_markers()) {
+            if !self.settings.night || is_lit(mark) {
+                grid.add(mark);
+            }
+        }
+        if self.show_trails {
+            for plane in &self.planes {
+                grid.add_trail(plane.trail.iter());
+            }
+        }
+        if let Some(Measurement { anchor: Some(anchor), cursor }) = self.measurement {
+            grid.add_line(&anchor.line_to(cursor));
         }
         for exit in &self.info.exits {
             grid.add(exit);
+            if self.is_saturated(exit) {
+                grid.tint(exit.entry_location.into(), 3);
+            }
         }
         for beacon in &self.info.beacons {
             grid.add(beacon);
+            //A reminder of which beacons commands are waiting on.
+            if self.planes_waiting_on(CompletePointOfInterest::Beacon(Argument::Value(beacon.index))) > 0 {
+                grid.embolden(beacon.location);
+            }
         }
         for airport in &self.info.airports {
             grid.add(airport);
         }
-        for plane in &self.planes {
-            grid.add(plane);
+        for site in &self.crash_sites {
+            grid.add(site);
         }
-
+        if with_planes {
+            for plane in &self.planes {
+                match plane.location() {
+                    Some(location) if !self.is_on_radar(plane) => grid.add(&PrimaryTarget(location)),
+                    _ => grid.add(plane),
+                }
+            }
+            grid.declutter();
+        }
+        //Shows where the plane being given a command needs to go.
+        if let CommandTarget::Plane(callsign) = self.current_command.target {
+            if let Some(destination) = self.planes.iter().find(|plane| plane.callsign.eq_ignore_ascii_case(&callsign)).and_then(|plane| plane.destination.location()) {
+                grid.highlight(destination);
+            }
+        }
+        if let Some(measurement) = self.measurement {
+            grid.highlight(measurement.cursor);
+            if let Some(anchor) = measurement.anchor {
+                grid.highlight(anchor);
+            }
+        }
+        grid
+    }
+    ///Draws the game. `progress` is how far through the current tick it is, from 0 to 1, which is
+    ///used to animate planes between cells when `settings.animate` is set.
+    pub fn render(&self, output: &mut impl Write, progress: f32) -> Result<()> {
+        let _span = profiling::span("render");
+        let cell_width = self.info.cell_width();
+        //While animating, planes are drawn over the grid instead, between cells.
+        let grid = self.radar(!self.settings.animate);
         write!(output, "{}{}", termion::cursor::Goto(1, 1), termion::clear::All)?;
-        write!(output, "{}", grid.render())?;
-        let table_left = self.info.width * 2 + 2;
+        let view_origin = self.view_origin();
+        let view_size = self.view_size();
+        let mut radar = String::new();
+        grid.render(view_origin, view_size).write_ansi(&mut radar)?;
+        write!(output, "{radar}")?;
+        self.grid_buffer.replace(grid.into_buffer());
+        if self.settings.animate {
+            for plane in &self.planes {
+                let (Some(GroundLocation(x, y)), Location::Flight(previous)) = (plane.location(), plane.previous_location) else { continue };
+                let on_radar = self.is_on_radar(plane);
+                let GroundLocation(previous_x, previous_y) = previous.into();
+                //Position in cells relative to the view, between where the plane was and where it is now.
+                let glide = |from: u16, to: u16, origin: u16| from as f32 + (to as f32 - from as f32) * progress.clamp(0.0, 1.0) - origin as f32;
+                let column = (glide(previous_x, x, view_origin.0) * (cell_width * self.zoom) as f32).round();
+                let row = (glide(previous_y, y, view_origin.1) * self.zoom as f32).round();
+                if column < 0.0 || row < 0.0 || column + (cell_width * self.zoom) as f32 > (view_size.0 * cell_width * self.zoom) as f32 || row >= (view_size.1 * self.zoom) as f32 {
+                    continue;
+                }
+                let spans = if on_radar { <Plane as GridRenderable>::spans(plane, &self.current_command) } else { PrimaryTarget(GroundLocation(x, y)).spans(&self.current_command) };
+                let spans = styled::clip(&spans, (cell_width * self.zoom) as usize);
+                write!(output, "{}{}", termion::cursor::Goto(column as u16 + 1, row as u16 + 1), Ansi(&spans))?;
+            }
+        }
+        let table_left = view_size.0 * cell_width * self.zoom + 2;
+        let grid_bottom = view_size.1 * self.zoom;
+        if self.is_viewport_active() {
+            let mut minimap = Minimap::new(self.info.width, self.info.height, (view_origin, view_size));
+            for plane in &self.planes {
+                if let Some(location) = plane.location() {
+                    minimap.add(location);
+                }
+            }
+            let mut text = String::new();
+            minimap.render().write_ansi(&mut text)?;
+            let minimap_left = (table_left - 1).saturating_sub(minimap.width + 2).max(1);
+            write!(output, "{}{text}", termion::cursor::Goto(minimap_left, 1))?;
+        }
         let mut table_top = 3;
-        write!(output, "{}Time: {:<4} Score: {:<4}", termion::cursor::Goto(table_left, 1), self.tick_no, self.planes_landed)?;
-        write!(output, "{}\x1b[1mplane dest cmd\x1b[0m", termion::cursor::Goto(table_left, 2))?;
-        for plane in &self.planes {
-            write!(output, "{}{}", termion::cursor::Goto(table_left, table_top), <Plane as ListRenderable>::render(plane, &self.current_command))?;
+        if self.settings.hot_seat {
+            let scores = Player::ALL.map(|player| format!("{player} {:<3}", self.player_score(player)));
+            write!(output, "{}{} {} ", termion::cursor::Goto(table_left, 1), tr!(Time, tick = format!("{:<4}", self.tick_no)), tr!(Score, score = scores.join(" ")))?;
+        } else {
+            write!(output, "{}{} {} ", termion::cursor::Goto(table_left, 1), tr!(Time, tick = format!("{:<4}", self.tick_no)), tr!(Score, score = format!("{:<4}", self.score())))?;
+        }
+        let mut flags = vec![match self.clock {
+            Clock::Running => Span::plain(tr!(Rate, seconds = format!("{:.2}", self.settings.tick_rate.as_secs_f32()))),
+            Clock::Stepping => Span::new(tr!(Stepping), Style { reverse: true, ..Style::default() }),
+        }];
+        if let Some(progress) = self.drill {
+            flags.extend([Span::plain(" "), Span::new(tr!(DrillRound, round = progress.round), Style::colored(6))]);
+        } else if self.is_puzzle() {
+            flags.extend([Span::plain(" "), Span::new(tr!(Puzzle), Style::colored(6))]);
+        } else if self.spawn_held() {
+            flags.extend([Span::plain(" "), Span::new(tr!(SpawnHeld), Style::colored(3))]);
+        }
+        if self.settings.night {
+            flags.extend([Span::plain(" "), Span::new(tr!(Night), Style::colored(4))]);
+        }
+        write!(output, "{}", Ansi(&flags))?;
+        let mut heading = vec![Span::new(tr!(ListHeading), Style { bold: true, ..Style::default() })];
+        if self.list_view.sort != ListSort::Spawn || self.list_view.marked_only {
+            heading.push(label(format!(" {}{}{}",
+                self.list_view.sort,
+                if self.list_view.sort != ListSort::Spawn && self.list_view.marked_only { ", " } else { "" },
+                if self.list_view.marked_only { tr!(MarkedOnly) } else { String::new() },
+            )));
+        }
+        write!(output, "{}{}", termion::cursor::Goto(table_left, 2), Ansi(&heading))?;
+        for line in self.status_lines() {
+            write!(output, "{}{}", termion::cursor::Goto(table_left, table_top), Ansi(&line))?;
             table_top += 1;
         }
-        match self.exit_state {
-            None => write!(output, "{}\x1b[0m{}", termion::cursor::Goto(1, self.info.height + 2), self.current_command)?,
-            Some(msg) => write!(output, "{}\x1b[0m{}", termion::cursor::Goto(1, self.info.height + 2), msg)?,
+        let list_lines = self.list_lines();
+        let rows = self.list_rows();
+        let first = self.list_view.scroll.min(list_lines.len().saturating_sub(rows));
+        let last = list_lines.len().min(first + rows);
+        if list_lines.len() > rows {
+            write!(output, " {}", Ansi(&[label(tr!(ListRange, first = first + 1, last = last, total = list_lines.len()))]))?;
+        }
+        for line in &list_lines[first..last] {
+            write!(output, "{}{}", termion::cursor::Goto(table_left, table_top), Ansi(line))?;
+            table_top += 1;
+        }
+        table_top += 1;
+        for message in self.messages.iter().rev().take(MESSAGE_LOG_LENGTH).rev() {
+            write!(output, "{}{} {}", termion::cursor::Goto(table_left, table_top), Ansi(&[label(format!("{:<4}", message.tick))]), Ansi(&message.text))?;
+            table_top += 1;
+        }
+        match self.measurement {
+            Some(measurement) if self.exit_state.is_empty() => {
+                let text = measurement.describe().unwrap_or_else(|| match measurement.anchor {
+                    None => tr!(MeasureStart),
+                    Some(_) => tr!(MeasureEnd),
+                });
+                write!(output, "{}{}", termion::cursor::Goto(1, grid_bottom + 2), tr!(Measuring, measurement = text))?;
+            },
+            None if self.exit_state.is_empty() => {
+                write!(output, "{}{}", termion::cursor::Goto(1, grid_bottom + 2), Ansi(&self.current_command.spans()))?;
+                if let Some(info) = self.command_target_info() {
+                    let info = info.into_iter().map(|span| Span { style: Style { dim: true, ..span.style }, ..span }).collect::<Vec<_>>();
+                    write!(output, "  {}", Ansi(&info))?;
+                }
+            },
+            _ => {},
+        }
+        for (i, status) in self.exit_state.iter().enumerate() {
+            let style = Style::colored(if status.is_success() { 2 } else { 1 });
+            write!(output, "{}{}", termion::cursor::Goto(1, grid_bottom + 2 + i as u16), Ansi(&[Span::new(status.to_string(), style)]))?;
+        }
+        //Each thing that ended the game gets its own line, pushing everything beneath down.
+        let status_lines = self.exit_state.len().max(1) as u16;
+        if let Some(rejection) = &self.rejection {
+            write!(output, "{}{}", termion::cursor::Goto(1, grid_bottom + 2 + status_lines), Ansi(&[Span::new(rejection.to_string(), Style::colored(1))]))?;
         }
 
-        let mut slot_top = self.info.height + 4;
+        let mut slot_top = grid_bottom + 3 + status_lines;
         let mut sorted_slots = self.command_slots.iter()
-            .collect::<Vec<(&u16, &CompleteCommand)>>();
+            .collect::<Vec<(&u16, &CommandSlot)>>();
         sorted_slots.sort_by(|a, b| u16::cmp(a.0, b.0));
 
-        for (_, command) in sorted_slots {
-            write!(output, "{}{}{}", termion::cursor::Goto(1, slot_top), command.target.as_text(), command.render(true))?;
+        for (index, slot) in sorted_slots {
+            //Slots past the digits can only be referred to by name, so they are shown by it.
+            let key = match &slot.name {
+                Some(name) if *index >= FIRST_NAMED_SLOT => SlotKey::Name(name.clone()),
+                _ => SlotKey::Number(*index),
+            };
+            let mut line = [CompleteCommandTarget::Slot(key).spans(), slot.command.spans(true)].concat();
+            if let (Some(name), true) = (&slot.name, *index < FIRST_NAMED_SLOT) {
+                line.push(Span::plain(" "));
+                line.push(Span::new(name.clone(), Style { bold: true, ..Style::default() }));
+            }
+            if self.show_slot_details {
+                let description: CommandSegment = slot.command.clone().into();
+                line.push(label(format!(" ({description})")));
+            }
+            write!(output, "{}{}", termion::cursor::Goto(1, slot_top), Ansi(&line))?;
             slot_top += 1;
         }
+        if self.current_command.target == CommandTarget::Pending(None) {
+            for spawn in &self.pending_spawns {
+                let plane = &spawn.plane;
+                let line = [
+                    label(format!("^")),
+                    Span::new(plane.callsign.to_string(), Style { bold: true, ..Style::default() }),
+                    Span::plain(format!(" {} {} {}", tr!(EntersIn, ticks = spawn.due.saturating_sub(self.tick_no)), glyphs().arrow, plane.destination)),
+                ];
+                write!(output, "{}{}", termion::cursor::Goto(1, slot_top), Ansi(&line))?;
+                slot_top += 1;
+            }
+        }
+        if let CommandSegment::Procedure(None) = self.current_command.current_segment() {
+            for procedure in &self.info.procedures {
+                let kind = match procedure.kind {
+                    ProcedureKind::Departure => tr!(DepartureFrom, airport = procedure.airport),
+                    ProcedureKind::Arrival => tr!(ArrivalTo, airport = procedure.airport),
+                };
+                let line = [
+                    Span::new(format!("P{}", procedure.index), Style { bold: true, ..Style::default() }),
+                    Span::plain(format!(" {} ", procedure.name)),
+                    label(format!("({kind})")),
+                ];
+                write!(output, "{}{}", termion::cursor::Goto(1, slot_top), Ansi(&line))?;
+                slot_top += 1;
+            }
+        }
 
         output.flush()?;
 
         Ok(())
     }
 }
+
+///The airport or exit with the same index in a new version of the map, if it still has one.
+fn refreshed_destination(destination: Destination, map: &MapStatic) -> Option<Destination> {
+    match destination {
+        Destination::Airport(airport) => map.airports.iter().find(|a| a.index == airport.index).map(|a| Destination::Airport(*a)),
+        Destination::Exit(exit) => map.exits.iter().find(|e| e.index == exit.index).map(|e| Destination::Exit(*e)),
+    }
+}
+
+///Moves a plane onto a new version of the map, or returns None if its location or destination is
+///no longer on it.
+fn refit_plane(mut plane: Plane, map: &MapStatic) -> Option<Plane> {
+    plane.destination = refreshed_destination(plane.destination, map)?;
+    plane.location = match plane.location {
+        Location::Airport(airport) => refreshed_destination(Destination::Airport(airport), map)?.entry(),
+        Location::Flight(location) if GroundLocation::from(location).is_inside(map) => plane.location,
+        Location::Flight(_) => return None,
+    };
+    //Otherwise a plane at an airport which moved would glide to it.
+    plane.previous_location = plane.location;
+    Some(plane)
+}
+
+///Whether two planes are close enough, horizontally and in flight levels, to be in conflict.
+fn in_conflict(a: &Plane, b: &Plane) -> bool {
+    let (Location::Flight(a), Location::Flight(b)) = (a.location, b.location) else { return false };
+    a.chebyshev_distance(b) <= CONFLICT_DISTANCE
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_unversioned_maps() {
+        let mut map: serde_json::Value = serde_json::de::from_slice(include_bytes!("../maps/crossing.json")).expect("bundled map to parse");
+        map.as_object_mut().expect("map to be an object").remove("version");
+        let map = MapStatic::from_value(map).expect("map to load");
+        assert_eq!(map.version, MAP_VERSION);
+        assert!(map.problems().is_empty());
+    }
+
+    #[test]
+    fn finds_forbidden_routes_which_stop_spawns() {
+        let mut map = MapStatic::from_value(serde_json::de::from_slice(include_bytes!("../maps/diagonal.json")).expect("bundled map to parse")).expect("map to load");
+        map.forbidden_routes = vec![(format!("E0"), format!("e1"))];
+        let (e0, e1) = (Destination::Exit(map.exits[0]), Destination::Exit(map.exits[1]));
+        assert!(!map.allows_route(&e0, &e1));
+        assert!(map.allows_route(&e1, &e0));
+        assert!(map.problems().is_empty());
+
+        map.forbidden_routes = (0..4).flat_map(|from| (0..4).map(move |to| (format!("E{from}"), format!("E{to}")))).collect();
+        assert_eq!(map.problems(), [format!("No plane can be spawned, as every route from a spawn point to an exit is forbidden.")]);
+        map.forbidden_routes.push((format!("E0"), format!("A3")));
+        assert_eq!(map.problems().len(), 2);
+    }
+
+    #[test]
+    fn finds_tick_rates_which_are_not_times() {
+        let mut map = crossing_map();
+        for rate in [-1.0, f32::NAN, f32::INFINITY] {
+            map.recommended.tick_rate = Some(rate);
+            assert_eq!(map.problems().len(), 1);
+        }
+        map.recommended.tick_rate = Some(0.5);
+        assert!(map.problems().is_empty());
+    }
+
+    #[test]
+    fn never_chooses_locations_weighted_0() {
+        use clap::Parser;
+        let mut map = crossing_map();
+        for exit in &mut map.exits {
+            exit.weights = Some(crate::map_objects::SpawnWeights { spawn: 0, destination: 0 });
+        }
+        for airport in &mut map.airports {
+            airport.weights = Some(crate::map_objects::SpawnWeights { spawn: 0, destination: 0 });
+        }
+        let mut game = Map::new(crate::Args::parse_from(["atc"]).into(), map, 0);
+        assert_eq!(game.generate_location(None), None);
+        for _ in 0..100 {
+            game.tick();
+        }
+        assert!(game.planes.is_empty() && game.pending_spawns.is_empty());
+    }
+
+    #[test]
+    fn rejects_newer_maps() {
+        let mut map: serde_json::Value = serde_json::de::from_slice(include_bytes!("../maps/crossing.json")).expect("bundled map to parse");
+        map["version"] = (MAP_VERSION + 1).into();
+        assert!(MapStatic::from_value(map).is_err());
+    }
+
+    #[test]
+    fn situations_keep_their_map() {
+        let map = crossing_map();
+        let situation = Situation { map: map.clone(), tick: 42, planes: vec![], announced: vec![] };
+        let saved = versioned::to_json(&situation).expect("situation to serialize");
+        let loaded: Situation = versioned::from_json(saved.as_bytes()).expect("situation to load");
+        assert!(loaded.map.problems().is_empty());
+        assert_eq!((loaded.tick, loaded.map.name, loaded.map.exits.len()), (42, map.name, map.exits.len()));
+    }
+
+    #[test]
+    fn rewinds_casual_games() {
+        use clap::Parser;
+        let map = crossing_map();
+        let mut game = Map::new(crate::Args::parse_from(["atc", "--casual"]).into(), map.clone(), 7);
+        let (mut planes, mut stats) = (vec![], vec![]);
+        for _ in 0..15 {
+            planes.push(format!("{:?}", game.planes));
+            stats.push(format!("{:?}", game.stats));
+            game.tick();
+        }
+        assert!(game.rewind());
+        assert_eq!((game.tick_no, game.rewinds_left, format!("{:?}", game.planes)), (15 - REWIND_TICKS as u32, 2, planes[15 - REWIND_TICKS].clone()));
+        assert_eq!(format!("{:?}", game.stats), stats[15 - REWIND_TICKS]);
+        assert!(!game.rewind());
+        game.tick();
+        assert_eq!(format!("{:?}", game.planes), planes[16 - REWIND_TICKS]);
+
+        let mut game = Map::new(crate::Args::parse_from(["atc"]).into(), map, 7);
+        game.tick();
+        assert!(!game.rewind());
+    }
+
+    #[test]
+    fn pilot_errors_dont_change_which_planes_come() {
+        use clap::Parser;
+        let map = crossing_map();
+        let play = |args: &[&str]| {
+            let mut game = Map::new(crate::Args::parse_from(args).into(), map.clone(), 3);
+            while !game.is_over() && game.tick_no < 60 {
+                for callsign in game.planes.iter().map(|plane| plane.callsign).collect::<Vec<_>>() {
+                    let _ = game.exec(typed(&format!("{callsign}a9")));
+                }
+                game.tick();
+            }
+            game
+        };
+        let (steady, erring) = (play(&["atc", "-p", "3"]), play(&["atc", "-p", "3", "--pilot-errors", "100"]));
+        //A misread can end the game, after which no more planes come.
+        let arrivals = |game: &Map| game.stats.planes.iter()
+            .filter(|plane| plane.entered < erring.tick_no)
+            .map(|plane| (plane.callsign, plane.start.clone(), plane.destination.clone(), plane.entered))
+            .collect::<Vec<_>>();
+        assert!(arrivals(&erring).len() > 2);
+        assert_eq!(arrivals(&steady), arrivals(&erring));
+    }
+
+    #[test]
+    fn strict_mode_gives_a_command_to_no_plane_if_any_rejects_it() {
+        let play = |strict: bool| {
+            let mut game = crossing();
+            game.settings.strict = strict;
+            while game.planes.is_empty() {
+                game.tick();
+            }
+            //One plane waits on the ground and another flies, so only the first can take off.
+            let mut grounded = game.planes[0].clone();
+            grounded.callsign = if grounded.callsign == 'z' { 'y' } else { 'z' };
+            grounded.location = Location::Airport(game.info.airports[0]);
+            game.planes.push(grounded);
+            for plane in &mut game.planes {
+                plane.show = Visibility::Marked;
+            }
+            let result = game.exec(typed("!l"));
+            (result, game.planes.iter().filter(|plane| plane.cleared_for_takeoff).count())
+        };
+        let (result, cleared) = play(false);
+        assert!(result.is_err());
+        assert_eq!(cleared, 1);
+        let (result, cleared) = play(true);
+        assert!(result.is_err());
+        assert_eq!(cleared, 0);
+    }
+
+    #[test]
+    fn repeating_commands_let_the_next_one_run() {
+        let mut game = crossing();
+        while game.planes.is_empty() {
+            game.tick();
+        }
+        let mut plane = game.planes[0].clone();
+        assert_eq!(plane.exec(typed("am~5&\"hi").head, &game.info), Ok(true));
+        assert_eq!(plane.note, "hi");
+        assert!(matches!(plane.command, Some(CompleteCommandSegment::Every(_))));
+
+        //A right side which waits keeps the repetition going beside it.
+        let mut plane = game.planes[0].clone();
+        assert_eq!(plane.exec(typed("am~5&a9@*0").head, &game.info), Ok(false));
+        for _ in 0..3 {
+            plane.tick(&game.info).expect("command to carry on");
+        }
+        let Some(CompleteCommandSegment::And(CompleteAnd { left, right })) = &plane.command else { panic!("both sides to be kept") };
+        assert!(matches!((&**left, &**right), (CompleteCommandSegment::Every(_), CompleteCommandSegment::At(_))));
+    }
+
+    ///The bundled crossing map.
+    pub(crate) fn crossing_map() -> MapStatic {
+        MapStatic::from_value(serde_json::de::from_slice(include_bytes!("../maps/crossing.json")).expect("bundled map to parse")).expect("map to load")
+    }
+
+    ///A game on the bundled crossing map, with the default settings and seed 0.
+    fn crossing() -> Map {
+        use clap::Parser;
+        Map::new(crate::Args::parse_from(["atc"]).into(), crossing_map(), 0)
+    }
+
+    ///The command typed as `text`, as if entered at the command input.
+    fn typed(text: &str) -> CompleteCommand {
+        let mut command = Command::default();
+        for letter in text.chars() {
+            command.input(letter);
+        }
+        command.to_complete().expect("command to be complete")
+    }
+
+    #[test]
+    fn expands_slots_which_refer_to_slots() {
+        let mut game = crossing();
+        //Slots loaded from a file can refer to others, which typed commands expand as they are stored.
+        let reference = |n| CommandSlot { name: None, command: CompleteCommandSegment::Ref(CompleteRef(SlotKey::Number(n), vec![])) };
+        game.command_slots.insert(2, CommandSlot { name: None, command: typed("aa9").head });
+        game.command_slots.insert(1, reference(2));
+        game.command_slots.insert(3, reference(3));
+        let mut command = typed("a%1").head;
+        game.traverse_command(&mut command).expect("slot 1 to expand");
+        assert_eq!(format!("{command:?}"), format!("{:?}", typed("aa9").head));
+        let mut command = typed("a%3").head;
+        assert_eq!(game.traverse_command(&mut command), Err(CommandRejection::SlotTooDeep(SlotKey::Number(3))));
+    }
+}
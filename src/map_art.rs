@@ -0,0 +1,282 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{direction::{CardinalDirection, OrdinalDirection}, location::{AirLocation, GroundLocation}, map::{MapStatic, MAP_VERSION}, map_objects::{Airport, Airway, Beacon, CrossingRestriction, Exit, Radar, SpawnWeights}};
+
+///Separates the header from the grid.
+const SEPARATOR: &str = "---";
+///Flight levels planes enter and leave at, unless the header gives others.
+const ENTRY_LEVEL: u16 = 7;
+const EXIT_LEVEL: u16 = 9;
+
+///Compiles a map drawn as text. A header of `key: value` lines comes first, then a line containing
+///only `---`, then the grid, drawn two characters to a cell as on the radar: `. ` for nothing, `+ `
+///for a path marker, `*1` for beacon 1, `E2` for exit 2, and `^0` (or `>0`, `v0`, `<0`) for
+///airport 0, launching in the direction of the arrow.
+pub fn compile(text: &str) -> Result<MapStatic> {
+    let mut map = MapStatic {
+        version: MAP_VERSION,
+        name: String::new(),
+        author: String::new(),
+        width: 0,
+        height: 0,
+        description: None,
+        difficulty: None,
+        tags: vec![],
+        recommended: Default::default(),
+        exits: vec![],
+        beacons: vec![],
+        airports: vec![],
+        path_markers: vec![],
+        airways: vec![],
+        procedures: vec![],
+        forbidden_routes: vec![],
+        ceiling: None,
+        radar: None,
+        goal: None,
+    };
+    let (mut entry_level, mut exit_level) = (ENTRY_LEVEL, EXIT_LEVEL);
+    //Weights are given to exits and airports once the grid has been read.
+    let mut weights = vec![];
+    let mut stands = vec![];
+    let mut handoff_intervals = vec![];
+    let mut crossings = vec![];
+
+    let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line));
+    loop {
+        let Some((line_number, line)) = lines.next() else { bail!("The map has no grid. Draw it after a line containing only `{SEPARATOR}`.") };
+        let line = line.trim();
+        if line == SEPARATOR {
+            break;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else { bail!("Line {line_number}: expected `key: value`, or `{SEPARATOR}` to start the grid.") };
+        let (key, value) = (key.trim(), value.trim());
+        match key.split_whitespace().collect::<Vec<_>>()[..] {
+            ["name"] => map.name = value.to_string(),
+            ["author"] => map.author = value.to_string(),
+            ["description"] => map.description = Some(value.to_string()),
+            ["difficulty"] => map.difficulty = Some(number(line_number, value)?),
+            ["tags"] => map.tags = value.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string).collect(),
+            ["plane_spawn_rate"] => map.recommended.plane_spawn_rate = Some(number(line_number, value)?),
+            ["spawn_pattern"] => map.recommended.spawn_pattern = Some(value.parse().map_err(|error| anyhow!("Line {line_number}: {error}."))?),
+            ["ceiling"] => map.ceiling = Some(number(line_number, value)?),
+            ["radar"] => {
+                let [x, y, range] = value.split_whitespace().collect::<Vec<_>>()[..] else {
+                    bail!("Line {line_number}: expected the column and row of the radar and its range.");
+                };
+                map.radar = Some(Radar { center: GroundLocation(number(line_number, x)?, number(line_number, y)?), range: number(line_number, range)? });
+            },
+            ["goal"] => map.goal = Some(value.parse().map_err(|error| anyhow!("Line {line_number}: {error}."))?),
+            ["max_planes"] => map.recommended.max_planes = Some(number(line_number, value)?),
+            ["tick_rate"] => map.recommended.tick_rate = Some(number(line_number, value)?),
+            ["entry_level"] => entry_level = number(line_number, value)?,
+            ["exit_level"] => exit_level = number(line_number, value)?,
+            ["weights", object] => {
+                let [spawn, destination] = value.split_whitespace().collect::<Vec<_>>()[..] else {
+                    bail!("Line {line_number}: expected a spawn weight and a destination weight.");
+                };
+                let spawn_weights = SpawnWeights { spawn: number(line_number, spawn)?, destination: number(line_number, destination)? };
+                weights.push((line_number, object.to_string(), spawn_weights));
+            },
+            ["stands", object] => stands.push((line_number, object.to_string(), number(line_number, value)?)),
+            ["crossing", object] => {
+                let crossing = if let Some(level) = value.strip_prefix(">=") {
+                    CrossingRestriction::AtOrAbove(number(line_number, level.trim())?)
+                } else if let Some(level) = value.strip_prefix("<=") {
+                    CrossingRestriction::AtOrBelow(number(line_number, level.trim())?)
+                } else {
+                    bail!("Line {line_number}: expected `>=` or `<=` and a flight level.");
+                };
+                crossings.push((line_number, object.to_string(), crossing));
+            },
+            ["handoff", object] => handoff_intervals.push((line_number, object.to_string(), number(line_number, value)?)),
+            ["forbid"] => {
+                let [from, to] = value.split_whitespace().collect::<Vec<_>>()[..] else {
+                    bail!("Line {line_number}: expected where the route starts and where it finishes, like `E0 E1`.");
+                };
+                map.forbidden_routes.push((from.to_string(), to.to_string()));
+            },
+            ["airway", index] => map.airways.push(Airway {
+                index: number(line_number, index)?,
+                beacons: value.split_whitespace().map(|beacon| number(line_number, beacon)).collect::<Result<_>>()?,
+            }),
+            _ => bail!("Line {line_number}: `{key}` is not a setting."),
+        }
+    }
+    if map.name.is_empty() || map.author.is_empty() {
+        bail!("The header must give the map's `name` and `author`.");
+    }
+
+    //Exits can only be given directions once the size of the map is known.
+    let mut exits = vec![];
+    let rows = text.lines().enumerate().skip_while(|(_, line)| line.trim() != SEPARATOR).skip(1).collect::<Vec<_>>();
+    //Blank lines after the grid are not part of it.
+    let rows = &rows[..rows.iter().rposition(|(_, line)| !line.trim().is_empty()).map_or(0, |last| last + 1)];
+    for (y, (index, row)) in rows.iter().enumerate() {
+        let chars = row.trim_end().chars().collect::<Vec<_>>();
+        for (x, cell) in chars.chunks(2).enumerate() {
+            let (symbol, digit) = (cell[0], cell.get(1).copied().unwrap_or(' '));
+            let position = format!("Line {}, column {}", index + 1, 2 * x + 1);
+            let location = GroundLocation(x as u16, y as u16);
+            let object_index = || digit.to_digit(10).map(|digit| digit as u16).ok_or_else(|| anyhow!("{position}: `{symbol}` must be followed by a digit."));
+            match symbol {
+                '.' | '·' | ' ' if digit == ' ' => {},
+                '+' | '┼' if digit == ' ' => map.path_markers.push(location),
+                '*' | '✱' => {
+                    let index = object_index()?;
+                    if map.beacons.iter().any(|beacon| beacon.index == index) {
+                        bail!("{position}: there is already a beacon {index}.");
+                    }
+                    map.beacons.push(Beacon { index, location });
+                },
+                'E' => {
+                    let index = object_index()?;
+                    if exits.iter().any(|(exit, _, _)| *exit == index) {
+                        bail!("{position}: there is already an exit {index}.");
+                    }
+                    exits.push((index, location, position));
+                },
+                '^' | '>' | 'v' | '<' | '↑' | '→' | '↓' | '←' => {
+                    let index = object_index()?;
+                    if map.airports.iter().any(|airport| airport.index == index) {
+                        bail!("{position}: there is already an airport {index}.");
+                    }
+                    let launch_direction = match symbol {
+                        '^' | '↑' => CardinalDirection::North,
+                        '>' | '→' => CardinalDirection::East,
+                        'v' | '↓' => CardinalDirection::South,
+                        _ => CardinalDirection::West,
+                    };
+                    map.airports.push(Airport { index, location, launch_direction, weights: None, stands: None });
+                },
+                _ => bail!("{position}: `{symbol}{digit}` is not something that can be on a map."),
+            }
+        }
+        map.width = map.width.max(chars.len().div_ceil(2) as u16);
+    }
+    map.height = rows.len() as u16;
+
+    for (index, location @ GroundLocation(x, y), position) in exits {
+        let offset = |at: u16, length: u16| if at == 0 { 1 } else if at == length - 1 { -1 } else { 0 };
+        let inward = (offset(x, map.width), offset(y, map.height));
+        let Some(entry_direction) = OrdinalDirection::CLOCKWISE.into_iter().find(|direction| direction.as_offset() == inward) else {
+            bail!("{position}: exit {index} is not on the edge of the map.");
+        };
+        let exit_direction = OrdinalDirection::CLOCKWISE.into_iter().find(|direction| direction.as_offset() == (-inward.0, -inward.1)).expect("every direction to have an opposite");
+        map.exits.push(Exit {
+            index,
+            entry_location: AirLocation(location.0, location.1, entry_level),
+            entry_direction,
+            exit_location: AirLocation(location.0, location.1, exit_level),
+            exit_direction,
+            exit_flight_levels: None,
+            weights: None,
+            handoff_interval: None,
+            crossing: None,
+        });
+    }
+    for (line_number, object, spawn_weights) in weights {
+        let found = match object.split_at_checked(1) {
+            Some(("E", index)) => map.exits.iter_mut().find(|exit| exit.index.to_string() == index).map(|exit| &mut exit.weights),
+            Some(("A", index)) => map.airports.iter_mut().find(|airport| airport.index.to_string() == index).map(|airport| &mut airport.weights),
+            _ => bail!("Line {line_number}: `{object}` should be an exit, like `E1`, or an airport, like `A0`."),
+        };
+        let Some(found) = found else { bail!("Line {line_number}: there is no {object} on the map.") };
+        *found = Some(spawn_weights);
+    }
+    for (line_number, object, count) in stands {
+        let Some(("A", index)) = object.split_at_checked(1) else { bail!("Line {line_number}: `{object}` should be an airport, like `A0`.") };
+        let Some(airport) = map.airports.iter_mut().find(|airport| airport.index.to_string() == index) else { bail!("Line {line_number}: there is no {object} on the map.") };
+        airport.stands = Some(count);
+    }
+    for (line_number, object, interval) in handoff_intervals {
+        let Some(("E", index)) = object.split_at_checked(1) else { bail!("Line {line_number}: `{object}` should be an exit, like `E1`.") };
+        let Some(exit) = map.exits.iter_mut().find(|exit| exit.index.to_string() == index) else { bail!("Line {line_number}: there is no {object} on the map.") };
+        exit.handoff_interval = Some(interval);
+    }
+    for (line_number, object, crossing) in crossings {
+        let Some(("E", index)) = object.split_at_checked(1) else { bail!("Line {line_number}: `{object}` should be an exit, like `E1`.") };
+        let Some(exit) = map.exits.iter_mut().find(|exit| exit.index.to_string() == index) else { bail!("Line {line_number}: there is no {object} on the map.") };
+        exit.crossing = Some(crossing);
+    }
+    map.exits.sort_by_key(|exit| exit.index);
+    map.beacons.sort_by_key(|beacon| beacon.index);
+    map.airports.sort_by_key(|airport| airport.index);
+    Ok(map)
+}
+
+///Parses a number in the header, saying which line it is on if it is not one.
+fn number<T: FromStr>(line_number: usize, value: &str) -> Result<T> {
+    value.parse().map_err(|_| anyhow!("Line {line_number}: `{value}` is not a number."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Goal;
+
+    const DIAGONAL: &str = "
+name: Diagonal
+author: Perodactyl
+description: A small airspace with exits in each corner.
+difficulty: 2
+tags: small
+plane_spawn_rate: 20
+weights E3: 0 2
+handoff E3: 4
+---
+E0. . . . . E3
+. + . . . + .
+. . + . + . .
+. . . *0. . .
+. . + . + . .
+. + . . . + .
+E2. . . . . E1
+";
+
+    #[test]
+    fn compiles_like_json() {
+        let drawn = compile(DIAGONAL).expect("drawn map to compile");
+        let json = crate::map_format::MapFormat::Json.parse(include_bytes!("../maps/diagonal.json")).expect("bundled map to load");
+        assert_eq!((drawn.width, drawn.height), (json.width, json.height));
+        assert_eq!(drawn.exits[..3], json.exits[..3]);
+        assert_eq!(drawn.exits[3].weights, Some(SpawnWeights { spawn: 0, destination: 2 }));
+        assert_eq!(drawn.exits[3].handoff_interval, Some(4));
+        assert_eq!(drawn.beacons.iter().map(|beacon| beacon.location).collect::<Vec<_>>(), json.beacons.iter().map(|beacon| beacon.location).collect::<Vec<_>>());
+        assert_eq!(drawn.path_markers.len(), 8);
+        assert_eq!(drawn.recommended.plane_spawn_rate, Some(20));
+        assert!(drawn.problems().is_empty());
+    }
+
+    #[test]
+    fn reports_where_errors_are() {
+        let error = compile(&DIAGONAL.replace("*0", "?0")).expect_err("unknown cell to be rejected");
+        assert_eq!(error.to_string(), "Line 14, column 7: `?0` is not something that can be on a map.");
+        let error = compile(&DIAGONAL.replace("*0", "E4")).expect_err("exit inside the map to be rejected");
+        assert_eq!(error.to_string(), "Line 14, column 7: exit 4 is not on the edge of the map.");
+        let error = compile(&DIAGONAL.replace("difficulty: 2", "difficulty: hard")).expect_err("bad number to be rejected");
+        assert_eq!(error.to_string(), "Line 5: `hard` is not a number.");
+    }
+
+    #[test]
+    fn gives_airports_stands() {
+        let drawn = compile(&DIAGONAL.replace("*0", "^0").replace("---", "stands A0: 2\n---")).expect("drawn map to compile");
+        assert_eq!(drawn.airports[0].stands, Some(2));
+        let error = compile(&DIAGONAL.replace("---", "stands E0: 2\n---")).expect_err("stands at an exit to be rejected");
+        assert_eq!(error.to_string(), "Line 10: `E0` should be an airport, like `A0`.");
+    }
+
+    #[test]
+    fn reads_goals() {
+        let drawn = compile(&DIAGONAL.replace("---", "goal: traffic 12\n---")).expect("drawn map to compile");
+        assert_eq!(drawn.goal, Some(Goal::Traffic(12)));
+        let error = compile(&DIAGONAL.replace("---", "goal: landings 12\n---")).expect_err("unknown goal to be rejected");
+        assert_eq!(error.to_string(), "Line 10: expected traffic, planes, or ticks and a number.");
+        let drawn = compile(&DIAGONAL.replace("---", "goal: ticks 0\n---")).expect("drawn map to compile");
+        assert_eq!(drawn.problems(), [format!("The map's goal is already met when the game starts.")]);
+    }
+}
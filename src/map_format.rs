@@ -0,0 +1,123 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{map::MapStatic, map_art};
+
+///A file format maps can be written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapFormat {
+    Json,
+    Toml,
+    Ron,
+    ///A grid drawn with characters, compiled by `map_art`.
+    Art,
+} impl MapFormat {
+    ///Every format, in the order map files are looked for.
+    pub const ALL: [MapFormat; 4] = [MapFormat::Json, MapFormat::Toml, MapFormat::Ron, MapFormat::Art];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MapFormat::Json => "json",
+            MapFormat::Toml => "toml",
+            MapFormat::Ron => "ron",
+            MapFormat::Art => "atc",
+        }
+    }
+    ///The format of a file, from its extension.
+    pub fn of(path: impl AsRef<Path>) -> Option<MapFormat> {
+        let extension = path.as_ref().extension()?;
+        MapFormat::ALL.into_iter().find(|format| extension.eq_ignore_ascii_case(format.extension()))
+    }
+    ///Reads a map written in this format, upgrading it if it was written for an older version of
+    ///the map format.
+    pub fn parse(self, bytes: &[u8]) -> Result<MapStatic> {
+        //Every format is read into the same tree, so that upgrades only need to be written once.
+        let map: serde_json::Value = match self {
+            MapFormat::Json => serde_json::de::from_slice(bytes)?,
+            MapFormat::Toml => toml::from_slice(bytes)?,
+            MapFormat::Ron => ron::de::from_bytes(bytes)?,
+            //Drawn maps have no older versions to upgrade from.
+            MapFormat::Art => return map_art::compile(std::str::from_utf8(bytes)?),
+        };
+        MapStatic::from_value(map)
+    }
+}
+
+///Reads a map from a file, in the format its extension names, or JSON if it names none.
+pub fn load_map(path: &Path) -> Result<MapStatic> {
+    MapFormat::of(path).unwrap_or(MapFormat::Json).parse(&fs::read(path)?)
+}
+
+///Writes a map as JSON in a consistent layout: indented with tabs, with everything that has an
+///index sorted by it, and path markers sorted by row and then column.
+pub fn to_normalized_json(map: &MapStatic) -> Result<String> {
+    let mut map = map.clone();
+    map.exits.sort_by_key(|exit| exit.index);
+    map.beacons.sort_by_key(|beacon| beacon.index);
+    map.airports.sort_by_key(|airport| airport.index);
+    map.airways.sort_by_key(|airway| airway.index);
+    map.procedures.sort_by_key(|procedure| procedure.index);
+    map.path_markers.sort_by_key(|marker| (marker.1, marker.0));
+    map.path_markers.dedup();
+
+    let mut json = vec![];
+    map.serialize(&mut serde_json::Serializer::with_formatter(&mut json, serde_json::ser::PrettyFormatter::with_indent(b"\t")))?;
+    json.push(b'\n');
+    Ok(String::from_utf8(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_every_format() {
+        let json = include_bytes!("../maps/diagonal.json");
+        let value: serde_json::Value = serde_json::de::from_slice(json).expect("bundled map to parse");
+        let toml = toml::to_string(&value).expect("map to serialize as TOML");
+        let ron = r#"(
+            version: 1,
+            name: "Diagonal",
+            author: "Perodactyl",
+            width: 7,
+            height: 7,
+            // Comments and trailing commas are allowed.
+            difficulty: Some(2),
+            exits: [
+                (index: 0, entry_location: (0, 0, 7), entry_direction: "se", exit_location: (0, 0, 9), exit_direction: "nw"),
+                (index: 1, entry_location: (6, 6, 7), entry_direction: "nw", exit_location: (6, 6, 9), exit_direction: "se"),
+            ],
+            beacons: [(index: 0, location: (3, 3))],
+            airports: [],
+            path_markers: [(1, 1), (2, 2), (4, 4), (5, 5)],
+        )"#;
+        for (format, bytes) in [(MapFormat::Json, &json[..]), (MapFormat::Toml, toml.as_bytes()), (MapFormat::Ron, ron.as_bytes())] {
+            let map = format.parse(bytes).unwrap_or_else(|error| panic!("{format:?} map to load: {error}"));
+            assert_eq!(map.name, "Diagonal");
+            assert_eq!(map.difficulty, Some(2));
+            assert!(map.problems().is_empty(), "{format:?} map has problems: {:?}", map.problems());
+        }
+    }
+
+    #[test]
+    fn normalized_json_reads_back() {
+        let mut map = MapFormat::Json.parse(include_bytes!("../maps/crossing.json")).expect("bundled map to load");
+        map.exits.reverse();
+        let json = to_normalized_json(&map).expect("map to serialize");
+        let normalized = MapFormat::Json.parse(json.as_bytes()).expect("normalized map to load");
+        assert!(normalized.exits.iter().map(|exit| exit.index).eq(0..map.exits.len() as u16));
+        assert_eq!(normalized.name, map.name);
+        assert_eq!(to_normalized_json(&normalized).expect("map to serialize"), json);
+    }
+
+    #[test]
+    fn finds_format_from_extension() {
+        assert_eq!(MapFormat::of("maps/crossing.json"), Some(MapFormat::Json));
+        assert_eq!(MapFormat::of("crossing.TOML"), Some(MapFormat::Toml));
+        assert_eq!(MapFormat::of("crossing.ron"), Some(MapFormat::Ron));
+        assert_eq!(MapFormat::of("crossing.atc"), Some(MapFormat::Art));
+        assert_eq!(MapFormat::of("crossing"), None);
+    }
+}
@@ -0,0 +1,140 @@
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{direction::{CardinalDirection, OrdinalDirection}, location::{AirLocation, GroundLocation}, map::{MapStatic, MAP_VERSION}, map_objects::{Airport, Airway, Beacon, Exit}};
+
+const MIN_SIZE: u16 = 12;
+const MAX_SIZE: u16 = 28;
+///Airways are kept at least this far from each other and from the edges of the map.
+const AIRWAY_SPACING: u16 = 3;
+
+///Parses a map name of the form `random` or `random:seed`. Returns the seed if one was given.
+pub fn parse_random_map(name: &str) -> Option<Option<u64>> {
+    match name.split_once(':') {
+        None if name == "random" => Some(None),
+        Some(("random", seed)) => seed.parse().ok().map(Some),
+        _ => None,
+    }
+}
+
+///Picks `count` positions between `AIRWAY_SPACING` and `length - AIRWAY_SPACING`, at least
+///`AIRWAY_SPACING` apart.
+fn airway_positions(rng: &mut ChaCha8Rng, length: u16, count: usize) -> Vec<u16> {
+    let mut positions: Vec<u16> = vec![];
+    for _ in 0..50 {
+        if positions.len() == count {
+            break;
+        }
+        let candidate = rng.random_range(AIRWAY_SPACING..length - AIRWAY_SPACING);
+        if positions.iter().all(|p| p.abs_diff(candidate) >= AIRWAY_SPACING) {
+            positions.push(candidate);
+        }
+    }
+    positions.sort();
+    positions
+}
+
+fn exit(index: u16, x: u16, y: u16, inward: CardinalDirection, outward: CardinalDirection) -> Exit {
+    Exit {
+        index,
+        entry_location: AirLocation(x, y, 7),
+        entry_direction: inward.into(),
+        exit_location: AirLocation(x, y, 9),
+        exit_direction: outward.into(),
+        exit_flight_levels: None,
+        handoff_interval: None,
+        crossing: None,
+        weights: None,
+    }
+}
+
+///Generates a map with straight airways crossing it, an exit at each end of each airway, beacons
+///where airways cross, and a few airports away from the airways. Airways which pass more than one
+///beacon can be followed with `v`. A seed gives the same map on every platform.
+pub fn generate_map(seed: u64) -> MapStatic {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let width = rng.random_range(MIN_SIZE..=MAX_SIZE);
+    let height = rng.random_range(MIN_SIZE..=MAX_SIZE);
+
+    let (row_count, column_count) = (rng.random_range(1..=2), rng.random_range(1..=2));
+    let rows = airway_positions(&mut rng, height, row_count);
+    let columns = airway_positions(&mut rng, width, column_count);
+
+    let mut exits = vec![];
+    for &y in &rows {
+        exits.push(exit(exits.len() as u16, 0, y, CardinalDirection::East, CardinalDirection::West));
+        exits.push(exit(exits.len() as u16, width - 1, y, CardinalDirection::West, CardinalDirection::East));
+    }
+    for &x in &columns {
+        exits.push(exit(exits.len() as u16, x, 0, CardinalDirection::South, CardinalDirection::North));
+        exits.push(exit(exits.len() as u16, x, height - 1, CardinalDirection::North, CardinalDirection::South));
+    }
+
+    let mut beacons = vec![];
+    for &y in &rows {
+        for &x in &columns {
+            beacons.push(Beacon { index: beacons.len() as u16, location: GroundLocation(x, y) });
+        }
+    }
+
+    //Beacons are numbered row by row, so each airway along a row or column visits them in order.
+    let mut airways = vec![];
+    if columns.len() > 1 {
+        for row in 0..rows.len() {
+            let beacons = (0..columns.len()).map(|column| (row * columns.len() + column) as u16).collect();
+            airways.push(Airway { index: airways.len() as u16, beacons });
+        }
+    }
+    if rows.len() > 1 {
+        for column in 0..columns.len() {
+            let beacons = (0..rows.len()).map(|row| (row * columns.len() + column) as u16).collect();
+            airways.push(Airway { index: airways.len() as u16, beacons });
+        }
+    }
+
+    let on_airway = |GroundLocation(x, y): GroundLocation| rows.contains(&y) || columns.contains(&x);
+    let mut airports: Vec<Airport> = vec![];
+    for _ in 0..rng.random_range(0..=2) {
+        //Give up on this airport if there is no room for it.
+        for _ in 0..50 {
+            let location = GroundLocation(rng.random_range(2..width - 2), rng.random_range(2..height - 2));
+            let launch_direction = *[CardinalDirection::North, CardinalDirection::South, CardinalDirection::East, CardinalDirection::West]
+                .choose(&mut rng).expect("directions to be non-empty");
+            let runway_end = location + <CardinalDirection as Into<OrdinalDirection>>::into(launch_direction).as_offset();
+            let is_clear = !on_airway(location) && !on_airway(runway_end)
+                && airports.iter().all(|a| a.location.chebyshev_distance(location) > 2);
+            if is_clear {
+                airports.push(Airport { location, launch_direction, index: airports.len() as u16, weights: None, stands: None });
+                break;
+            }
+        }
+    }
+
+    let is_taken = |location: GroundLocation| beacons.iter().any(|b| b.location == location)
+        || exits.iter().any(|e| GroundLocation::from(e.entry_location) == location);
+    let mut path_markers = vec![];
+    for &y in &rows {
+        path_markers.extend((0..width).map(|x| GroundLocation(x, y)).filter(|l| !is_taken(*l)));
+    }
+    for &x in &columns {
+        path_markers.extend((0..height).map(|y| GroundLocation(x, y)).filter(|l| !is_taken(*l) && !rows.contains(&l.1)));
+    }
+
+    let difficulty = ((exits.len() + airports.len() * 2) / 3).clamp(1, 5) as u8;
+    MapStatic {
+        version: MAP_VERSION,
+        name: format!("Random {seed}"),
+        author: format!("generated"),
+        width, height,
+        description: Some(format!("A randomly generated map. Play it again with --map random:{seed}.")),
+        difficulty: Some(difficulty),
+        tags: vec![format!("random")],
+        recommended: Default::default(),
+        exits, beacons, airports, path_markers, airways,
+        procedures: vec![],
+        forbidden_routes: vec![],
+        ceiling: None,
+        radar: None,
+        goal: None,
+    }
+}
@@ -1,121 +1,598 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{command::{Command, PointOfInterest}, direction::{CardinalDirection, OrdinalDirection}, location::{AirLocation, GroundLocation}};
+use crate::{command::{Argument, Command, PointOfInterest}, direction::{CardinalDirection, OrdinalDirection}, glyphs::glyphs, location::{AirLocation, GroundLocation}, styled::{self, CellGrid, Span, Style}};
 
-pub const COMMAND_TARGET_EMPHASIS: &str = "\x1b[4m";
-pub const COMMAND_TARGET_EMPHASIS_RESET: &str = "\x1b[24m";
+///Cells before a runway in which a plane is on its final approach, where it can be cleared to land.
+///Path markers this close to an airport stay lit at night.
+pub const FINAL_APPROACH_LENGTH: i16 = 3;
+///Shades of gray, from the 256-color palette, that trails fade through from newest to oldest.
+const TRAIL_SHADES: [u8; 3] = [250, 244, 238];
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Airport {
     pub location: GroundLocation,
     pub launch_direction: CardinalDirection,
     pub index: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weights: Option<SpawnWeights>,
+    ///How many planes can be on the ground here at once, counting those waiting to take off and
+    ///those which have landed. Unlimited if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stands: Option<u16>,
 } impl Airport {
-    pub fn to_display_string(&self, colorize: bool) -> String {
-        format!("{}{}{}{}", if colorize { "\x1b[34m" } else { "" }, self.launch_direction, self.index, if colorize { "\x1b[39m" } else { "" })
+    ///The airport as it is named in lists, in blue if `colorize` is set.
+    pub fn label(&self, colorize: bool) -> Span {
+        Span::new(format!("{}{}", self.launch_direction, self.index), Style::colored(4).when(colorize))
+    }
+    ///The cells a landing plane flies through just before the runway, nearest first.
+    pub fn final_approach(&self) -> Vec<GroundLocation> {
+        let (x, y) = <CardinalDirection as Into<OrdinalDirection>>::into(self.launch_direction).as_offset();
+        (1..=FINAL_APPROACH_LENGTH).map(|cells| self.location + (-x * cells, -y * cells)).collect()
     }
 } impl GridRenderable for Airport {
     fn location(&self) -> Option<GroundLocation> {
         Some(self.location)
     }
-    fn render(&self, _command: &Command) -> String {
-        self.to_display_string(true)
+    fn spans(&self, _command: &Command) -> Vec<Span> {
+        vec![Span::new(format!("{}{}", self.launch_direction, self.index), Style::colored(4))]
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Beacon {
     pub index: u16,
     pub location: GroundLocation,
-} impl Beacon {
-    pub fn to_display_string(&self, colorize: bool) -> String {
-        format!("{}*{}{}", if colorize { "\x1b[33m" } else { "" }, self.index, if colorize { "\x1b[39m" } else { "" })
-    }
 } impl GridRenderable for Beacon {
     fn location(&self) -> Option<GroundLocation> {
         Some(self.location)
     }
-    fn render(&self, command: &Command) -> String {
-        let emphasis = match command.current_segment().target() {
-            Some(PointOfInterest::Beacon(Some(b)) | PointOfInterest::Default(b)) if b == self.index => COMMAND_TARGET_EMPHASIS,
-            _ => "",
-        };
-        format!("{}{}{COMMAND_TARGET_EMPHASIS_RESET}", emphasis, self.to_display_string(true))
+    fn spans(&self, command: &Command) -> Vec<Span> {
+        let underline = matches!(command.current_segment().target(), Some(PointOfInterest::Beacon(Some(Argument::Value(b))) | PointOfInterest::Default(b)) if b == self.index);
+        vec![Span::new(format!("{}{}", glyphs().beacon, self.index), Style { underline, ..Style::colored(3) })]
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+///How often planes start or finish at an exit or airport, relative to the others. Each is 1 if
+///absent, and 0 means never.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpawnWeights {
+    #[serde(default = "default_weight")]
+    pub spawn: u32,
+    #[serde(default = "default_weight")]
+    pub destination: u32,
+} impl Default for SpawnWeights {
+    fn default() -> Self {
+        SpawnWeights { spawn: default_weight(), destination: default_weight() }
+    }
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+///A route between beacons, which planes can be cleared along with `v`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Airway {
+    pub index: u16,
+    ///Indices of the beacons along the airway, in the order it is flown.
+    pub beacons: Vec<u16>,
+}
+
+///Whether a procedure is flown leaving an airport or arriving at one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcedureKind {
+    Departure,
+    Arrival,
+}
+
+///One instruction of a procedure. Everything given is done together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcedureStep {
+    ///Index of the beacon to wait for before carrying out the step. If absent, it is carried out
+    ///as soon as the previous one is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<OrdinalDirection>,
+    ///Index of an airway to follow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub airway: Option<u16>,
+}
+
+///A standard departure or arrival, which planes can be cleared for with `p`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Procedure {
+    pub index: u16,
+    pub name: String,
+    pub kind: ProcedureKind,
+    ///Index of the airport departures leave from or arrivals land at.
+    pub airport: u16,
+    pub steps: Vec<ProcedureStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Exit {
     pub index: u16,
     pub entry_location: AirLocation,
     pub entry_direction: OrdinalDirection,
     pub exit_location: AirLocation,
     pub exit_direction: OrdinalDirection,
+    ///Lowest and highest flight levels planes may leave at. If absent, they must leave at the
+    ///level of `exit_location`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_flight_levels: Option<(u16, u16)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weights: Option<SpawnWeights>,
+    ///Fewest ticks between planes leaving by the exit, as the next sector can only take them so
+    ///fast. A plane which leaves sooner costs points. Unlimited if absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handoff_interval: Option<u16>,
+    ///A flight level planes must leave at or above, or at or below. Without `exit_flight_levels`,
+    ///planes may leave at any level which meets it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crossing: Option<CrossingRestriction>,
 } impl Exit {
-    pub fn to_display_string(&self, colorize: bool, show_char: bool) -> String {
-        match (colorize, show_char) {
-            (false, false) => format!("{} ", self.index),
-            (false, true)  => format!("E{}", self.index),
-            (true, false)  => format!("\x1b[31m{} \x1b[0m", self.index),
-            (true, true)   => format!("\x1b[31mE{}\x1b[0m", self.index),
-        }
+    ///Whether a plane here may leave through the exit, ignoring its direction.
+    pub fn accepts(&self, AirLocation(x, y, level): AirLocation) -> bool {
+        let AirLocation(exit_x, exit_y, _) = self.exit_location;
+        (x, y) == (exit_x, exit_y) && self.allows_level(level)
+    }
+    ///Whether planes may leave through the exit at this flight level.
+    pub fn allows_level(&self, level: u16) -> bool {
+        let in_band = match (self.exit_flight_levels, self.crossing) {
+            (Some((lowest, highest)), _) => (lowest..=highest).contains(&level),
+            (None, Some(_)) => true,
+            (None, None) => level == self.exit_location.2,
+        };
+        in_band && self.crossing.is_none_or(|crossing| crossing.allows(level))
+    }
+    ///Whether planes may leave at more than the level of `exit_location`.
+    pub fn restricts_levels(&self) -> bool {
+        self.exit_flight_levels.is_some() || self.crossing.is_some()
+    }
+    ///The exit as it is named in lists, in red if `colorize` is set. Without `show_char`, it is
+    ///named by its index alone.
+    pub fn label(&self, colorize: bool, show_char: bool) -> Span {
+        let text = if show_char { format!("E{}", self.index) } else { format!("{} ", self.index) };
+        Span::new(text, Style::colored(1).when(colorize))
     }
 } impl GridRenderable for Exit {
     fn location(&self) -> Option<GroundLocation> {
         Some(self.entry_location.into())
     }
-    fn render(&self, _command: &Command) -> String {
-        self.to_display_string(true, false)
+    fn spans(&self, command: &Command) -> Vec<Span> {
+        let underline = matches!(command.current_segment().target(), Some(PointOfInterest::Exit(Some(Argument::Value(e)))) if e == self.index);
+        vec![Span::new(format!("{} ", self.index), Style { underline, ..Style::colored(1) })]
+    }
+}
+
+///A flight level which planes must leave an exit at or above, or at or below.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossingRestriction {
+    AtOrAbove(u16),
+    AtOrBelow(u16),
+} impl CrossingRestriction {
+    pub fn allows(self, level: u16) -> bool {
+        match self {
+            CrossingRestriction::AtOrAbove(limit) => level >= limit,
+            CrossingRestriction::AtOrBelow(limit) => level <= limit,
+        }
     }
 }
 
+///Marks where planes crashed, in casual mode, until it is cleared away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrashSite {
+    pub location: GroundLocation,
+    ///The tick on which the marker is removed.
+    pub until: u32,
+} impl GridRenderable for CrashSite {
+    fn location(&self) -> Option<GroundLocation> {
+        Some(self.location)
+    }
+    fn spans(&self, _command: &Command) -> Vec<Span> {
+        vec![Span::new(format!("{} ", glyphs().crash_site), Style::colored(1))]
+    }
+}
+
+///Where a map's radar is and how far it sees. Planes further away show up only as primary targets,
+///without their callsigns or altitudes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Radar {
+    pub center: GroundLocation,
+    ///In cells.
+    pub range: u16,
+} impl Radar {
+    pub fn covers(&self, location: GroundLocation) -> bool {
+        self.center.euclidean_distance(location) <= self.range as f32
+    }
+}
+
+///A plane beyond radar range, of which nothing is known but where it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrimaryTarget(pub GroundLocation);
+impl GridRenderable for PrimaryTarget {
+    fn location(&self) -> Option<GroundLocation> {
+        Some(self.0)
+    }
+    fn spans(&self, _command: &Command) -> Vec<Span> {
+        vec![Span::new(format!("{} ", glyphs().primary_target), Style { dim: true, ..Style::default() })]
+    }
+}
+
+///What a cell of the radar holds, which decides whether a datablock may be moved into it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layer {
+    ///Blank space, path markers, and other marks which a datablock can cover.
+    Background,
+    ///Exits, airports, beacons, and anything else which must stay visible.
+    Object,
+    ///A plane's callsign and flight level.
+    Datablock,
+}
+
+#[derive(Debug, Clone)]
+struct Tile {
+    spans: Vec<Span>,
+    ///How many characters `spans` take up.
+    width: usize,
+    layer: Layer,
+}
+
+///Cells a datablock may be moved to when it would run into the one to its left, in order of
+///preference, with the leader line drawn from the plane toward each.
+const LEADER_DIRECTIONS: [OrdinalDirection; 4] = [OrdinalDirection::NorthEast, OrdinalDirection::SouthEast, OrdinalDirection::North, OrdinalDirection::South];
+
+///The tiles of a `RenderGrid`, kept between frames so that their spans can be reused instead of
+///allocated again each time the grid is drawn.
+#[derive(Debug, Clone, Default)]
+pub struct GridBuffer {
+    tiles: Vec<Tile>,
+}
+
 pub struct RenderGrid<'a> {
     pub width: u16,
-    pub height: u16,
+    ///Characters across each cell, before zooming.
+    pub cell_width: u16,
+    ///Each cell is drawn `zoom` rows tall and `zoom` times as wide.
+    pub zoom: u16,
     command: &'a Command,
-    tiles: Vec<String>,
+    tiles: Vec<Tile>,
 } impl<'a> RenderGrid<'a> {
-    pub fn new(width: u16, height: u16, command: &'a Command) -> Self {
-        RenderGrid {
-            width, height, command,
-            tiles: vec!["\x1b[2m. \x1b[0m".to_string(); (width*height) as usize],
+    ///A blank grid, reusing the tiles of `buffer`.
+    pub fn new(width: u16, height: u16, cell_width: u16, zoom: u16, command: &'a Command, buffer: GridBuffer) -> Self {
+        let mut tiles = buffer.tiles;
+        tiles.resize_with((width*height) as usize, || Tile { spans: vec![], width: 2, layer: Layer::Background });
+        for tile in &mut tiles {
+            tile.spans.clear();
+            tile.spans.push(Span::new(format!("{} ", glyphs().blank), Style { dim: true, ..Style::default() }));
+            tile.width = 2;
+            tile.layer = Layer::Background;
         }
+        RenderGrid { width, cell_width, zoom, command, tiles }
+    }
+    ///Gives back the tiles, to be reused for the next frame.
+    pub fn into_buffer(self) -> GridBuffer {
+        GridBuffer { tiles: self.tiles }
     }
     pub fn add(&mut self, obj: &impl GridRenderable) {
         if let Some(GroundLocation(x, y)) = obj.location() {
-            let result = obj.render(&self.command);
             let loc = self.index_of(x, y);
-            self.tiles[loc] = result;
+            let tile = &mut self.tiles[loc];
+            (tile.spans, tile.width, tile.layer) = (obj.spans(self.command), obj.width(), obj.layer());
+        }
+    }
+    ///Draws the cells a plane has flown through, oldest first, fading the further back they are.
+    ///Anything drawn afterward covers the trail.
+    pub fn add_trail<'t>(&mut self, trail: impl ExactSizeIterator<Item = &'t GroundLocation>) {
+        let length = trail.len();
+        for (i, &location) in trail.enumerate() {
+            let age = length - 1 - i;
+            let shade = TRAIL_SHADES[age * TRAIL_SHADES.len() / length];
+            //The map may have shrunk since the plane flew through, if it was reloaded.
+            if let Some(tile) = self.get_mut(location) {
+                *tile = Tile { spans: vec![Span::new(format!("{} ", glyphs().trail), Style::colored(shade))], width: 2, layer: Layer::Background };
+            }
+        }
+    }
+    ///Draws a line through these cells. Anything drawn afterward covers it.
+    pub fn add_line(&mut self, cells: &[GroundLocation]) {
+        for &location in cells {
+            if let Some(tile) = self.get_mut(location) {
+                *tile = Tile { spans: vec![Span::new(format!("{} ", glyphs().measurement), Style::colored(3))], width: 2, layer: Layer::Background };
+            }
+        }
+    }
+    ///Draws whatever is in a cell in reverse video, to show it is selected.
+    pub fn highlight(&mut self, location: GroundLocation) {
+        self.restyle(location, |style| style.reverse = true);
+    }
+    ///Draws whatever is in a cell over a background color from the 16-color palette, as a warning.
+    pub fn tint(&mut self, location: GroundLocation, background: u8) {
+        self.restyle(location, |style| style.bg = Some(background));
+    }
+    ///Draws whatever is in a cell in bold, to set it apart without selecting it.
+    pub fn embolden(&mut self, location: GroundLocation) {
+        self.restyle(location, |style| style.bold = true);
+    }
+    ///Changes the style of everything in a cell.
+    fn restyle(&mut self, location: GroundLocation, change: impl Fn(&mut Style)) {
+        if let Some(tile) = self.get_mut(location) {
+            tile.spans.iter_mut().for_each(|span| change(&mut span.style));
+        }
+    }
+    ///Moves each datablock which fills its cell, and so would run into the datablock to its right,
+    ///so that both can be read. The datablock on the right is moved into a free neighbouring cell,
+    ///and a leader line is drawn from the plane toward it. If there is no free cell, it stays put.
+    pub fn declutter(&mut self) {
+        let cell_width = (self.cell_width * self.zoom) as usize;
+        let height = (self.tiles.len() / self.width as usize) as u16;
+        //Whether a datablock in this cell would run into one to its right.
+        let crowds = |grid: &Self, x: u16, y: u16| {
+            let tile = &grid.tiles[grid.index_of(x, y)];
+            tile.layer == Layer::Datablock && tile.width >= cell_width
+        };
+        for y in 0..height {
+            for x in 1..self.width {
+                if !crowds(self, x - 1, y) || self.tiles[self.index_of(x, y)].layer != Layer::Datablock {
+                    continue;
+                }
+                let block_width = self.tiles[self.index_of(x, y)].width;
+                let free = LEADER_DIRECTIONS.into_iter().find_map(|direction| {
+                    let target = GroundLocation(x, y) + direction.as_offset();
+                    let GroundLocation(target_x, target_y) = target;
+                    if target_x >= self.width || target_y >= height || self.tiles[self.index_of(target_x, target_y)].layer != Layer::Background {
+                        return None;
+                    }
+                    //The datablock must not run into others where it is moved to, either.
+                    let crowded_from_left = target_x > 0 && crowds(self, target_x - 1, target_y);
+                    let crowds_right = block_width >= cell_width && target_x + 1 < self.width
+                        && self.tiles[self.index_of(target_x + 1, target_y)].layer == Layer::Datablock;
+                    (!crowded_from_left && !crowds_right).then_some((direction, target))
+                });
+                let Some((direction, target)) = free else { continue };
+                let (here, there) = (self.index_of(x, y), self.index_of(target.0, target.1));
+                let leader = match direction {
+                    OrdinalDirection::NorthEast | OrdinalDirection::SouthWest => glyphs().leader_rising,
+                    OrdinalDirection::SouthEast | OrdinalDirection::NorthWest => glyphs().leader_falling,
+                    _ => glyphs().vertical,
+                };
+                self.tiles[there] = std::mem::replace(&mut self.tiles[here], Tile { spans: vec![Span::new(format!("{leader} "), Style { dim: true, ..Style::default() })], width: 2, layer: Layer::Object });
+            }
         }
     }
     fn index_of(&self, x: u16, y: u16) -> usize {
         ((y as usize) * (self.width as usize)) + (x as usize)
     }
-    fn get(&self, x: u16, y: u16) -> &str {
+    fn get(&self, x: u16, y: u16) -> &Tile {
         &self.tiles[self.index_of(x, y)]
     }
+    ///The tile at a location, if it is on the grid.
+    fn get_mut(&mut self, GroundLocation(x, y): GroundLocation) -> Option<&mut Tile> {
+        let loc = self.index_of(x, y);
+        if x < self.width { self.tiles.get_mut(loc) } else { None }
+    }
 } impl RenderGrid<'_> {
-    pub fn render(&self) -> String {
-        let mut out = String::with_capacity((self.width * self.height * 2) as usize);
+    ///Renders the cells of the grid from `origin`, `size` cells across and down, as characters on
+    ///the screen. Zoomed in, each cell is drawn as `zoom` cells across and down: the background is
+    ///drawn in each of them, so that lines and the blank grid keep their spacing, and objects are
+    ///drawn once, in the top left, over the background.
+    pub fn render(&self, origin: (u16, u16), size: (u16, u16)) -> CellGrid {
+        let (left, top) = origin;
+        let (width, height) = size;
+        let (cell_width, zoom) = (self.cell_width as usize, self.zoom as usize);
+        let blank = [Span::new(format!("{} ", glyphs().blank), Style { dim: true, ..Style::default() })];
+        //Draws `spans` into `cells` cells of the line, cut short or padded to fill them.
+        let fill = |line: &mut Vec<Span>, spans: &[Span], cells: usize| {
+            let clipped = styled::clip(spans, cells * cell_width);
+            let drawn = clipped.iter().map(|span| span.text.chars().count()).sum::<usize>();
+            line.extend(clipped);
+            line.push(Span::new(" ".repeat(cells * cell_width - drawn), Style::default()));
+        };
+        let mut lines = Vec::with_capacity(height as usize * zoom);
+        for y in top..top + height {
+            for row in 0..zoom {
+                let mut line = Vec::with_capacity(width as usize * zoom * 2);
+                for x in left..left + width {
+                    let tile = self.get(x, y);
+                    let background: &[Span] = if tile.layer == Layer::Background { &tile.spans } else { &blank };
+                    let mut cells = 0;
+                    //Objects are cut short if they are wider than their cell, so that they don't
+                    //cover the cells beside them.
+                    if row == 0 && tile.layer != Layer::Background {
+                        cells = tile.width.div_ceil(cell_width).clamp(1, zoom);
+                        fill(&mut line, &tile.spans, cells);
+                    }
+                    for _ in cells..zoom {
+                        fill(&mut line, background, 1);
+                    }
+                }
+                lines.push(line);
+            }
+        }
+        CellGrid::from_lines(&lines)
+    }
+}
+
+///Largest size of the minimap, in characters.
+const MINIMAP_WIDTH: u16 = 16;
+const MINIMAP_HEIGHT: u16 = 8;
+
+///A downsampled overview of the whole map, showing how many planes are in each area and which part
+///is in the radar view.
+pub struct Minimap {
+    pub width: u16,
+    pub height: u16,
+    ///How many cells across and down each character of the minimap covers.
+    scale: u16,
+    density: Vec<u16>,
+    ///The origin and size of the radar view, in cells.
+    view: ((u16, u16), (u16, u16)),
+} impl Minimap {
+    pub fn new(map_width: u16, map_height: u16, view: ((u16, u16), (u16, u16))) -> Self {
+        let scale = map_width.div_ceil(MINIMAP_WIDTH).max(map_height.div_ceil(MINIMAP_HEIGHT)).max(1);
+        let width = map_width.div_ceil(scale);
+        let height = map_height.div_ceil(scale);
+        Minimap {
+            width, height, scale, view,
+            density: vec![0; (width * height) as usize],
+        }
+    }
+    pub fn add(&mut self, GroundLocation(x, y): GroundLocation) {
+        let (x, y) = (x / self.scale, y / self.scale);
+        if x < self.width && y < self.height {
+            self.density[(y * self.width + x) as usize] += 1;
+        }
+    }
+    fn in_view(&self, x: u16, y: u16) -> bool {
+        let ((left, top), (width, height)) = self.view;
+        let (cell_left, cell_top) = (x * self.scale, y * self.scale);
+        cell_left < left + width && cell_left + self.scale > left
+            && cell_top < top + height && cell_top + self.scale > top
+    }
+    ///Renders the minimap, framed with a border.
+    pub fn render(&self) -> CellGrid {
+        let glyphs = glyphs();
+        let edge = |left, right| vec![Span::plain(format!("{left}{}{right}", glyphs.horizontal.repeat(self.width as usize)))];
+        let mut lines = vec![edge(glyphs.top_left, glyphs.top_right)];
         for y in 0..self.height {
+            let mut line = vec![Span::plain(glyphs.vertical)];
             for x in 0..self.width {
-                out.push_str(self.get(x, y));
+                let cell = glyphs.density(self.density[(y * self.width + x) as usize]);
+                let in_view = self.in_view(x, y);
+                line.push(Span::new(cell, Style { reverse: in_view, dim: !in_view, ..Style::default() }));
             }
-            out.push_str(&format!("\x1b[{}D\x1b[B", self.width * 2));
+            line.push(Span::plain(glyphs.vertical));
+            lines.push(line);
         }
-        out
+        lines.push(edge(glyphs.bottom_left, glyphs.bottom_right));
+        CellGrid::from_lines(&lines)
     }
 }
 
 pub trait GridRenderable {
     fn location(&self) -> Option<GroundLocation>;
-    fn render(&self, command: &Command) -> String;
+    ///The object as it is drawn on the grid.
+    fn spans(&self, command: &Command) -> Vec<Span>;
+    ///Number of characters the object draws.
+    fn width(&self) -> usize {
+        2
+    }
+    fn layer(&self) -> Layer {
+        Layer::Object
+    }
 }
 
 pub trait ListRenderable {
-    fn render(&self, command: &Command) -> String;
+    ///The item as it is listed.
+    fn spans(&self, command: &Command) -> Vec<Span>;
 }
 
 pub trait ListItemPartRenderable {
-    fn render(&self, colorize: bool) -> String;
+    ///The part as it is listed, among the parts around it. It is only styled if `colorize` is set.
+    fn spans(&self, colorize: bool) -> Vec<Span>;
+    ///The part as plain text.
+    fn text(&self) -> String {
+        styled::text(&self.spans(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///A datablock which fills a two-character cell.
+    struct Block(char, GroundLocation);
+    impl GridRenderable for Block {
+        fn location(&self) -> Option<GroundLocation> {
+            Some(self.1)
+        }
+        fn spans(&self, _command: &Command) -> Vec<Span> {
+            vec![Span::new(format!("{}5", self.0), Style::default())]
+        }
+        fn layer(&self) -> Layer {
+            Layer::Datablock
+        }
+    }
+
+    #[test]
+    fn moves_crowded_datablocks_beside_a_leader() {
+        let command = Command::default();
+        let mut grid = RenderGrid::new(4, 3, 2, 1, &command, GridBuffer::default());
+        grid.add(&Block('a', GroundLocation(1, 1)));
+        grid.add(&Block('d', GroundLocation(2, 1)));
+        grid.declutter();
+        assert_eq!(grid.get(1, 1).spans[0].text, "a5");
+        assert_eq!(grid.get(3, 0).spans[0].text, "d5");
+        assert_eq!(grid.get(2, 1).layer, Layer::Object);
+    }
+
+    #[test]
+    fn leaves_datablocks_with_nowhere_to_go() {
+        let command = Command::default();
+        let mut grid = RenderGrid::new(2, 1, 2, 1, &command, GridBuffer::default());
+        grid.add(&Block('a', GroundLocation(0, 0)));
+        grid.add(&Block('d', GroundLocation(1, 0)));
+        grid.declutter();
+        assert_eq!(grid.get(1, 0).spans[0].text, "d5");
+    }
+
+    #[test]
+    fn reused_buffers_start_blank() {
+        let command = Command::default();
+        let mut grid = RenderGrid::new(2, 2, 2, 1, &command, GridBuffer::default());
+        grid.add(&Block('a', GroundLocation(1, 1)));
+        let blank = grid.get(0, 0).spans.clone();
+        let grid = RenderGrid::new(3, 2, 2, 1, &command, grid.into_buffer());
+        assert_eq!(grid.tiles.len(), 6);
+        assert!(grid.tiles.iter().all(|tile| tile.spans == blank && tile.layer == Layer::Background));
+    }
+
+    #[test]
+    fn zooming_scales_the_background_and_draws_objects_once() {
+        let command = Command::default();
+        let mut grid = RenderGrid::new(2, 1, 2, 2, &command, GridBuffer::default());
+        grid.add(&GroundLocation(0, 0));
+        grid.add(&Block('a', GroundLocation(1, 0)));
+        let rendered = grid.render((0, 0), (2, 1));
+        let rows = rendered.rows().map(|row| row.iter().map(|cell| cell.glyph).collect::<String>()).collect::<Vec<_>>();
+        let (marker, blank) = (glyphs().path_marker, glyphs().blank);
+        assert_eq!(rows, [format!("{marker} {marker} a5{blank} "), format!("{marker} {marker} {blank} {blank} ")]);
+    }
+
+    #[test]
+    fn exits_enforce_crossing_restrictions() {
+        let mut exit = Exit {
+            index: 0,
+            entry_location: AirLocation(0, 0, 7),
+            entry_direction: OrdinalDirection::South,
+            exit_location: AirLocation(0, 0, 9),
+            exit_direction: OrdinalDirection::North,
+            exit_flight_levels: None,
+            weights: None,
+            handoff_interval: None,
+            crossing: Some(CrossingRestriction::AtOrAbove(5)),
+        };
+        assert!(exit.accepts(AirLocation(0, 0, 5)) && exit.accepts(AirLocation(0, 0, 9)));
+        assert!(!exit.accepts(AirLocation(0, 0, 4)) && !exit.accepts(AirLocation(1, 0, 6)));
+        exit.exit_flight_levels = Some((3, 6));
+        assert_eq!((3..=9).filter(|level| exit.allows_level(*level)).collect::<Vec<_>>(), [5, 6]);
+    }
+
+    #[test]
+    fn radar_sees_a_circle() {
+        let radar = Radar { center: GroundLocation(5, 5), range: 3 };
+        assert!(radar.covers(GroundLocation(5, 8)) && radar.covers(GroundLocation(7, 7)));
+        assert!(!radar.covers(GroundLocation(8, 8)) && !radar.covers(GroundLocation(1, 5)));
+    }
+
+    #[test]
+    fn final_approach_leads_up_to_the_runway() {
+        let airport = Airport { location: GroundLocation(5, 5), launch_direction: CardinalDirection::East, index: 0, weights: None, stands: None };
+        assert_eq!(airport.final_approach(), vec![GroundLocation(4, 5), GroundLocation(3, 5), GroundLocation(2, 5)]);
+    }
 }
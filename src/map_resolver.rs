@@ -0,0 +1,151 @@
+use std::{collections::HashSet, fs, io, path::{Path, PathBuf}};
+
+use anyhow::{bail, Result};
+
+use crate::{map_format::MapFormat, paths};
+
+///Directory maps are installed to system-wide.
+const SYSTEM_MAPS_DIR: &str = "/usr/share/atc/maps";
+
+///Finds maps by name in the map search path.
+#[derive(Debug, Clone)]
+pub struct MapResolver {
+    ///Searched in order, so maps in earlier directories hide maps of the same name in later ones.
+    dirs: Vec<PathBuf>,
+} impl MapResolver {
+    ///Searches `extra_dirs`, then the user's maps directory, then the system maps directory, then
+    ///`maps` in the current directory.
+    pub fn new(extra_dirs: &[PathBuf]) -> Self {
+        let mut dirs = extra_dirs.to_vec();
+        if let Some(dir) = user_maps_dir() {
+            dirs.push(dir);
+        }
+        dirs.push(PathBuf::from(SYSTEM_MAPS_DIR));
+        dirs.push(PathBuf::from("maps"));
+        MapResolver { dirs }
+    }
+    ///Finds the file for a map, given its name or a path to it. A map can be in any of the formats
+    ///in `MapFormat`; JSON is preferred when there is more than one.
+    pub fn resolve(&self, name: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(name);
+        if path.is_file() {
+            return Some(path);
+        }
+        std::iter::once(Path::new("")).chain(self.dirs.iter().map(PathBuf::as_path))
+            .flat_map(|dir| MapFormat::ALL.map(|format| dir.join(format!("{name}.{}", format.extension()))))
+            .find(|path| path.is_file())
+    }
+    ///Every map in the search path, by name, sorted by name.
+    pub fn maps(&self) -> Vec<(String, PathBuf)> {
+        let mut seen = HashSet::new();
+        let mut maps = vec![];
+        for dir in &self.dirs {
+            for path in map_files(dir) {
+                let Some(name) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else { continue };
+                if seen.insert(name.clone()) {
+                    maps.push((name, path));
+                }
+            }
+        }
+        maps.sort_by(|a, b| a.0.cmp(&b.0));
+        maps
+    }
+}
+
+///Directory maps are installed to for the current user.
+pub fn user_maps_dir() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("maps"))
+}
+
+///Names of the maps installed for the current user, sorted.
+pub fn installed_maps() -> Vec<String> {
+    let Some(dir) = user_maps_dir() else { return vec![] };
+    let mut maps = map_files(&dir).into_iter()
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .collect::<Vec<_>>();
+    maps.sort();
+    maps
+}
+
+///Fetches a map from a URL.
+#[cfg(feature = "download")]
+fn download(source: &str) -> Result<Vec<u8>> {
+    match ureq::get(source).call().and_then(|mut response| response.body_mut().read_to_vec()) {
+        Ok(contents) => Ok(contents),
+        Err(e) => bail!("Could not download {source}: {e}"),
+    }
+}
+
+#[cfg(not(feature = "download"))]
+fn download(source: &str) -> Result<Vec<u8>> {
+    bail!("Could not download {source}: this build of ATC was made without the `download` feature. Download the map yourself and install the file instead.")
+}
+
+///Copies a map from a file or URL into the user's maps directory, if it is valid. Returns the name
+///it was installed as.
+pub fn install_map(source: &str) -> Result<String> {
+    let is_url = source.starts_with("http://") || source.starts_with("https://");
+    let contents = if is_url {
+        download(source)?
+    } else {
+        fs::read(source)?
+    };
+
+    let file_name = source.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    let format = MapFormat::of(file_name).unwrap_or(MapFormat::Json);
+    let map = format.parse(&contents)?;
+    let problems = map.problems();
+    if !problems.is_empty() {
+        bail!("{source} is not a valid map:\n{}", problems.join("\n"));
+    }
+
+    let name = match file_name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() && MapFormat::of(file_name).is_some() => installed_name(stem),
+        _ => installed_name(&map.name),
+    };
+    if name.is_empty() {
+        bail!("{source} has no name to install it under.");
+    }
+    let Some(dir) = user_maps_dir() else { bail!("Could not find a directory to install maps to.") };
+    fs::create_dir_all(&dir)?;
+    //Otherwise an older copy in another format could be found in its place.
+    for other in MapFormat::ALL.into_iter().filter(|other| *other != format) {
+        match fs::remove_file(dir.join(format!("{name}.{}", other.extension()))) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {},
+        }
+    }
+    fs::write(dir.join(format!("{name}.{}", format.extension())), contents)?;
+    Ok(name)
+}
+
+///The name a map is installed under: only lowercase letters, digits and dashes, so that it can't
+///be written anywhere but the maps directory.
+fn installed_name(name: &str) -> String {
+    let name = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' }).collect::<String>();
+    name.trim_matches('-').to_string()
+}
+
+///Map files in a directory, with JSON before other formats so that it is preferred, as in
+///`MapResolver::resolve`.
+fn map_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return vec![] };
+    let mut files = entries.filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter_map(|path| Some((MapFormat::ALL.iter().position(|format| Some(*format) == MapFormat::of(&path))?, path)))
+        .collect::<Vec<_>>();
+    files.sort_by_key(|(format, _)| *format);
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installs_maps_under_safe_names() {
+        assert_eq!(installed_name("Crossing Paths"), "crossing-paths");
+        assert_eq!(installed_name("../../.bashrc"), "bashrc");
+        assert_eq!(installed_name("/.."), "");
+    }
+}
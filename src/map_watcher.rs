@@ -0,0 +1,41 @@
+use std::{fs, path::{Path, PathBuf}, thread, time::{Duration, SystemTime}};
+
+use crossbeam_channel::{RecvTimeoutError, Sender};
+
+use crate::{events::Event, map_format};
+
+///How often the map file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+///Watches a map file from another thread, reading it again whenever it changes and sending the
+///result to the game loop.
+pub struct MapWatcher {
+    pub path: PathBuf,
+    ///Dropped along with the watcher, which tells the thread to stop.
+    _stop: Sender<()>,
+} impl MapWatcher {
+    pub fn new(path: PathBuf, events: Sender<Event>) -> Self {
+        let (stop, stopped) = crossbeam_channel::unbounded();
+        let watched = path.clone();
+        thread::spawn(move || {
+            let mut last_modified = modified(&watched);
+            //Waits between checks until the watcher is dropped.
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(POLL_INTERVAL) {
+                let modified = modified(&watched);
+                //Editors may remove the file for a moment while saving it.
+                if modified.is_none() || modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                if events.send(Event::MapChanged(watched.clone(), Box::new(map_format::load_map(&watched)))).is_err() {
+                    break;
+                }
+            }
+        });
+        MapWatcher { path, _stop: stop }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,201 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use tabled::Tabled;
+use termion::{event::Key, input::TermRead};
+
+use crate::{glyphs::glyphs, map::MapStatic, map_format, map_resolver::MapResolver, scores, styled::{self, Ansi, Span, Style}};
+
+///Menus shown over the game, for starting a new one.
+#[derive(Debug, Clone)]
+pub enum Menu {
+    Main,
+    ///Lists the maps that can be chosen, by name.
+    Maps(Vec<String>),
+}
+
+///What the player chose in a menu.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuChoice {
+    Restart,
+    RestartWithNewSeed,
+    SwitchMap(String),
+    Quit,
+}
+
+impl Menu {
+    ///Handles a key, returning the player's choice if they made one.
+    pub fn input(&mut self, letter: char, maps: &MapResolver) -> Option<MenuChoice> {
+        match self {
+            Menu::Main => match letter {
+                'r' | 'R' => return Some(MenuChoice::Restart),
+                'n' | 'N' => return Some(MenuChoice::RestartWithNewSeed),
+                'm' | 'M' => *self = Menu::Maps(list_maps(maps)),
+                'q' | 'Q' => return Some(MenuChoice::Quit),
+                _ => {},
+            },
+            Menu::Maps(maps) => match letter {
+                '0'..='9' => {
+                    let index = letter as usize - '0' as usize;
+                    if let Some(name) = maps.get(index) {
+                        return Some(MenuChoice::SwitchMap(name.clone()));
+                    }
+                },
+                '\x7f' => *self = Menu::Main,
+                _ => {},
+            },
+        }
+        None
+    }
+    fn lines(&self, seed: u64, can_resume: bool) -> Vec<Vec<Span>> {
+        match self {
+            Menu::Main => {
+                let mut lines = vec![
+                    option("r", format!("Restart (seed {seed})")),
+                    option("n", format!("Restart with a new seed")),
+                    option("m", format!("Change map")),
+                    option("q", format!("Quit")),
+                    option("Ctrl-T", format!("Timeline")),
+                ];
+                if can_resume {
+                    lines.push(option("Esc", format!("Resume")));
+                }
+                lines
+            },
+            Menu::Maps(maps) => {
+                let mut lines = maps.iter().enumerate()
+                    .map(|(index, name)| option(&index.to_string(), name.clone()))
+                    .collect::<Vec<_>>();
+                if lines.is_empty() {
+                    lines.push(vec![Span::new("No maps found.", Style { dim: true, ..Style::default() })]);
+                }
+                lines.push(option("Backspace", format!("Back")));
+                lines
+            },
+        }
+    }
+    ///Draws the menu in a box at the top left of the terminal.
+    pub fn render(&self, output: &mut impl Write, seed: u64, can_resume: bool) -> Result<()> {
+        const WIDTH: usize = 32;
+        let glyphs = glyphs();
+        let lines = self.lines(seed, can_resume);
+        write!(output, "{}{}{}{}", termion::cursor::Goto(3, 2), glyphs.top_left, glyphs.horizontal.repeat(WIDTH), glyphs.top_right)?;
+        for (y, line) in lines.iter().enumerate() {
+            let padding = " ".repeat(WIDTH.saturating_sub(styled::width(line) + 1));
+            write!(output, "{}{} {}{padding}{}", termion::cursor::Goto(3, 3 + y as u16), glyphs.vertical, Ansi(line), glyphs.vertical)?;
+        }
+        write!(output, "{}{}{}{}", termion::cursor::Goto(3, 3 + lines.len() as u16), glyphs.bottom_left, glyphs.horizontal.repeat(WIDTH), glyphs.bottom_right)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+///A row of the map list.
+#[derive(Debug, Clone, Tabled)]
+pub struct MapListing {
+    ///The name the map is chosen by, as with `--map`.
+    #[tabled(skip)]
+    pub file: String,
+    #[tabled(rename = "Map")]
+    pub name: String,
+    #[tabled(rename = "Author")]
+    pub author: String,
+    #[tabled(rename = "Size")]
+    pub size: String,
+    #[tabled(rename = "Difficulty")]
+    pub difficulty: String,
+    #[tabled(rename = "Recommended")]
+    pub recommended: String,
+    #[tabled(rename = "Tags")]
+    pub tags: String,
+    #[tabled(rename = "Best")]
+    pub best: String,
+    #[tabled(rename = "Description")]
+    pub description: String,
+} impl MapListing {
+    fn new(file: String, map: MapStatic) -> Self {
+        //Written as the arguments which would give the same settings.
+        let mut recommended = vec![];
+        if let Some(rate) = map.recommended.plane_spawn_rate {
+            recommended.push(format!("-p {rate}"));
+        }
+        if let Some(rate) = map.recommended.tick_rate {
+            recommended.push(format!("-t {rate}"));
+        }
+        if let Some(pattern) = map.recommended.spawn_pattern {
+            recommended.push(format!("--spawn-pattern {pattern}"));
+        }
+        if let Some(max) = map.recommended.max_planes {
+            recommended.push(format!("--max-planes {max}"));
+        }
+        MapListing {
+            file,
+            size: format!("{}x{}", map.width, map.height),
+            recommended: if recommended.is_empty() { format!("-") } else { recommended.join(" ") },
+            tags: if map.tags.is_empty() { format!("-") } else { map.tags.join(", ") },
+            description: map.description.unwrap_or_default(),
+            difficulty: map.difficulty.map(|d| "*".repeat(d as usize)).unwrap_or_else(|| format!("-")),
+            best: scores::personal_best(&map.name).map(|b| b.to_string()).unwrap_or_else(|| format!("-")),
+            name: map.name,
+            author: map.author,
+        }
+    }
+}
+
+///Details of every map in the search path which can be read.
+pub fn map_listings(maps: &MapResolver) -> Vec<MapListing> {
+    maps.maps().into_iter()
+        .filter_map(|(name, path)| {
+            let map = map_format::load_map(&path).ok()?;
+            Some(MapListing::new(name, map))
+        })
+        .collect()
+}
+
+///Shows the map list and lets the player choose one with the arrow keys. Returns the chosen map,
+///or None if they quit.
+pub fn pick_map(output: &mut impl Write, input: impl Read, maps: &MapResolver) -> Result<Option<String>> {
+    let listings = map_listings(maps);
+    if listings.is_empty() {
+        return Ok(None);
+    }
+    let table = tabled::Table::new(&listings).with(tabled::settings::Style::blank()).to_string();
+    let mut selected = 0;
+    let mut keys = input.keys();
+    loop {
+        let heading = [
+            Span::new("Choose a map", Style { bold: true, ..Style::default() }),
+            Span::plain("  "),
+            Span::new("(arrows to move, Enter to play, q to quit)", Style { dim: true, ..Style::default() }),
+        ];
+        write!(output, "{}{}{}", termion::clear::All, termion::cursor::Goto(1, 1), Ansi(&heading))?;
+        for (y, line) in table.lines().enumerate() {
+            //The first line of the table is its header.
+            let style = match y {
+                0 => Style { bold: true, ..Style::default() },
+                _ if y - 1 == selected => Style { reverse: true, ..Style::default() },
+                _ => Style::default(),
+            };
+            write!(output, "{}{}", termion::cursor::Goto(1, 3 + y as u16), Ansi(&[Span::new(line, style)]))?;
+        }
+        output.flush()?;
+
+        match keys.next() {
+            Some(Ok(Key::Up | Key::Char('k'))) => selected = selected.saturating_sub(1),
+            Some(Ok(Key::Down | Key::Char('j'))) => selected = (selected + 1).min(listings.len() - 1),
+            Some(Ok(Key::Char('\n' | '\r'))) => return Ok(Some(listings[selected].file.clone())),
+            Some(Ok(Key::Char('q') | Key::Ctrl('c'))) | None => return Ok(None),
+            _ => {},
+        }
+    }
+}
+
+///A line of a menu, with the key which chooses it in bold.
+fn option(key: &str, text: String) -> Vec<Span> {
+    vec![Span::new(key, Style { bold: true, ..Style::default() }), Span::plain(format!("  {text}"))]
+}
+
+///Names of the maps in the search path, at most 10 so each can be chosen with a digit.
+fn list_maps(maps: &MapResolver) -> Vec<String> {
+    maps.maps().into_iter().map(|(name, _)| name).take(10).collect()
+}
@@ -0,0 +1,17 @@
+use std::{env, path::PathBuf};
+
+///Directory for user configuration, following the XDG base directory spec.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("atc"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("atc"))
+}
+
+///Directory for user data, such as installed maps, following the XDG base directory spec.
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("atc"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share").join("atc"))
+}
@@ -1,22 +1,47 @@
-use std::fmt::Display;
+use std::{cmp::Ordering, collections::VecDeque, fmt::Display};
 
-use crate::{command::{Command, CommandTarget, CompleteAltitude, CompleteAnd, CompleteAt, CompleteCommandSegment, CompleteIn, CompleteTurn}, direction::{CardinalDirection, OrdinalDirection}, location::{AirLocation, Destination, GroundLocation, Location}, map::MapStatic, map_objects::{GridRenderable, ListItemPartRenderable, ListRenderable, COMMAND_TARGET_EMPHASIS, COMMAND_TARGET_EMPHASIS_RESET}};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use crate::{command::{Argument, Command, CommandRejection, CommandTarget, CompleteAltitude, CompleteAnd, CompleteAt, CompleteCommandSegment, CompleteDivert, CompleteElse, CompleteEvery, CompleteIn, CompleteTurn}, direction::{CardinalDirection, CircleDirection, OrdinalDirection}, glyphs::glyphs, i18n::tr, location::{AirLocation, Destination, GroundLocation, Location}, map::MapStatic, map_objects::{GridRenderable, Layer, ProcedureKind, ListItemPartRenderable, ListRenderable}, styled::{Span, Style}, units::altitude_unit};
+
+///Number of cells a plane remembers having flown through, for drawing its trail.
+const TRAIL_LENGTH: usize = 6;
+///Characters taken up by a flying plane's tag in the plane list, matching the `plane` heading.
+const LIST_TAG_WIDTH: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Visibility {
     Marked,
     Unmarked,
     Ignored,
-} impl Display for Visibility {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+}
+
+///Who a plane belongs to in hot-seat mode, decided by the letter of its callsign.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Player {
+    ///Planes `a` to `m`.
+    One,
+    ///Planes `n` to `z`.
+    Two,
+} impl Player {
+    pub const ALL: [Player; 2] = [Player::One, Player::Two];
+    pub fn of(callsign: char) -> Player {
+        if callsign.to_ascii_lowercase() <= 'm' { Player::One } else { Player::Two }
+    }
+    pub fn index(self) -> usize {
         match self {
-            Visibility::Marked => Ok(()),
-            Visibility::Unmarked | Visibility::Ignored => write!(f, "\x1b[2m"),
+            Player::One => 0,
+            Player::Two => 1,
         }
     }
+} impl Display for Player {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "P{}", self.index() + 1)
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Plane {
     pub location: Location,
     pub destination: Destination,
@@ -24,21 +49,53 @@ pub struct Plane {
     pub callsign: char,
     pub is_jet: bool,
     pub ticks_active: u32,
+    ///Ticks the plane should take to reach its destination. Reaching it sooner earns a bonus.
+    pub par: u32,
     pub target_direction: OrdinalDirection,
     pub current_direction: OrdinalDirection,
     pub show: Visibility,
     pub command: Option<CompleteCommandSegment>,
+    ///Whether a plane waiting at an airport may take off.
+    pub cleared_for_takeoff: bool,
+    ///Whether the plane has been cleared to land, which it must be at night.
+    #[serde(default)]
+    pub cleared_to_land: bool,
+    ///Written by the player on the plane's flight strip.
+    pub note: String,
+    ///Whether the plane has been given a different destination from the one it entered with.
+    #[serde(default)]
+    pub diverted: bool,
+    ///Ticks active when the plane's radio fails and when it comes back, for a plane which is out
+    ///of contact (NORDO) for a while.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radio_failure: Option<(u32, u32)>,
+    ///Where the plane was before the last tick, for animating its movement.
+    pub previous_location: Location,
+    ///Commands which could only partly be carried out, such as a climb stopped by the ceiling, to
+    ///be shown in the message log.
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+    ///The flight level the plane last reported, shown instead of its own while its transponder is
+    ///not reporting. None when it reports every tick.
+    #[serde(skip)]
+    pub reported_flight_level: Option<u16>,
+    ///Cells the plane has most recently flown through, oldest first, not including where it is now.
+    #[serde(skip)]
+    pub trail: VecDeque<GroundLocation>,
 } impl Plane {
-    pub fn tick(&mut self, map: &MapStatic) {
+    ///Returns a rejection if a pending command could not be carried out when its time came.
+    pub fn tick(&mut self, map: &MapStatic) -> Result<(), CommandRejection> {
+        self.previous_location = self.location;
+        let mut result = Ok(());
         if let Some(cmd) = &self.command {
-            self.exec(cmd.clone(), map);
+            result = self.exec(cmd.clone(), map).map(|_| ());
         }
         match self.location {
             Location::Flight(loc) => {
                 let AirLocation(mut x, mut y, mut flight_level) = loc;
 
 
-                if self.is_jet || self.ticks_active % 2 == 0 {
+                if self.is_jet || self.ticks_active.is_multiple_of(2) {
                     match (self.target_flight_level).cmp(&flight_level) {
                         std::cmp::Ordering::Less => {
                             flight_level -= 1;
@@ -58,26 +115,144 @@ pub struct Plane {
                 }
             },
             Location::Airport(port) => {
-                if self.target_flight_level > 0 {
+                if self.cleared_for_takeoff && self.target_flight_level > 0 {
                     let GroundLocation(x, y) = port.location + <CardinalDirection as Into<OrdinalDirection>>::into(port.launch_direction).as_offset();
                     self.location = Location::Flight(AirLocation(x, y, 1));
                 }
             }
         }
+        if let (Location::Flight(previous), Location::Flight(current)) = (self.previous_location, self.location) {
+            //Prop planes only move every other tick, and should not leave a mark when they stay put.
+            if GroundLocation::from(previous) != GroundLocation::from(current) {
+                self.trail.push_back(previous.into());
+                if self.trail.len() > TRAIL_LENGTH {
+                    self.trail.pop_front();
+                }
+            }
+        }
         self.ticks_active += 1;
+        match self.radio_failure {
+            Some((from, until)) if self.ticks_active == from => self.warnings.push(tr!(RadioLost, plane = self.callsign, ticks = until - from)),
+            Some((_, until)) if self.ticks_active == until => self.warnings.push(tr!(RadioRestored, plane = self.callsign)),
+            _ => {},
+        }
+        result
+    }
+    ///Whether the plane's radio has failed, so that it can't be given commands.
+    pub fn is_nordo(&self) -> bool {
+        self.radio_failure.is_some_and(|(from, until)| (from..until).contains(&self.ticks_active))
     }
-    fn flight_level(&self) -> u16 {
+    ///Ticks until the plane's radio works again, or 0 if it does.
+    pub fn ticks_until_radio(&self) -> u32 {
+        match self.radio_failure {
+            Some((_, until)) if self.is_nordo() => until - self.ticks_active,
+            _ => 0,
+        }
+    }
+    ///Callsign and flight level, followed by an arrow and the flight level it has been cleared to
+    ///if it is climbing or descending, like `b5^7`, and a marker if it is turning.
+    pub fn tag(&self) -> String {
+        self.tag_spans(Style::default()).into_iter().map(|span| span.text).collect()
+    }
+    ///The tag in `style`, with a flight level which has not been reported for a while set apart.
+    fn tag_spans(&self, style: Style) -> Vec<Span> {
+        let (level, is_stale) = match self.shown_flight_level() {
+            Some(level) => (level, true),
+            None => (self.flight_level(), false),
+        };
+        let vertical = match self.target_flight_level.cmp(&level) {
+            Ordering::Greater => format!("{}{}", glyphs().climb, altitude_unit().short(self.target_flight_level)),
+            Ordering::Less => format!("{}{}", glyphs().descend, altitude_unit().short(self.target_flight_level)),
+            Ordering::Equal => String::new(),
+        };
+        let turn = match self.turn_trend() {
+            Some(CircleDirection::Clockwise) => glyphs().turn_clockwise,
+            Some(CircleDirection::CounterClockwise) => glyphs().turn_counterclockwise,
+            None => "",
+        };
+        let level_style = if is_stale { Style { dim: true, ..style } } else { style };
+        [Span::new(self.callsign, style), Span::new(altitude_unit().short(level), level_style), Span::new(format!("{vertical}{turn}"), style)]
+            .into_iter()
+            .filter(|span| !span.text.is_empty())
+            .collect()
+    }
+    ///The style of the plane's tag: green if it is marked, dim otherwise, and underlined if it is
+    ///being given a command.
+    fn tag_style(&self, command: &Command) -> Style {
+        let underline = match command.target {
+            CommandTarget::Plane(p) => p.eq_ignore_ascii_case(&self.callsign),
+            CommandTarget::All => self.show == Visibility::Marked,
+            _ => false,
+        };
+        match self.show {
+            Visibility::Marked => Style { underline, ..Style::colored(2) },
+            _ => Style { underline, dim: true, ..Style::default() },
+        }
+    }
+    ///The flight level last reported, if it is shown instead of the plane's own. Marked planes are
+    ///always watched closely enough to be up to date.
+    fn shown_flight_level(&self) -> Option<u16> {
+        self.reported_flight_level.filter(|_| self.show != Visibility::Marked)
+    }
+    ///Which way the plane is turning, if it is in the air and has not reached its heading.
+    pub fn turn_trend(&self) -> Option<CircleDirection> {
+        if matches!(self.location, Location::Airport(_)) {
+            return None;
+        }
+        match self.current_direction.turn_steps_between(self.target_direction).cmp(&0) {
+            Ordering::Greater => Some(CircleDirection::Clockwise),
+            Ordering::Less => Some(CircleDirection::CounterClockwise),
+            Ordering::Equal => None,
+        }
+    }
+    pub fn flight_level(&self) -> u16 {
         match self.location {
             Location::Airport(_) => 0,
             Location::Flight(AirLocation(_, _, fl)) => fl,
         }
     }
-    pub fn exec(&mut self, mut command: CompleteCommandSegment, map: &MapStatic) -> bool {
+    ///Misreads the instruction which changed the plane's targets from those of `previous`: the pilot
+    ///turns the wrong way, or levels off a flight level above or below the one given, and says so in
+    ///a warning. Nothing happens if the instruction changed neither.
+    pub fn misread(&mut self, previous: &Plane, map: &MapStatic, rng: &mut impl Rng) {
+        let steps = self.current_direction.turn_steps_between(self.target_direction);
+        //Circling planes choose a new direction every tick, and turns straight back have no wrong way.
+        let can_turn = self.target_direction != previous.target_direction && steps.abs() % 4 != 0
+            && !matches!(self.command, Some(CompleteCommandSegment::Circle(_)));
+        let can_level = self.target_flight_level != previous.target_flight_level;
+        if can_turn && (!can_level || rng.random_bool(0.5)) {
+            self.target_direction = self.current_direction.rotated(-steps);
+            self.warnings.push(tr!(MisreadHeading, plane = self.callsign, heading = format!("{:03}", self.target_direction.to_deg())));
+        } else if can_level {
+            let given = self.target_flight_level;
+            let misread = if given == 0 || (given < map.ceiling() && rng.random_bool(0.5)) { given + 1 } else { given - 1 };
+            self.target_flight_level = misread;
+            self.warnings.push(tr!(MisreadAltitude, plane = self.callsign, altitude = altitude_unit().long(misread)));
+        }
+    }
+    ///Returns whether the command has finished, or a rejection if it cannot be carried out. A
+    ///repeating command counts as finished once it is set up. A rejected command may have been
+    ///partially applied.
+    pub fn exec(&mut self, mut command: CompleteCommandSegment, map: &MapStatic) -> Result<bool, CommandRejection> {
         match command {
             CompleteCommandSegment::SetVisibility(v) => self.show = v.into(),
-            CompleteCommandSegment::Altitude(CompleteAltitude::To(a)) => self.target_flight_level = a,
-            CompleteCommandSegment::Altitude(CompleteAltitude::Plus(a)) => self.target_flight_level += a,
-            CompleteCommandSegment::Altitude(CompleteAltitude::Minus(a)) => self.target_flight_level -= a,
+            CompleteCommandSegment::Altitude(altitude) => {
+                let requested = match altitude {
+                    CompleteAltitude::To(a) => a.value() as i32,
+                    CompleteAltitude::Plus(a) => self.target_flight_level as i32 + a.value() as i32,
+                    CompleteAltitude::Minus(a) => self.target_flight_level as i32 - a.value() as i32,
+                };
+                let ceiling = map.ceiling();
+                self.target_flight_level = requested.clamp(0, ceiling as i32) as u16;
+                if requested > ceiling as i32 {
+                    self.warnings.push(tr!(AboveCeiling, plane = self.callsign, ceiling = ceiling));
+                } else if requested < 0 {
+                    self.warnings.push(tr!(BelowGround, plane = self.callsign));
+                }
+            },
+            CompleteCommandSegment::Turn(_) | CompleteCommandSegment::Circle(_) if matches!(self.location, Location::Airport(_)) => {
+                return Err(CommandRejection::PlaneOnGround(self.callsign));
+            },
             CompleteCommandSegment::Turn(CompleteTurn::ToHeading(h)) => {
                 self.target_direction = h;
                 if let Some(CompleteCommandSegment::Circle(_)) = self.command {
@@ -92,34 +267,155 @@ pub struct Plane {
                 if poi.is_satisfied(self, map) {
                     self.command = None;
                     if self.show == Visibility::Unmarked { self.show = Visibility::Marked };
-                    self.exec(*tail.clone(), map);
+                    self.exec(*tail.clone(), map)?;
                 } else {
                     self.command = Some(command);
-                    return false;
+                    return Ok(false);
                 }
             },
+            CompleteCommandSegment::Else(CompleteElse { ref at, timeout, ref mut remaining, ref fallback }) => {
+                if at.poi.is_satisfied(self, map) {
+                    self.command = None;
+                    if self.show == Visibility::Unmarked { self.show = Visibility::Marked };
+                    self.exec(*at.tail.clone(), map)?;
+                } else {
+                    let remaining = remaining.get_or_insert(timeout.value());
+                    if *remaining == 0 {
+                        self.command = None;
+                        if self.show == Visibility::Unmarked { self.show = Visibility::Marked };
+                        self.exec(*fallback.clone(), map)?;
+                    } else {
+                        *remaining -= 1;
+                        self.command = Some(command);
+                        return Ok(false);
+                    }
+                }
+            },
+            CompleteCommandSegment::And(CompleteAnd { ref left, ref right }) if left.repeats() => {
+                //Repeating never finishes, so it carries on beside whatever the right side leaves waiting.
+                self.exec(*left.clone(), map)?;
+                let repeating = self.command.take();
+                let finished = self.exec(*right.clone(), map)?;
+                self.command = match (repeating, self.command.take()) {
+                    (Some(repeating), Some(waiting)) => Some(CompleteCommandSegment::And(CompleteAnd { left: Box::new(repeating), right: Box::new(waiting) })),
+                    (repeating, waiting) => waiting.or(repeating),
+                };
+                return Ok(finished);
+            },
             CompleteCommandSegment::And(CompleteAnd { ref left, ref right }) => {
-                if self.exec(*left.clone(), map) {
-                    self.exec(*right.clone(), map);
+                if self.exec(*left.clone(), map)? {
+                    self.exec(*right.clone(), map)?;
                 } else {
-                    self.command = Some(command);
-                    return false;
+                    //Keep whatever progress the left side made while waiting.
+                    let left = self.command.take().map(Box::new).unwrap_or(left.clone());
+                    self.command = Some(CompleteCommandSegment::And(CompleteAnd { left, right: right.clone() }));
+                    return Ok(false);
                 }
             },
             CompleteCommandSegment::In(CompleteIn { ref tail, ref mut time }) => {
-                if *time > 0 {
-                    *time -= 1;
+                if time.value() > 0 {
+                    *time = Argument::Value(time.value() - 1);
                     self.command = Some(command);
                 } else {
                     self.command = None;
                     if self.show == Visibility::Unmarked { self.show = Visibility::Marked };
-                    self.exec(*tail.clone(), map);
+                    self.exec(*tail.clone(), map)?;
+                }
+            },
+            CompleteCommandSegment::Every(CompleteEvery { ref tail, interval, ref mut remaining }) => {
+                match remaining {
+                    Some(r) if *r > 0 => *r -= 1,
+                    _ => {
+                        if remaining.is_some() {
+                            self.exec(*tail.clone(), map)?;
+                        }
+                        *remaining = Some(interval.value().saturating_sub(1));
+                    },
+                }
+                //Repeating never finishes, but it should not hold up whatever was given after it.
+                self.command = Some(command);
+            },
+            CompleteCommandSegment::Cancel => self.command = None,
+            CompleteCommandSegment::Takeoff => {
+                if !matches!(self.location, Location::Airport(_)) {
+                    return Err(CommandRejection::PlaneAirborne(self.callsign));
+                }
+                self.cleared_for_takeoff = true;
+                self.target_flight_level = self.target_flight_level.max(1);
+            },
+            CompleteCommandSegment::Land => {
+                let on_final = match (self.location, self.destination) {
+                    (Location::Flight(location), Destination::Airport(airport)) => airport.final_approach().contains(&location.into()),
+                    _ => false,
+                };
+                if !on_final {
+                    return Err(CommandRejection::NotOnFinal(self.callsign));
+                }
+                self.cleared_to_land = true;
+            },
+            CompleteCommandSegment::Note(note) => self.note = note,
+            CompleteCommandSegment::Divert(divert) => {
+                let destination = match divert {
+                    CompleteDivert::Exit(n) => map.exits.iter().find(|exit| exit.index == n).map(|exit| Destination::Exit(*exit)),
+                    CompleteDivert::Airport(n) => map.airports.iter().find(|airport| airport.index == n).map(|airport| Destination::Airport(*airport)),
+                };
+                let destination = destination.ok_or(CommandRejection::NoSuchDestination(divert))?;
+                if destination != self.destination {
+                    self.destination = destination;
+                    self.diverted = true;
+                }
+            },
+            CompleteCommandSegment::Airway(n) => {
+                let clearance = map.airway_clearance(n).ok_or(CommandRejection::NoSuchAirway(n))?;
+                return self.exec(clearance, map);
+            },
+            CompleteCommandSegment::Procedure(n) => {
+                let procedure = map.procedures.iter().find(|p| p.index == n).ok_or(CommandRejection::NoSuchProcedure(n))?;
+                let is_for_plane = match (procedure.kind, self.location, self.destination) {
+                    (ProcedureKind::Departure, Location::Airport(airport), _) => airport.index == procedure.airport,
+                    (ProcedureKind::Arrival, _, Destination::Airport(airport)) => airport.index == procedure.airport,
+                    _ => false,
+                };
+                if !is_for_plane {
+                    return Err(CommandRejection::WrongProcedure(self.callsign, n));
                 }
+                let clearance = map.procedure_clearance(procedure).ok_or(CommandRejection::NoSuchProcedure(n))?;
+                return self.exec(clearance, map);
             },
             CompleteCommandSegment::None => {},
             CompleteCommandSegment::Ref(_) => unreachable!("map should have cast this to its inner value"),
+            CompleteCommandSegment::Hold(_) => unreachable!("holds are only given to planes which have not appeared"),
         }
-        return true;
+        Ok(true)
+    }
+    ///The plane's destination, in italics if it has been diverted, and what it must leave at.
+    fn destination_spans(&self, colorize: bool) -> Vec<Span> {
+        let mut destination = self.destination.label(colorize, true);
+        destination.style.italic = self.diverted;
+        [vec![destination], self.destination.requirement_spans()].concat()
+    }
+    ///Renders the plane as a flight strip: callsign, type, assigned flight level and destination,
+    ///then its cleared route and note.
+    pub fn render_strip(&self, command: &Command) -> Vec<Vec<Span>> {
+        let colorize = self.show == Visibility::Marked;
+        let kind = if self.is_jet { "jet " } else { "prop" };
+        let route = match (self.show, &self.command) {
+            (Visibility::Ignored, _) => vec![Span::plain("---")],
+            (_, Some(c)) => c.spans(colorize),
+            (_, None) => vec![Span::new("no clearance", Style { dim: true, ..Style::default() })],
+        };
+        let glyphs = glyphs();
+        vec![
+            [
+                vec![Span::plain(format!("{} ", glyphs.top_left))],
+                <Plane as GridRenderable>::spans(self, command),
+                vec![Span::plain(format!(" {} {kind} {:<6} {} ", glyphs.direction(self.current_direction), altitude_unit().long(self.target_flight_level), glyphs.arrow))],
+                self.destination_spans(colorize),
+                vec![Span::plain(" ")],
+            ].concat(),
+            [vec![Span::plain(format!("{} ", glyphs.vertical))], route].concat(),
+            vec![Span::plain(format!("{} ", glyphs.bottom_left)), Span::new(self.note.clone(), Style { italic: true, ..Style::default() })],
+        ]
     }
 } impl GridRenderable for Plane {
     fn location(&self) -> Option<GroundLocation> {
@@ -128,39 +424,39 @@ pub struct Plane {
             Location::Flight(air_location) => Some(air_location.into()),
         }
     }
-    fn render(&self, command: &Command) -> String {
-        let emphasis = match command.target {
-            CommandTarget::Plane(p) if p.to_ascii_lowercase() == self.callsign.to_ascii_lowercase() => format!("{COMMAND_TARGET_EMPHASIS}"),
-            _ => String::new(),
-        };
-        let color = match self.show {
-            Visibility::Marked => "\x1b[32m",
-            _ => "\x1b[2m",
-        };
-
-        format!("{}{}{}{}{COMMAND_TARGET_EMPHASIS_RESET}\x1b[39m\x1b[22m", emphasis, color, self.callsign, self.flight_level())
+    fn spans(&self, command: &Command) -> Vec<Span> {
+        self.tag_spans(self.tag_style(command))
+    }
+    fn width(&self) -> usize {
+        self.tag().chars().count()
+    }
+    fn layer(&self) -> Layer {
+        Layer::Datablock
     }
 } impl ListRenderable for Plane {
-    fn render(&self, command: &Command) -> String {
+    fn spans(&self, command: &Command) -> Vec<Span> {
         let colorize = self.show == Visibility::Marked;
-        let emphasis = match command.target {
-            CommandTarget::Plane(p) if p.to_ascii_lowercase() == self.callsign.to_ascii_lowercase() => format!("{COMMAND_TARGET_EMPHASIS}"),
-            _ => String::new(),
-        };
-        let color = match self.show {
-            Visibility::Marked => "\x1b[32m",
-            _ => "\x1b[2m",
-        };
-        let airport = match self.location {
-            Location::Flight(_) => format!("   "),
-            Location::Airport(a) => format!("@{}", a.to_display_string(colorize)),
-        };
-        let command = match (self.show, &self.command) {
-            (Visibility::Ignored, _) => format!("---"),
-            (Visibility::Unmarked, Some(c)) => c.render(false),
-            (Visibility::Marked, Some(c)) => c.render(true),
-            _ => String::new(),
-        };
-        format!("\x1b[0m{}{}{}{}{COMMAND_TARGET_EMPHASIS_RESET}\x1b[39m{} {}   {}", emphasis, color, self.callsign, self.flight_level(), airport, self.destination.to_display_string(colorize, true), command)
+        let tag = self.tag();
+        let mut spans = <Plane as GridRenderable>::spans(self, command);
+        match self.location {
+            //Keeps the destination column lined up, unless the tag is unusually long.
+            Location::Flight(_) => spans.push(Span::plain(" ".repeat(LIST_TAG_WIDTH.saturating_sub(tag.chars().count())))),
+            Location::Airport(a) => spans.extend([Span::plain("@"), a.label(colorize)]),
+        }
+        spans.push(Span::plain(" "));
+        spans.extend(self.destination_spans(colorize));
+        spans.push(Span::plain("   "));
+        if matches!(self.location, Location::Airport(_)) && !self.cleared_for_takeoff {
+            spans.extend([Span::new("hold", Style { reverse: true, ..Style::default() }), Span::plain(" ")]);
+        }
+        if self.is_nordo() {
+            spans.extend([Span::new(format!("nordo {}", self.ticks_until_radio()), Style { reverse: true, ..Style::colored(1) }), Span::plain(" ")]);
+        }
+        match (self.show, &self.command) {
+            (Visibility::Ignored, _) => spans.push(Span::plain("---")),
+            (_, Some(c)) => spans.extend(c.spans(colorize)),
+            _ => {},
+        }
+        spans
     }
 }
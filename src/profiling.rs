@@ -0,0 +1,99 @@
+#[cfg(feature = "profiling")]
+use std::{collections::BTreeMap, fs, path::Path, sync::{Mutex, OnceLock}, time::{Duration, Instant}};
+
+#[cfg(feature = "profiling")]
+use anyhow::Result;
+
+///Most spans kept for the trace. Totals for the summary are kept for every span.
+#[cfg(feature = "profiling")]
+const MAX_TRACED_SPANS: usize = 1_000_000;
+
+#[cfg(feature = "profiling")]
+static PROFILE: Mutex<Profile> = Mutex::new(Profile { totals: BTreeMap::new(), spans: vec![] });
+///When profiling started, which trace timestamps count from.
+#[cfg(feature = "profiling")]
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+#[cfg(feature = "profiling")]
+struct Profile {
+    totals: BTreeMap<&'static str, Total>,
+    spans: Vec<(&'static str, Duration, Duration)>,
+}
+
+///Every time a span with one name was measured.
+#[cfg(feature = "profiling")]
+#[derive(Default)]
+struct Total {
+    count: u32,
+    time: Duration,
+    longest: Duration,
+}
+
+///Measures the time until it is dropped, under a name, when built with the `profiling` feature.
+///Without it, spans do nothing.
+#[must_use = "a span measures until it is dropped"]
+pub struct Span {
+    #[cfg(feature = "profiling")]
+    name: &'static str,
+    #[cfg(feature = "profiling")]
+    start: Instant,
+} impl Drop for Span {
+    fn drop(&mut self) {
+        #[cfg(feature = "profiling")]
+        {
+            let duration = self.start.elapsed();
+            let started = self.start.duration_since(*STARTED.get_or_init(Instant::now));
+            let mut profile = PROFILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let total = profile.totals.entry(self.name).or_default();
+            total.count += 1;
+            total.time += duration;
+            total.longest = total.longest.max(duration);
+            if profile.spans.len() < MAX_TRACED_SPANS {
+                profile.spans.push((self.name, started, duration));
+            }
+        }
+    }
+}
+
+///Starts measuring a part of the game, until the span returned is dropped.
+#[cfg_attr(not(feature = "profiling"), allow(unused_variables))]
+pub fn span(name: &'static str) -> Span {
+    #[cfg(feature = "profiling")]
+    {
+        let start = Instant::now();
+        STARTED.get_or_init(|| start);
+        Span { name, start }
+    }
+    #[cfg(not(feature = "profiling"))]
+    Span {}
+}
+
+///A table of how many times each part was measured, and how long it took in total, on average, and
+///at most.
+#[cfg(feature = "profiling")]
+pub fn summary() -> String {
+    let profile = PROFILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut summary = format!("{:<20} {:>8} {:>12} {:>12} {:>12}\n", "span", "count", "total", "average", "longest");
+    for (name, total) in &profile.totals {
+        let average = total.time / total.count.max(1);
+        summary += &format!("{name:<20} {:>8} {:>12.3?} {:>12.3?} {:>12.3?}\n", total.count, total.time, average, total.longest);
+    }
+    summary
+}
+
+///Writes every span measured as a Chrome trace, which can be opened in `chrome://tracing` or
+///Perfetto.
+#[cfg(feature = "profiling")]
+pub fn write_trace(path: &Path) -> Result<()> {
+    let profile = PROFILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let events = profile.spans.iter().map(|(name, start, duration)| serde_json::json!({
+        "name": name,
+        "ph": "X",
+        "ts": start.as_secs_f64() * 1e6,
+        "dur": duration.as_secs_f64() * 1e6,
+        "pid": 0,
+        "tid": 0,
+    })).collect::<Vec<_>>();
+    fs::write(path, serde_json::to_string(&serde_json::json!({ "traceEvents": events }))?)?;
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::{env, fs::File, io::{self, BufWriter, Write}, path::Path, time::{Instant, SystemTime, UNIX_EPOCH}};
+
+use anyhow::Result;
+
+///Passes output through to the terminal, and, if asked to, records it as an asciinema cast (format
+///version 2): a header line, then a line for each time the output is flushed, giving the time and
+///what was written since the last.
+pub struct Recorder<W: Write> {
+    inner: W,
+    cast: Option<Cast>,
+}
+
+struct Cast {
+    file: BufWriter<File>,
+    started: Instant,
+    ///Output written since the last flush.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Recorder<W> {
+    ///Records to the file at `path`, if there is one.
+    pub fn new(inner: W, path: Option<&Path>) -> Result<Self> {
+        let cast = match path {
+            Some(path) => {
+                let mut file = BufWriter::new(File::create(path)?);
+                let (width, height) = termion::terminal_size()?;
+                let header = serde_json::json!({
+                    "version": 2,
+                    "width": width,
+                    "height": height,
+                    "timestamp": SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                    "env": { "TERM": env::var("TERM").unwrap_or_default() },
+                });
+                writeln!(file, "{header}")?;
+                Some(Cast { file, started: Instant::now(), pending: vec![] })
+            },
+            None => None,
+        };
+        Ok(Recorder { inner, cast })
+    }
+} impl<W: Write> Write for Recorder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(cast) = &mut self.cast {
+            cast.pending.extend_from_slice(&buf[..written]);
+        }
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        let Some(cast) = &mut self.cast else { return Ok(()) };
+        if cast.pending.is_empty() {
+            return Ok(());
+        }
+        let event = (cast.started.elapsed().as_secs_f64(), "o", String::from_utf8_lossy(&cast.pending));
+        writeln!(cast.file, "{}", serde_json::to_string(&event)?)?;
+        cast.pending.clear();
+        cast.file.flush()
+    }
+}
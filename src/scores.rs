@@ -0,0 +1,35 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::paths;
+
+fn scores_file() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("scores.json"))
+}
+
+///The best score on each map, by map name.
+fn load_scores() -> HashMap<String, u32> {
+    let Some(file) = scores_file() else { return HashMap::new() };
+    let Ok(text) = fs::read(file) else { return HashMap::new() };
+    serde_json::de::from_slice(&text).unwrap_or_default()
+}
+
+pub fn personal_best(map: &str) -> Option<u32> {
+    load_scores().get(map).copied()
+}
+
+///Saves a score if it is the best on its map. Returns whether it was.
+pub fn record_score(map: &str, score: u32) -> Result<bool> {
+    let mut scores = load_scores();
+    if scores.get(map).is_some_and(|best| *best >= score) {
+        return Ok(false);
+    }
+    scores.insert(map.to_string(), score);
+    let Some(file) = scores_file() else { return Ok(true) };
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, serde_json::ser::to_string_pretty(&scores)?)?;
+    Ok(true)
+}
@@ -0,0 +1,115 @@
+use std::{fmt::Display, str::FromStr};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+///Planes come this many times as often during a rush.
+const RUSH_FACTOR: u32 = 2;
+///A rush and the quiet spell after it last this many times the spawn rate.
+const RUSH_CYCLE: u32 = 12;
+
+///How planes are spaced out over time. Each averages about one plane every `plane_spawn_rate`
+///ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpawnPattern {
+    ///Exactly one plane every `plane_spawn_rate` ticks.
+    #[default]
+    Regular,
+    ///Planes arrive independently of each other, so they sometimes bunch up and sometimes leave
+    ///long gaps.
+    Poisson,
+    ///Rushes, a quarter of the time, where planes come twice as often, between quiet spells.
+    Bursts,
+    ///A gap of between the first and second number of ticks, chosen at random, instead of
+    ///`plane_spawn_rate`.
+    Gaps(u32, u32),
+} impl SpawnPattern {
+    ///Ticks from a plane announced on tick `tick` to the next.
+    pub fn gap(self, rate: u32, tick: u32, rng: &mut impl Rng) -> u32 {
+        match self {
+            SpawnPattern::Regular => rate,
+            SpawnPattern::Poisson => {
+                //Gaps between independent arrivals are exponentially distributed.
+                let gap = -(1.0 - rng.random::<f64>()).ln() * rate as f64;
+                (gap.round() as u32).max(1)
+            },
+            SpawnPattern::Bursts => {
+                let cycle = (rate * RUSH_CYCLE).max(1);
+                if tick % cycle < cycle / 4 {
+                    (rate / RUSH_FACTOR).max(1)
+                } else {
+                    //Keeps the average at about one plane every `rate` ticks.
+                    rate * 3 / 2
+                }
+            },
+            SpawnPattern::Gaps(shortest, longest) => rng.random_range(shortest..=longest),
+        }
+    }
+    ///Describes what is wrong with the pattern, if anything.
+    pub fn problem(self) -> Option<String> {
+        match self {
+            SpawnPattern::Gaps(0, _) => Some(format!("The shortest gap between planes must be at least 1 tick.")),
+            SpawnPattern::Gaps(shortest, longest) if shortest > longest => Some(format!("The shortest gap between planes, {shortest}, is longer than the longest, {longest}.")),
+            _ => None,
+        }
+    }
+} impl FromStr for SpawnPattern {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = match s.split_once(':') {
+            None if s == "regular" => SpawnPattern::Regular,
+            None if s == "poisson" => SpawnPattern::Poisson,
+            None if s == "bursts" => SpawnPattern::Bursts,
+            Some(("gaps", range)) => {
+                let Some((shortest, longest)) = range.split_once('-') else { return Err(format!("expected gaps:<shortest>-<longest>")) };
+                let (Ok(shortest), Ok(longest)) = (shortest.parse(), longest.parse()) else { return Err(format!("expected gaps:<shortest>-<longest>")) };
+                SpawnPattern::Gaps(shortest, longest)
+            },
+            _ => return Err(format!("expected regular, poisson, bursts, or gaps:<shortest>-<longest>")),
+        };
+        match pattern.problem() {
+            Some(problem) => Err(problem),
+            None => Ok(pattern),
+        }
+    }
+} impl Display for SpawnPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnPattern::Regular => write!(f, "regular"),
+            SpawnPattern::Poisson => write!(f, "poisson"),
+            SpawnPattern::Bursts => write!(f, "bursts"),
+            SpawnPattern::Gaps(shortest, longest) => write!(f, "gaps:{shortest}-{longest}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn parses_what_it_displays() {
+        for pattern in [SpawnPattern::Regular, SpawnPattern::Poisson, SpawnPattern::Bursts, SpawnPattern::Gaps(5, 20)] {
+            assert_eq!(pattern.to_string().parse(), Ok(pattern));
+        }
+        assert!("gaps:20-5".parse::<SpawnPattern>().is_err());
+        assert!("steady".parse::<SpawnPattern>().is_err());
+    }
+
+    #[test]
+    fn averages_about_the_spawn_rate() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for pattern in [SpawnPattern::Regular, SpawnPattern::Poisson, SpawnPattern::Bursts, SpawnPattern::Gaps(10, 30)] {
+            let (mut tick, mut planes) = (0, 0);
+            while tick < 100_000 {
+                tick += pattern.gap(20, tick, &mut rng);
+                planes += 1;
+            }
+            let average = tick as f64 / planes as f64;
+            assert!((17.0..23.0).contains(&average), "{pattern} averaged a plane every {average} ticks");
+        }
+    }
+}
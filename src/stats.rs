@@ -0,0 +1,246 @@
+use std::{collections::VecDeque, fs, path::{Path, PathBuf}};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{location::Destination, plane::Plane};
+
+///Most ticks kept. Older ones are forgotten as new ones are recorded, so that long games don't
+///grow the statistics, which are copied whenever the map is.
+const TICKS_KEPT: usize = 2000;
+///Most finished planes kept. Older ones are counted in `GameStats::forgotten` instead.
+const FINISHED_PLANES_KEPT: usize = 200;
+///Most altitudes and command ticks kept for each plane.
+const PLANE_HISTORY_KEPT: usize = 1000;
+///Most conflict starts kept.
+const CONFLICT_STARTS_KEPT: usize = 100;
+
+///What happened to one plane, from when it appeared.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaneStats {
+    pub callsign: char,
+    pub is_jet: bool,
+    pub start: String,
+    pub destination: String,
+    ///Tick on which the plane appeared.
+    pub entered: u32,
+    ///Tick on which the plane reached its destination, if it did.
+    pub finished: Option<u32>,
+    ///Ticks between the plane appearing and reaching its destination, or the end of the game.
+    pub handling_time: u32,
+    ///Ticks the plane could take to reach its destination and still earn the par bonus.
+    pub par: u32,
+    pub commands: u32,
+    pub conflicts: u32,
+    ///The plane's flight level at the end of each tick it was in the game, from the latest
+    ///`PLANE_HISTORY_KEPT` ticks.
+    pub altitudes: Vec<u16>,
+    ///Altitudes forgotten from the start of `altitudes`.
+    #[serde(skip)]
+    pub earlier_altitudes: u32,
+    ///Ticks on which the plane was given each of its latest commands.
+    #[serde(skip)]
+    pub command_ticks: Vec<u32>,
+} impl PlaneStats {
+    pub fn record_command(&mut self, tick: u32) {
+        self.commands += 1;
+        if self.command_ticks.len() == PLANE_HISTORY_KEPT {
+            self.command_ticks.remove(0);
+        }
+        self.command_ticks.push(tick);
+    }
+    fn record_altitude(&mut self, altitude: u16) {
+        if self.altitudes.len() == PLANE_HISTORY_KEPT {
+            self.altitudes.remove(0);
+            self.earlier_altitudes += 1;
+        }
+        self.altitudes.push(altitude);
+    }
+}
+
+///Totals for the finished planes which are no longer kept.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ForgottenPlanes {
+    pub planes: u32,
+    pub handling_time: u32,
+    pub conflicts: u32,
+}
+
+///The state of the game at the end of one tick.
+#[derive(Debug, Clone, Serialize)]
+pub struct TickStats {
+    pub tick: u32,
+    pub planes: usize,
+    pub conflicts: usize,
+    pub score: u32,
+}
+
+///Enough of the statistics to put them back as they were at the start of a tick, for rewinding,
+///without copying them all. Planes which had finished by then don't change afterward.
+#[derive(Debug, Clone)]
+pub struct StatsMark {
+    tick: u32,
+    unfinished: Vec<PlaneStats>,
+}
+
+///Statistics collected over a game, for exporting.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GameStats {
+    pub planes: Vec<PlaneStats>,
+    pub forgotten: ForgottenPlanes,
+    ///The latest `TICKS_KEPT` ticks.
+    pub ticks: VecDeque<TickStats>,
+    ///Ticks on which pairs of planes came into conflict, by their callsigns.
+    #[serde(skip)]
+    conflict_starts: VecDeque<(u32, char, char)>,
+} impl GameStats {
+    pub fn plane_entered(&mut self, plane: &Plane, start: Destination, tick: u32) {
+        self.add(PlaneStats {
+            callsign: plane.callsign,
+            is_jet: plane.is_jet,
+            start: start.to_string(),
+            destination: plane.destination.to_string(),
+            entered: tick,
+            finished: None,
+            handling_time: 0,
+            par: plane.par,
+            commands: 0,
+            conflicts: 0,
+            altitudes: vec![],
+            earlier_altitudes: 0,
+            command_ticks: vec![],
+        });
+    }
+    ///Adds a plane's statistics, forgetting the oldest finished plane if too many are kept.
+    fn add(&mut self, stats: PlaneStats) {
+        if self.planes.iter().filter(|stats| stats.finished.is_some()).count() >= FINISHED_PLANES_KEPT {
+            if let Some(oldest) = self.planes.iter().position(|stats| stats.finished.is_some()) {
+                let oldest = self.planes.remove(oldest);
+                self.forgotten.planes += 1;
+                self.forgotten.handling_time += oldest.handling_time;
+                self.forgotten.conflicts += oldest.conflicts;
+            }
+        }
+        self.planes.push(stats);
+    }
+    ///The statistics of the plane in the game with this callsign. Callsigns are reused, so this is
+    ///the latest plane to have it.
+    pub fn plane(&mut self, callsign: char) -> Option<&mut PlaneStats> {
+        self.planes.iter_mut().rev().find(|stats| stats.callsign == callsign && stats.finished.is_none())
+    }
+    ///The statistics of the latest plane with this callsign, whether or not it has finished.
+    pub fn latest(&self, callsign: char) -> Option<&PlaneStats> {
+        self.planes.iter().rev().find(|stats| stats.callsign == callsign)
+    }
+    pub fn conflict_began(&mut self, tick: u32, a: char, b: char) {
+        if self.conflict_starts.len() == CONFLICT_STARTS_KEPT {
+            self.conflict_starts.pop_front();
+        }
+        self.conflict_starts.push_back((tick, a, b));
+    }
+    ///The tick on which the latest conflict between two planes began, if they have had one.
+    pub fn conflict_start(&self, a: char, b: char) -> Option<u32> {
+        self.conflict_starts.iter().rev().find(|(_, first, second)| (*first, *second) == (a, b) || (*first, *second) == (b, a)).map(|(tick, ..)| *tick)
+    }
+    ///Marks the statistics as they are at the start of `tick`.
+    pub fn mark(&self, tick: u32) -> StatsMark {
+        StatsMark { tick, unfinished: self.planes.iter().filter(|plane| plane.finished.is_none()).cloned().collect() }
+    }
+    ///Forgets everything recorded since `mark` was made. Planes forgotten since then stay counted
+    ///in `forgotten`, as only planes which had finished long before are forgotten.
+    pub fn rewind(&mut self, mark: StatsMark) {
+        self.planes.retain(|plane| plane.finished.is_some_and(|finished| finished < mark.tick));
+        self.planes.extend(mark.unfinished);
+        //Planes are kept in the order they entered, so that the latest with a callsign is last.
+        self.planes.sort_by_key(|plane| plane.entered);
+        self.ticks.retain(|tick| tick.tick < mark.tick);
+        self.conflict_starts.retain(|(tick, ..)| *tick < mark.tick);
+    }
+    ///Records the state of each plane at the end of a tick.
+    pub fn record_tick(&mut self, tick: u32, planes: &[Plane], conflicts: usize, score: u32) {
+        for plane in planes {
+            if let Some(stats) = self.plane(plane.callsign) {
+                stats.record_altitude(plane.flight_level());
+                stats.handling_time = plane.ticks_active;
+            }
+        }
+        if self.ticks.len() == TICKS_KEPT {
+            self.ticks.pop_front();
+        }
+        self.ticks.push_back(TickStats { tick, planes: planes.len(), conflicts, score });
+    }
+    ///Planes which reached their destinations in the `window` ticks up to `tick`: arrivals, which
+    ///landed, and departures, which left by an exit.
+    pub fn throughput(&self, tick: u32, window: u32) -> (usize, usize) {
+        let finished = self.planes.iter().filter(|plane| plane.finished.is_some_and(|finished| finished + window > tick));
+        //Destinations are recorded as they are shown, so airports start with `A`.
+        let (arrivals, departures) = finished.partition::<Vec<_>, _>(|plane| plane.destination.starts_with('A'));
+        (arrivals.len(), departures.len())
+    }
+    ///The mean handling time of planes which have reached their destinations, if any have.
+    pub fn average_handling_time(&self) -> Option<f32> {
+        let times = self.planes.iter().filter(|plane| plane.finished.is_some()).map(|plane| plane.handling_time).collect::<Vec<_>>();
+        let count = times.len() as u32 + self.forgotten.planes;
+        (count > 0).then(|| (times.iter().sum::<u32>() + self.forgotten.handling_time) as f32 / count as f32)
+    }
+    ///Conflict alerts raised so far. Each is counted by both planes in it.
+    pub fn conflict_alerts(&self) -> u32 {
+        (self.planes.iter().map(|plane| plane.conflicts).sum::<u32>() + self.forgotten.conflicts) / 2
+    }
+    ///Writes the statistics as JSON, or as CSV if the path ends in `.csv`. As CSV, planes are
+    ///written to the path and ticks to a file beside it ending in `-ticks.csv`. Returns the paths
+    ///written to.
+    pub fn save(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        if path.extension().is_none_or(|extension| extension != "csv") {
+            fs::write(path, serde_json::ser::to_string_pretty(self)?)?;
+            return Ok(vec![path.to_path_buf()]);
+        }
+
+        let mut planes = format!("callsign,type,start,destination,entered,finished,handling_time,par,commands,conflicts,altitudes\n");
+        for plane in &self.planes {
+            let altitudes = plane.altitudes.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(" ");
+            planes += &format!(
+                "{},{},{},{},{},{},{},{},{},{},{altitudes}\n",
+                plane.callsign, if plane.is_jet { "jet" } else { "prop" }, plane.start, plane.destination, plane.entered,
+                plane.finished.map(|f| f.to_string()).unwrap_or_default(), plane.handling_time, plane.par, plane.commands, plane.conflicts,
+            );
+        }
+        let mut ticks = format!("tick,planes,conflicts,score\n");
+        for tick in &self.ticks {
+            ticks += &format!("{},{},{},{}\n", tick.tick, tick.planes, tick.conflicts, tick.score);
+        }
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let ticks_path = path.with_file_name(format!("{stem}-ticks.csv"));
+        fs::write(path, planes)?;
+        fs::write(&ticks_path, ticks)?;
+        Ok(vec![path.to_path_buf(), ticks_path])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_old_planes_but_keeps_their_totals() {
+        let mut stats = GameStats::default();
+        for entered in 0..FINISHED_PLANES_KEPT as u32 + 5 {
+            stats.add(PlaneStats {
+                callsign: 'a', is_jet: false, start: format!("E0"), destination: format!("A0"), entered, finished: Some(entered + 10),
+                handling_time: 10, par: 20, commands: 1, conflicts: 1, altitudes: vec![], earlier_altitudes: 0, command_ticks: vec![],
+            });
+        }
+        assert_eq!(stats.planes.len(), FINISHED_PLANES_KEPT);
+        assert_eq!(stats.planes[0].entered, 5);
+        assert_eq!((stats.forgotten.planes, stats.conflict_alerts(), stats.average_handling_time()), (5, (FINISHED_PLANES_KEPT as u32 + 5) / 2, Some(10.0)));
+
+        let plane = stats.planes.last_mut().expect("a plane to be kept");
+        for altitude in 0..PLANE_HISTORY_KEPT as u16 + 3 {
+            plane.record_altitude(altitude);
+        }
+        assert_eq!((plane.altitudes.len(), plane.altitudes[0], plane.earlier_altitudes), (PLANE_HISTORY_KEPT, 3, 3));
+    }
+}
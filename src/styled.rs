@@ -0,0 +1,192 @@
+use std::fmt::{self, Display, Write};
+
+///How text is drawn. Colors are indices into the terminal's 256-color palette, where 0 to 7 are the
+///standard colors, or None for the terminal's default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Option<u8>,
+    pub bg: Option<u8>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+} impl Style {
+    ///The terminal's default style, in a color from its palette.
+    pub fn colored(fg: u8) -> Self {
+        Style { fg: Some(fg), ..Style::default() }
+    }
+    ///This style if `on` is set, or else the default, for text which is only sometimes styled.
+    pub fn when(self, on: bool) -> Self {
+        if on { self } else { Style::default() }
+    }
+    ///The parameters of the SGR escape sequence (`ESC [ ... m`) which turns on everything the style
+    ///sets, from the default style.
+    fn parameters(&self) -> Vec<String> {
+        let mut parameters = [(self.bold, "1"), (self.dim, "2"), (self.italic, "3"), (self.underline, "4"), (self.reverse, "7")]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, code)| code.to_string())
+            .collect::<Vec<_>>();
+        match self.fg {
+            Some(color @ 0..=7) => parameters.push(format!("{}", 30 + color)),
+            Some(color) => parameters.push(format!("38;5;{color}")),
+            None => {},
+        }
+        match self.bg {
+            Some(color @ 0..=7) => parameters.push(format!("{}", 40 + color)),
+            Some(color) => parameters.push(format!("48;5;{color}")),
+            None => {},
+        }
+        parameters
+    }
+    ///Writes the escape sequence which switches to this style from any other.
+    pub fn write_ansi(&self, out: &mut impl Write) -> fmt::Result {
+        out.write_str("\x1b[0")?;
+        for parameter in self.parameters() {
+            write!(out, ";{parameter}")?;
+        }
+        out.write_char('m')
+    }
+}
+
+///A run of text drawn in one style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+} impl Span {
+    pub fn new(text: impl Into<String>, style: Style) -> Self {
+        Span { text: text.into(), style }
+    }
+    ///A span in the default style.
+    pub fn plain(text: impl Into<String>) -> Self {
+        Span::new(text, Style::default())
+    }
+    ///Writes the span with escape sequences, turning off afterward only what its style turned on,
+    ///so that it can be written among other text without undoing that text's style.
+    pub fn write_ansi(&self, out: &mut impl Write) -> fmt::Result {
+        let parameters = self.style.parameters();
+        if parameters.is_empty() {
+            return out.write_str(&self.text);
+        }
+        write!(out, "\x1b[{}m{}", parameters.join(";"), self.text)?;
+        let Style { fg, bg, bold, dim, italic, underline, reverse } = self.style;
+        let resets = [(bold || dim, "22"), (italic, "23"), (underline, "24"), (reverse, "27"), (fg.is_some(), "39"), (bg.is_some(), "49")]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, code)| code)
+            .collect::<Vec<_>>();
+        write!(out, "\x1b[{}m", resets.join(";"))
+    }
+}
+
+///The text of `spans`, without their styling.
+pub fn text(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
+
+///How many characters `spans` take up.
+pub fn width(spans: &[Span]) -> usize {
+    spans.iter().map(|span| span.text.chars().count()).sum()
+}
+
+///Formats spans with escape sequences, to be written among other text.
+pub struct Ansi<'a>(pub &'a [Span]);
+impl Display for Ansi<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|span| span.write_ansi(f))
+    }
+}
+
+///The start of `spans` which takes up at most `width` characters.
+pub fn clip(spans: &[Span], width: usize) -> Vec<Span> {
+    let mut left = width;
+    let mut clipped = vec![];
+    for span in spans {
+        if left == 0 {
+            break;
+        }
+        let text = span.text.chars().take(left).collect::<String>();
+        left -= text.chars().count();
+        clipped.push(Span { text, style: span.style });
+    }
+    clipped
+}
+
+///One character on the screen, and how it is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub glyph: char,
+    pub style: Style,
+}
+
+///A block of the screen as styled characters, independent of how it is shown, so that the same
+///frame can be drawn to the terminal or exported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellGrid {
+    pub width: usize,
+    pub cells: Vec<Cell>,
+} impl CellGrid {
+    ///Lays out lines of spans, padding short lines with blank cells to the width of the longest.
+    pub fn from_lines(lines: &[Vec<Span>]) -> Self {
+        let width = lines.iter().map(|line| width(line)).max().unwrap_or(0);
+        let blank = Cell { glyph: ' ', style: Style::default() };
+        let mut cells = Vec::with_capacity(width * lines.len());
+        for line in lines {
+            let row = line.iter().flat_map(|span| span.text.chars().map(|glyph| Cell { glyph, style: span.style }));
+            let start = cells.len();
+            cells.extend(row);
+            cells.resize(start + width, blank);
+        }
+        CellGrid { width, cells }
+    }
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.width.max(1))
+    }
+    ///Draws the grid from the cursor with escape sequences, leaving the style reset afterward.
+    pub fn write_ansi(&self, out: &mut impl Write) -> fmt::Result {
+        for row in self.rows() {
+            let mut style = None;
+            for cell in row {
+                if style != Some(cell.style) {
+                    cell.style.write_ansi(out)?;
+                    style = Some(cell.style);
+                }
+                out.write_char(cell.glyph)?;
+            }
+            //Returns to the start of the row, then moves down to the next.
+            write!(out, "\x1b[0m\x1b[{}D\x1b[B", self.width)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_undo_only_their_own_style() {
+        let mut ansi = String::new();
+        Span::new("E1", Style { bold: true, reverse: true, ..Style::colored(1) }).write_ansi(&mut ansi).expect("writing to a String not to fail");
+        Span::new(" ", Style::default()).write_ansi(&mut ansi).expect("writing to a String not to fail");
+        assert_eq!(ansi, "\x1b[1;7;31mE1\x1b[22;27;39m ");
+    }
+
+    #[test]
+    fn clips_spans_to_a_width() {
+        let spans = [Span::new("a5", Style::colored(2)), Span::new("^7", Style { dim: true, ..Style::default() })];
+        assert_eq!(clip(&spans, 3), vec![spans[0].clone(), Span::new("^", spans[1].style)]);
+        assert_eq!(clip(&spans, 2), vec![spans[0].clone()]);
+        assert_eq!(clip(&spans, 9), spans.to_vec());
+    }
+
+    #[test]
+    fn draws_rows_of_cells() {
+        let grid = CellGrid::from_lines(&[vec![Span::new("E1", Style::colored(1))], vec![Span::new(".", Style { dim: true, ..Style::default() })]]);
+        let mut ansi = String::new();
+        grid.write_ansi(&mut ansi).expect("writing to a String not to fail");
+        assert_eq!(ansi, "\x1b[0;31mE1\x1b[0m\x1b[2D\x1b[B\x1b[0;2m.\x1b[0m \x1b[0m\x1b[2D\x1b[B");
+    }
+}
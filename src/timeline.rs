@@ -0,0 +1,132 @@
+use std::{collections::VecDeque, io::Write};
+
+use anyhow::Result;
+
+use crate::{i18n::tr, styled::{self, Ansi, Span, Style}};
+
+///Most events kept. Older ones are forgotten as new ones happen.
+const RETENTION: usize = 2000;
+
+///What kind of thing happened, which sets how it is colored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Spawn,
+    Command,
+    Conflict,
+    ///A plane landed or left by its exit.
+    Landing,
+    ///Planes collided, or the game ended.
+    Crash,
+    ///What led to the game being lost.
+    Diagnosis,
+} impl EventKind {
+    fn style(self) -> Style {
+        match self {
+            EventKind::Spawn => Style::colored(2),
+            EventKind::Command => Style::default(),
+            EventKind::Conflict => Style::colored(3),
+            EventKind::Landing => Style::colored(6),
+            EventKind::Crash => Style::colored(1),
+            EventKind::Diagnosis => Style::colored(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub tick: u32,
+    pub kind: EventKind,
+    pub text: Vec<Span>,
+} impl Event {
+    ///The event's text, colored by its kind where it has no color of its own.
+    fn spans(&self) -> Vec<Span> {
+        let color = self.kind.style().fg;
+        self.text.iter().map(|span| Span { style: Style { fg: span.style.fg.or(color), ..span.style }, ..span.clone() }).collect()
+    }
+}
+
+///Everything which happened in a game, shown full-screen when opened. Unlike the message log,
+///it can be scrolled back through, which makes it a post-mortem once the game has ended.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    events: VecDeque<Event>,
+    pub is_open: bool,
+    ///Lines scrolled back from the latest event.
+    scroll: usize,
+} impl Timeline {
+    pub fn record(&mut self, tick: u32, kind: EventKind, text: Vec<Span>) {
+        if self.events.len() == RETENTION {
+            self.events.pop_front();
+        }
+        self.events.push_back(Event { tick, kind, text });
+        //Keep the same events in view while scrolled back.
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+    pub fn toggle(&mut self) {
+        self.is_open = !self.is_open;
+        self.scroll = 0;
+    }
+    ///Scrolls back by a number of lines, or forward if negative.
+    pub fn scroll(&mut self, lines: isize) {
+        self.scroll = self.scroll.saturating_add_signed(lines).min(self.events.len().saturating_sub(1));
+    }
+    ///Draws the events over the whole terminal, latest at the bottom.
+    pub fn render(&self, output: &mut impl Write, (columns, rows): (u16, u16)) -> Result<()> {
+        write!(output, "{}{}", termion::cursor::Goto(1, 1), termion::clear::All)?;
+        let height = rows.saturating_sub(2).max(1) as usize;
+        let last = self.events.len() - self.scroll.min(self.events.len());
+        let first = last.saturating_sub(height);
+        let dim = Style { dim: true, ..Style::default() };
+        let range = if self.events.is_empty() {
+            tr!(NothingHappenedYet)
+        } else {
+            tr!(TimelineRange, first = first + 1, last = last, total = self.events.len())
+        };
+        let heading = [
+            Span::new(tr!(Timeline), Style { bold: true, ..Style::default() }),
+            Span::plain(" "),
+            Span::new(format!("{range} {}", tr!(TimelineHelp)), dim),
+        ];
+        write!(output, "{}", Ansi(&heading))?;
+        for (y, event) in self.events.range(first..last).enumerate() {
+            //Leave room for the tick, and don't wrap onto the next line.
+            let line = [vec![Span::new(format!("{:<5}", event.tick), dim), Span::plain(" ")], event.spans()].concat();
+            write!(output, "{}{}", termion::cursor::Goto(1, 3 + y as u16), Ansi(&styled::clip(&line, columns as usize)))?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_events_in_view_while_scrolled_back() {
+        let mut timeline = Timeline::default();
+        for tick in 0..RETENTION as u32 + 5 {
+            timeline.record(tick, EventKind::Spawn, vec![Span::plain(format!("{tick}"))]);
+        }
+        assert_eq!((timeline.events.len(), timeline.events[0].tick), (RETENTION, 5));
+        timeline.scroll(3);
+        timeline.record(0, EventKind::Command, vec![]);
+        assert_eq!(timeline.scroll, 4);
+        timeline.scroll(-10);
+        assert_eq!(timeline.scroll, 0);
+        timeline.scroll(isize::MAX);
+        assert_eq!(timeline.scroll, RETENTION - 1);
+    }
+
+    #[test]
+    fn colors_text_by_kind_where_it_has_no_color() {
+        let event = Event {
+            tick: 0,
+            kind: EventKind::Crash,
+            text: vec![Span::new("A", Style::colored(2)), Span::new(" crashed", Style { dim: true, ..Style::default() })],
+        };
+        assert_eq!(event.spans(), [Span::new("A", Style::colored(2)), Span::new(" crashed", Style { dim: true, ..Style::colored(1) })]);
+    }
+}
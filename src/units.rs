@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+///Feet in a meter.
+const FEET_PER_METER: f32 = 3.2808;
+
+///How altitudes are shown. The game counts altitude in thousands of feet.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum AltitudeUnit {
+    ///Feet, like `5000ft`, shortened to thousands, like `5`, in datablocks.
+    Feet,
+    ///Flight levels, in hundreds of feet, like `FL050`, shortened to `050` in datablocks.
+    #[value(name = "fl")]
+    FlightLevel,
+    ///Meters, like `1520m`, shortened to kilometers, like `1.5`, in datablocks.
+    Meters,
+} impl AltitudeUnit {
+    ///An altitude in full, for command previews and flight strips.
+    pub fn long(self, altitude: u16) -> String {
+        match self {
+            AltitudeUnit::Feet => format!("{altitude}000ft"),
+            AltitudeUnit::FlightLevel => format!("FL{:03}", altitude * 10),
+            AltitudeUnit::Meters => format!("{}m", (meters(altitude) / 10.0).round() * 10.0),
+        }
+    }
+    ///An altitude as briefly as it can be told apart, for datablocks and the plane list.
+    pub fn short(self, altitude: u16) -> String {
+        match self {
+            AltitudeUnit::Feet => format!("{altitude}"),
+            AltitudeUnit::FlightLevel => format!("{:03}", altitude * 10),
+            AltitudeUnit::Meters => format!("{:.1}", meters(altitude) / 1000.0),
+        }
+    }
+}
+
+fn meters(altitude: u16) -> f32 {
+    altitude as f32 * 1000.0 / FEET_PER_METER
+}
+
+static UNIT: AtomicU8 = AtomicU8::new(AltitudeUnit::Feet as u8);
+
+///Selects the unit altitudes are shown in for everything drawn afterward.
+pub fn set_altitude_unit(unit: AltitudeUnit) {
+    UNIT.store(unit as u8, Ordering::Relaxed);
+}
+
+pub fn altitude_unit() -> AltitudeUnit {
+    match UNIT.load(Ordering::Relaxed) {
+        1 => AltitudeUnit::FlightLevel,
+        2 => AltitudeUnit::Meters,
+        _ => AltitudeUnit::Feet,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_altitudes_in_each_unit() {
+        assert_eq!((AltitudeUnit::Feet.long(5), AltitudeUnit::Feet.short(12)), (format!("5000ft"), format!("12")));
+        assert_eq!((AltitudeUnit::FlightLevel.long(5), AltitudeUnit::FlightLevel.short(12)), (format!("FL050"), format!("120")));
+        assert_eq!((AltitudeUnit::Meters.long(5), AltitudeUnit::Meters.short(12)), (format!("1520m"), format!("3.7")));
+    }
+}
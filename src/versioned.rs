@@ -0,0 +1,71 @@
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+///Version of the format commands, planes, and locations are saved in. Raise it when a change would
+///stop files saved by older versions from loading, and convert their data in `upgrade`.
+pub const FORMAT_VERSION: u32 = 1;
+
+///Fails if data was saved in a format newer than this version understands.
+pub fn check_version(version: u32) -> Result<()> {
+    if version > FORMAT_VERSION {
+        bail!("This file was saved by a newer version of ATC, in format {version}; only up to {FORMAT_VERSION} can be read.");
+    }
+    Ok(())
+}
+
+///Writes data as JSON, along with the version of its format.
+pub fn to_json<T: Serialize>(data: &T) -> Result<String> {
+    Ok(serde_json::ser::to_string_pretty(&serde_json::json!({
+        "version": FORMAT_VERSION,
+        "data": data,
+    }))?)
+}
+
+///Reads JSON written by `to_json`, or written before versions were recorded.
+pub fn from_json<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let value: Value = serde_json::de::from_slice(bytes)?;
+    let (version, data) = match value {
+        Value::Object(mut object) if object.len() == 2 && object.contains_key("data") && object.get("version").is_some_and(Value::is_u64) => {
+            let version = object["version"].as_u64().unwrap_or_default();
+            let Ok(version) = u32::try_from(version) else { bail!("This file was saved in format {version}, which no version of ATC can read.") };
+            (version, object.remove("data").unwrap_or_default())
+        },
+        data => (0, data),
+    };
+    check_version(version)?;
+    Ok(serde_json::from_value(upgrade(version, data))?)
+}
+
+///Converts data saved in an older format to the current one.
+fn upgrade(version: u32, data: Value) -> Value {
+    match version {
+        //Version 1 only began recording the version, so nothing has changed yet.
+        0 | 1 => data,
+        _ => unreachable!("newer versions are rejected before upgrading"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn loads_current_and_unversioned_data() {
+        let data = HashMap::from([(1u16, format!("one"))]);
+        let saved = to_json(&data).expect("data to serialize");
+        assert_eq!(from_json::<HashMap<u16, String>>(saved.as_bytes()).expect("data to load"), data);
+        assert_eq!(from_json::<HashMap<u16, String>>(br#"{"1": "one"}"#).expect("unversioned data to load"), data);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let saved = format!(r#"{{"version": {}, "data": {{}}}}"#, FORMAT_VERSION + 1);
+        assert!(from_json::<HashMap<u16, String>>(saved.as_bytes()).is_err());
+        //Versions too big for a `u32` must not wrap around to an old one.
+        let saved = format!(r#"{{"version": {}, "data": {{}}}}"#, u32::MAX as u64 + 1);
+        assert!(from_json::<HashMap<u16, String>>(saved.as_bytes()).is_err());
+    }
+}